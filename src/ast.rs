@@ -1,7 +1,10 @@
+use std::fmt;
+
 /// A primitive terminal value
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Term {
     Integer(i32),
+    DoublePrecisionFloat(f64),
     Ident(String),
     Str(String),
     Bool(bool),
@@ -36,24 +39,70 @@ pub enum Op {
     MemberAccess,
 }
 
+/// A type annotation as written in source, like `int`, `dict[str, list[int]]`,
+/// `fn(int) -> str` or a host type like `Connection`. Annotations on fns are
+/// only read by `iris check`; they don't change how a program runs.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TypeExpr {
+    Named(String),
+    /// A type with params in square brackets, like list[int]
+    Generic(String, Vec<TypeExpr>),
+    Fn(Vec<TypeExpr>, Box<TypeExpr>),
+}
+
+impl fmt::Display for TypeExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let join = |types: &[TypeExpr]| types.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(", ");
+        match self {
+            TypeExpr::Named(name) => write!(f, "{}", name),
+            TypeExpr::Generic(name, params) => write!(f, "{}[{}]", name, join(params)),
+            TypeExpr::Fn(params, ret) => write!(f, "fn({}) -> {}", join(params), ret),
+        }
+    }
+}
+
+/// A function parameter, with its type annotation if it has one
+pub type Param = (String, Option<TypeExpr>);
+
 /// A node of the AST, built by the parser, and evaluated by the runner
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum AstNode {
     FnCall {
         name: String,
         args: Vec<AstNode>,
     },
+    /// A call written `x.name(args)`, with x first in args. Methods for the
+    /// args are looked for before functions.
+    MethodCall {
+        name: String,
+        args: Vec<AstNode>,
+    },
     FnDef {
+        /// Where the `fn` starts
+        pos: usize,
         name: String,
         args: Vec<String>,
+        /// Annotations for each of args
+        arg_types: Vec<Option<TypeExpr>>,
+        ret_type: Option<TypeExpr>,
         body: Vec<AstNode>,
     },
     MethodDef {
         name: String,
-        for_type: String,
+        /// The types of the args it's for: one for `for int`, or more for
+        /// a multimethod, like `for (int, str)`
+        for_types: Vec<TypeExpr>,
         args: Vec<String>,
+        arg_types: Vec<Option<TypeExpr>>,
+        ret_type: Option<TypeExpr>,
         body: Vec<AstNode>,
     },
+    /// A statement, along with the byte offsets it starts and ends at in the source
+    Located {
+        pos: usize,
+        end: usize,
+        stmt: Box<AstNode>,
+    },
     Term(Term),
     Infix(Box<AstNode>, Op, Box<AstNode>),
     Unary(Op, Box<AstNode>),
@@ -66,11 +115,41 @@ pub enum AstNode {
         body: Vec<AstNode>,
         else_if: Vec<AstNode>, // Should contain ElseIf nodes
         else_body: Vec<AstNode>,
+        /// Where the `else` starts, if there is one
+        else_pos: Option<usize>,
     },
     ElseIf {
+        /// Where the `elif` starts
+        pos: usize,
         cond_expr: Box<AstNode>,
         body: Vec<AstNode>,
     },
+    /// A string with expressions interpolated into it. The parts are string
+    /// terms and expressions, to be formatted and joined together.
+    Interpolation(Vec<AstNode>),
+}
+
+impl AstNode {
+    /// The nodes directly inside this one, in source order
+    pub fn children(&self) -> Vec<&AstNode> {
+        match self {
+            AstNode::FnCall { args, .. } | AstNode::MethodCall { args, .. } => args.iter().collect(),
+            AstNode::FnDef { body, .. } | AstNode::MethodDef { body, .. } => body.iter().collect(),
+            AstNode::Located { stmt, .. } => vec![stmt],
+            AstNode::Term(_) => vec![],
+            AstNode::Infix(l, _, r) => vec![l, r],
+            AstNode::Unary(_, r) => vec![r],
+            AstNode::VarDeclaration(_, e) | AstNode::Assignment(_, e) => vec![e],
+            AstNode::WhileStmt(cond, body) | AstNode::ForStmt(_, cond, body) => {
+                std::iter::once(&**cond).chain(body).collect()
+            },
+            AstNode::If { cond_expr, body, else_if, else_body, .. } => {
+                std::iter::once(&**cond_expr).chain(body).chain(else_if).chain(else_body).collect()
+            },
+            AstNode::ElseIf { cond_expr, body, .. } => std::iter::once(&**cond_expr).chain(body).collect(),
+            AstNode::Interpolation(parts) => parts.iter().collect(),
+        }
+    }
 }
 
 pub fn infix(l: AstNode, op: Op, r: AstNode) -> AstNode {
@@ -79,4 +158,18 @@ pub fn infix(l: AstNode, op: Op, r: AstNode) -> AstNode {
 
 pub fn unary(op: Op, r: AstNode) -> AstNode {
     AstNode::Unary(op, Box::new(r))
+}
+
+pub fn located(pos: usize, end: usize, stmt: AstNode) -> AstNode {
+    AstNode::Located { pos, end, stmt: Box::new(stmt) }
+}
+
+/// Split params into their names and their annotations
+pub fn split_params(params: Option<Vec<Param>>) -> (Vec<String>, Vec<Option<TypeExpr>>) {
+    params.unwrap_or_default().into_iter().unzip()
+}
+
+pub fn fn_def(pos: usize, name: String, params: Option<Vec<Param>>, ret_type: Option<TypeExpr>, body: Vec<AstNode>) -> AstNode {
+    let (args, arg_types) = split_params(params);
+    AstNode::FnDef { pos, name, args, arg_types, ret_type, body }
 }
\ No newline at end of file
@@ -0,0 +1,288 @@
+//! Type signatures of the builtin functions, written the way annotations are,
+//! plus a few extras: single letters are type variables, `iterable[a]` takes
+//! anything that can be looped over, `seq[a]` gives back a list or an
+//! Iterator to match the first arg, `?` marks an arg that can be left off,
+//! `...` takes any number of args, and `|` separates alternatives.
+
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use super::types::{Substitution, Type};
+
+/// How a function can be called
+pub struct Signature {
+    pub params: Vec<Type>,
+    /// How many of the params have to be given
+    pub required: usize,
+    /// The type of any extra args, for functions like print
+    pub rest: Option<Type>,
+    pub ret: Type,
+}
+
+impl Signature {
+    pub fn accepts(&self, n: usize) -> bool {
+        n >= self.required && (n <= self.params.len() || self.rest.is_some())
+    }
+
+    /// A description of how many args are accepted, like "1 to 3 args"
+    pub fn arity(&self) -> String {
+        let max = self.params.len();
+        let plural = |n| if n == 1 { "" } else { "s" };
+        match self.rest {
+            Some(_) => format!("at least {} arg{}", self.required, plural(self.required)),
+            None if self.required == max => format!("{} arg{}", max, plural(max)),
+            None => format!("{} to {} args", self.required, max),
+        }
+    }
+}
+
+const SIGNATURES: &[(&str, &str)] = &[
+    ("print", "fn(any...) -> Option[a]"),
+    ("len", "fn(any) -> int"),
+    ("repr", "fn(any) -> str"),
+    ("str", "fn(any) -> str"),
+    ("slice", "fn(a, int, int?) -> a"),
+    ("Some", "fn(a) -> Option[a]"),
+    ("unwrap", "fn(Option[a]) -> a"),
+    ("is_some", "fn(Option[a]) -> bool"),
+    ("is_none", "fn(Option[a]) -> bool"),
+    ("split", "fn(str, str?) -> list[str]"),
+    ("join", "fn(list[a], str) -> str"),
+    ("trim", "fn(str) -> str"),
+    ("upper", "fn(str) -> str"),
+    ("lower", "fn(str) -> str"),
+    ("replace", "fn(str, str, str) -> str"),
+    ("starts_with", "fn(str, str) -> bool"),
+    ("ends_with", "fn(str, str) -> bool"),
+    ("contains", "fn(str, str) -> bool"),
+    ("find", "fn(str, str) -> Option[int]"),
+    ("chars", "fn(str) -> list[str]"),
+    ("repeat", "fn(a) -> Iterator[a] | fn(str, int) -> str"),
+    ("parse_int", "fn(str) -> Option[int]"),
+    ("parse_float", "fn(str) -> Option[float]"),
+    ("regex", "fn(str) -> Regex"),
+    ("re_match", "fn(any, str) -> Option[any]"),
+    ("re_find_all", "fn(any, str) -> list[any]"),
+    ("re_replace", "fn(any, str, str) -> str"),
+    ("re_split", "fn(any, str) -> list[str]"),
+    ("read_file", "fn(str) -> str"),
+    ("write_file", "fn(str, str) -> Option[a]"),
+    ("read_lines", "fn(str) -> list[str]"),
+    ("stdin_lines", "fn() -> Iterator[str]"),
+    ("list_dir", "fn(str) -> list[str]"),
+    ("exists", "fn(str) -> bool"),
+    ("args", "fn() -> list[str]"),
+    ("env", "fn(str) -> Option[str]"),
+    ("exit", "fn(int) -> a"),
+    ("format", "fn(str, any...) -> str"),
+    ("json_parse", "fn(str) -> any"),
+    ("json_stringify", "fn(any, bool?) -> str"),
+    ("iter", "fn(iterable[a]) -> Iterator[a]"),
+    ("collect", "fn(iterable[a]) -> list[a]"),
+    ("next", "fn(Iterator[a]) -> Option[a]"),
+    ("range", "fn(int, int?, int?) -> Iterator[int]"),
+    ("count", "fn(int?, int?) -> Iterator[int]"),
+    ("take", "fn(iterable[a], int) -> Iterator[a]"),
+    ("skip", "fn(iterable[a], int) -> Iterator[a]"),
+    ("take_while", "fn(iterable[a], fn(a) -> bool) -> Iterator[a]"),
+    ("chain", "fn(iterable[a], iterable[a]) -> Iterator[a]"),
+    ("map", "fn(iterable[a], fn(a) -> b) -> seq[b]"),
+    ("filter", "fn(iterable[a], fn(a) -> bool) -> seq[a]"),
+    ("reduce", "fn(iterable[a], fn(a, a) -> a) -> a"),
+    ("fold", "fn(iterable[a], b, fn(b, a) -> b) -> b"),
+    ("zip", "fn(iterable[a], iterable[b]) -> seq[list[any]]"),
+    ("enumerate", "fn(iterable[a]) -> seq[list[any]]"),
+    ("any", "fn(iterable[a], fn(a) -> bool?) -> bool"),
+    ("all", "fn(iterable[a], fn(a) -> bool?) -> bool"),
+    ("sum", "fn(iterable[a]) -> a"),
+    ("min", "fn(iterable[a]) -> a"),
+    ("max", "fn(iterable[a]) -> a"),
+    ("sort", "fn(iterable[a]) -> list[a]"),
+    ("sort_by", "fn(iterable[a], fn(a) -> b) -> list[a]"),
+    ("reverse", "fn(a) -> a"),
+    ("flat_map", "fn(iterable[a], fn(a) -> any) -> list[any]"),
+    ("group_by", "fn(iterable[a], fn(a) -> k) -> dict[k, list[a]]"),
+    ("dict", "fn(iterable[any]?) -> dict[k, v]"),
+    ("get", "fn(dict[k, v], k) -> Option[v]"),
+    ("insert", "fn(dict[k, v], k, v) -> dict[k, v]"),
+    ("remove", "fn(a, any) -> a"),
+    ("has", "fn(any, any) -> bool"),
+    ("keys", "fn(dict[k, v]) -> list[k]"),
+    ("values", "fn(dict[k, v]) -> list[v]"),
+    ("items", "fn(dict[k, v]) -> list[list[any]]"),
+    ("set", "fn(iterable[a]?) -> set[a]"),
+    ("add", "fn(set[a], a) -> set[a]"),
+    ("union", "fn(set[a], set[a]) -> set[a]"),
+    ("intersection", "fn(set[a], set[a]) -> set[a]"),
+    ("difference", "fn(set[a], set[a]) -> set[a]"),
+    ("assert", "fn(bool, str?) -> Option[a]"),
+    ("assert_eq", "fn(a, a) -> Option[b]"),
+    ("assert_raises", "fn(fn() -> a, str?) -> Option[b]"),
+];
+
+/// Builtins that are methods rather than functions when running, with the
+/// types they're for. Functions and methods defined in Iris come first.
+const METHODS: &[(&str, &[&str])] = &[
+    ("get", &["dict"]),
+    ("insert", &["dict"]),
+    ("remove", &["dict", "set"]),
+    ("has", &["dict", "set"]),
+    ("keys", &["dict"]),
+    ("values", &["dict"]),
+    ("items", &["dict"]),
+    ("add", &["set"]),
+    ("union", &["set"]),
+    ("intersection", &["set"]),
+    ("difference", &["set"]),
+];
+
+/// The types a builtin is a method for, or None if it's a function
+pub fn method_types(name: &str) -> Option<&'static [&'static str]> {
+    METHODS.iter().find(|(n, _)| *n == name).map(|(_, types)| *types)
+}
+
+/// Whether there's a builtin function with this name. `list` is a builtin
+/// too, but is checked specially since its items needn't all be the same type.
+pub fn is_builtin(name: &str) -> bool {
+    name == "list" || SIGNATURES.iter().any(|(n, _)| *n == name)
+}
+
+/// A builtin's signatures as they're written above, for showing to people
+pub fn signature_source(name: &str) -> Option<&'static str> {
+    SIGNATURES.iter().find(|(n, _)| *n == name).map(|(_, source)| *source)
+}
+
+/// The signatures of a builtin, with fresh type variables
+pub fn signatures(name: &str, subst: &mut Substitution) -> Vec<Signature> {
+    let source = match SIGNATURES.iter().find(|(n, _)| *n == name) {
+        Some((_, source)) => source,
+        None => return vec![],
+    };
+    source.split(" | ")
+        .map(|sig| {
+            let mut parser = SigParser { chars: sig.chars().peekable(), vars: HashMap::new(), subst };
+            parser.signature()
+        })
+        .collect()
+}
+
+/// Reads the signatures above. They're part of the source, so any mistake
+/// in them is a bug, and panics.
+struct SigParser<'a, 's> {
+    chars: Peekable<Chars<'a>>,
+    vars: HashMap<String, Type>,
+    subst: &'s mut Substitution,
+}
+
+impl<'a, 's> SigParser<'a, 's> {
+    fn skip_spaces(&mut self) {
+        while self.chars.peek() == Some(&' ') {
+            self.chars.next();
+        }
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        self.skip_spaces();
+        let rest: String = self.chars.clone().take(s.len()).collect();
+        if rest == s {
+            self.chars.nth(s.len() - 1);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, s: &str) {
+        assert!(self.eat(s), "expected {:?} in builtin signature", s);
+    }
+
+    fn name(&mut self) -> String {
+        self.skip_spaces();
+        let mut name = String::new();
+        while let Some(&c) = self.chars.peek().filter(|c| c.is_alphanumeric() || **c == '_') {
+            name.push(c);
+            self.chars.next();
+        }
+        name
+    }
+
+    fn signature(&mut self) -> Signature {
+        self.expect("fn(");
+        let mut sig = Signature { params: vec![], required: 0, rest: None, ret: Type::Any };
+        while !self.eat(")") {
+            if !sig.params.is_empty() || sig.rest.is_some() {
+                self.expect(",");
+            }
+            let t = self.typ();
+            if self.eat("...") {
+                sig.rest = Some(t);
+                continue;
+            }
+            if !self.eat("?") {
+                sig.required += 1;
+            }
+            sig.params.push(t);
+        }
+        self.expect("->");
+        sig.ret = self.typ();
+        sig
+    }
+
+    /// Type parameters in square brackets
+    fn params(&mut self, n: usize) -> Vec<Type> {
+        self.expect("[");
+        let mut ret = vec![];
+        for i in 0..n {
+            if i > 0 {
+                self.expect(",");
+            }
+            ret.push(self.typ());
+        }
+        self.expect("]");
+        ret
+    }
+
+    fn param(&mut self) -> Box<Type> {
+        Box::new(self.params(1).remove(0))
+    }
+
+    fn typ(&mut self) -> Type {
+        let name = self.name();
+        match name.as_str() {
+            "int" => Type::Int,
+            "float" => Type::Float,
+            "str" => Type::Str,
+            "bool" => Type::Bool,
+            "any" => Type::Any,
+            "list" => Type::List(self.param()),
+            "set" => Type::Set(self.param()),
+            "Option" => Type::Option(self.param()),
+            "Iterator" => Type::Iterator(self.param()),
+            "iterable" => Type::Iterable(self.param()),
+            "seq" => Type::Seq(self.param()),
+            "dict" => {
+                let mut kv = self.params(2).into_iter().map(Box::new);
+                Type::Dict(kv.next().unwrap(), kv.next().unwrap())
+            },
+            "fn" => {
+                self.expect("(");
+                let mut params = vec![];
+                while !self.eat(")") {
+                    if !params.is_empty() {
+                        self.expect(",");
+                    }
+                    params.push(self.typ());
+                }
+                self.expect("->");
+                Type::Fn(params, Box::new(self.typ()))
+            },
+            _ if name.len() == 1 => {
+                let subst = &mut self.subst;
+                self.vars.entry(name).or_insert_with(|| subst.fresh()).clone()
+            },
+            _ if name.starts_with(char::is_uppercase) => Type::Native(name),
+            _ => panic!("unknown type {:?} in builtin signature", name),
+        }
+    }
+}
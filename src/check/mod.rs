@@ -0,0 +1,1006 @@
+//! `iris check`: finds type errors before a program runs. Types are inferred
+//! Hindley-Milner style, so nothing needs annotating, but annotations like
+//! `fn add(a: int, b: int) -> int` are checked against when they're there.
+//! Anything that can't be known statically, like the result of json_parse(),
+//! is given the type `any` and left alone. Checking never changes how a
+//! program runs.
+
+pub(crate) mod builtins;
+mod types;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::ast::{AstNode, Op, Term, TypeExpr};
+use crate::error::IrisError;
+use crate::parse;
+use types::{Scheme, Substitution, Type};
+
+/// A problem found in a program
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    /// Where the problem is, counting from 1
+    pub line: usize,
+    pub col: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.col, self.message)
+    }
+}
+
+/// Type check some source code, giving the problems found in source order.
+/// It's only an error if the source doesn't parse.
+pub fn check_str(source: &str) -> Result<Vec<Diagnostic>, IrisError> {
+    let ast = parse::parse_str(source)?;
+    let mut checker = Checker::new();
+    checker.block(&ast);
+
+    let mut errors = checker.errors;
+    errors.sort_by_key(|(pos, _)| *pos);
+    errors.dedup();
+    Ok(errors.into_iter()
+        .map(|(pos, message)| {
+            let (line, col) = parse::line_col(source, pos);
+            Diagnostic { line, col, message }
+        })
+        .collect())
+}
+
+struct Checker {
+    subst: Substitution,
+    /// The variables in scope, innermost last
+    scopes: Vec<HashMap<String, Scheme>>,
+    /// Methods, by name and the types they're for as written, like ["list[int]"]
+    methods: HashMap<(String, Vec<String>), Scheme>,
+    /// What the type variables in the annotations of the function being
+    /// checked stand for, like T in list[T]
+    type_vars: HashMap<String, Type>,
+    /// Where the statement being checked starts
+    pos: usize,
+    errors: Vec<(usize, String)>,
+}
+
+impl Checker {
+    fn new() -> Checker {
+        Checker {
+            subst: Substitution::default(),
+            scopes: vec![HashMap::new()],
+            methods: HashMap::new(),
+            type_vars: HashMap::new(),
+            pos: 0,
+            errors: vec![],
+        }
+    }
+
+    fn error(&mut self, msg: String) {
+        self.errors.push((self.pos, msg));
+    }
+
+    /// Check that a type is what it should be, reporting it if not. The
+    /// message is made from the type wanted and the type found.
+    fn expect<F>(&mut self, got: &Type, want: &Type, msg: F)
+    where F: FnOnce(&str, &str) -> String {
+        if self.subst.unify(want, got).is_err() {
+            let shown = self.subst.show(&[want, got]);
+            self.error(msg(&shown[0], &shown[1]));
+        }
+    }
+
+    /// The type of None, and of statements that don't give a value
+    fn none(&mut self) -> Type {
+        Type::option(self.subst.fresh())
+    }
+
+    fn declare(&mut self, name: &str, scheme: Scheme) {
+        self.scopes.last_mut().unwrap().insert(name.to_string(), scheme);
+    }
+
+    fn lookup(&mut self, name: &str) -> Option<Type> {
+        let scheme = self.scopes.iter().rev().find_map(|scope| scope.get(name))?.clone();
+        Some(self.subst.instantiate(&scheme))
+    }
+
+    /// The type variables the environment depends on, which can't be
+    /// generalized over
+    fn env_vars(&self) -> HashSet<usize> {
+        let mut vars = HashSet::new();
+        for scheme in self.scopes.iter().flat_map(|scope| scope.values()) {
+            self.subst.free_vars(scheme, &mut vars);
+        }
+        vars
+    }
+
+    fn annotation(&mut self, t: &TypeExpr) -> Type {
+        match t {
+            TypeExpr::Named(name) => match name.as_str() {
+                "int" => Type::Int,
+                "float" => Type::Float,
+                "str" => Type::Str,
+                "bool" => Type::Bool,
+                "any" | "function" => Type::Any,
+                "list" => Type::list(self.subst.fresh()),
+                "set" => Type::Set(Box::new(self.subst.fresh())),
+                "Option" => Type::option(self.subst.fresh()),
+                "Iterator" => Type::Iterator(Box::new(self.subst.fresh())),
+                "dict" => Type::Dict(Box::new(self.subst.fresh()), Box::new(self.subst.fresh())),
+                // A single capital letter is a type variable
+                _ if name.len() == 1 && name.starts_with(char::is_uppercase) => {
+                    let subst = &mut self.subst;
+                    self.type_vars.entry(name.to_string()).or_insert_with(|| subst.fresh()).clone()
+                },
+                _ if name.starts_with(char::is_uppercase) => Type::Native(name.to_string()),
+                _ => {
+                    self.error(format!("unknown type {}", name));
+                    Type::Any
+                },
+            },
+            TypeExpr::Generic(name, params) => {
+                let expected = match name.as_str() {
+                    "list" | "set" | "Option" | "Iterator" => 1,
+                    "dict" => 2,
+                    // Host types can't be looked inside
+                    _ if name.starts_with(char::is_uppercase) => return Type::Native(name.to_string()),
+                    _ => {
+                        self.error(format!("{} doesn't take type params", name));
+                        return Type::Any;
+                    },
+                };
+                if params.len() != expected {
+                    self.error(format!(
+                        "{} takes {} type param{}, got {}",
+                        name, expected, if expected == 1 { "" } else { "s" }, params.len(),
+                    ));
+                    return Type::Any;
+                }
+                let mut params = params.iter().map(|t| Box::new(self.annotation(t)));
+                let mut param = || params.next().unwrap();
+                match name.as_str() {
+                    "list" => Type::List(param()),
+                    "set" => Type::Set(param()),
+                    "Option" => Type::Option(param()),
+                    "Iterator" => Type::Iterator(param()),
+                    _ => Type::Dict(param(), param()),
+                }
+            },
+            TypeExpr::Fn(params, ret) => {
+                let params = params.iter().map(|t| self.annotation(t)).collect();
+                Type::Fn(params, Box::new(self.annotation(ret)))
+            },
+        }
+    }
+
+    /// Whether there's a method with this name for a type, going by the
+    /// name methods are declared for, like "list"
+    fn has_method(&self, name: &str, for_type: &str) -> bool {
+        self.methods.iter()
+            .filter(|((n, _), _)| n == name)
+            .any(|(_, scheme)| match &scheme.typ {
+                Type::Fn(params, _) => params.first()
+                    .and_then(|t| self.subst.resolve(t).method_type())
+                    .is_some_and(|t| t == for_type),
+                _ => false,
+            })
+    }
+
+    /// Check the statements of a body, giving the type of the last one
+    fn block(&mut self, body: &[AstNode]) -> Type {
+        // Functions and methods can be called before they're defined, from
+        // inside other functions, so declare them all up front. Calls made
+        // before the definition is run still fail though.
+        let groups = fn_groups(body);
+        let mut checked = 0;
+        self.declare_methods(body);
+        self.used_before_defined(body);
+
+        let mut ret = self.none();
+        for (idx, ast) in body.iter().enumerate() {
+            if let Some(group) = groups.iter().position(|group| group.contains(&idx)) {
+                // Check this function, and anything it calls, if we haven't yet
+                while checked <= group {
+                    let defs: Vec<&AstNode> = groups[checked].iter().map(|&i| &body[i]).collect();
+                    self.fn_group(&defs);
+                    checked += 1;
+                }
+                ret = self.none();
+                continue;
+            }
+            ret = self.stmt(ast);
+        }
+        ret
+    }
+
+    /// Report statements that use a function from the body before the body
+    /// defines it, either directly or through functions they call. Functions
+    /// are only defined when their definition is run.
+    fn used_before_defined(&mut self, body: &[AstNode]) {
+        let defs: HashMap<&str, usize> = (0..body.len())
+            .filter(|&i| matches!(unlocated(&body[i]), AstNode::FnDef { name, .. } if name != "anon"))
+            .map(|i| (fn_name(&body[i]), i))
+            .collect();
+        for (idx, ast) in body.iter().enumerate() {
+            if defs.values().any(|&i| i == idx) {
+                continue;
+            }
+            // Work through what the statement uses, then what those use
+            let mut used = HashSet::new();
+            immediate_references(ast, &mut used);
+            let mut todo: Vec<(String, Option<String>)> = used.iter().map(|name| (name.clone(), None)).collect();
+            let mut seen = HashSet::new();
+            while let Some((name, through)) = todo.pop() {
+                let def = match defs.get(name.as_str()) {
+                    Some(&def) if seen.insert(name.clone()) => def,
+                    _ => continue,
+                };
+                if def > idx {
+                    let saved_pos = self.pos;
+                    if let AstNode::Located { pos, .. } = ast {
+                        self.pos = *pos;
+                    }
+                    match through {
+                        Some(through) => self.error(format!(
+                            "{}() is used before it's defined, by calling {}()", name, through,
+                        )),
+                        None => self.error(format!("{}() is used before it's defined", name)),
+                    }
+                    self.pos = saved_pos;
+                    break;
+                }
+                let mut calls = HashSet::new();
+                if let AstNode::FnDef { body, .. } = unlocated(&body[def]) {
+                    body.iter().for_each(|ast| immediate_references(ast, &mut calls));
+                }
+                let through = through.unwrap_or_else(|| name.clone());
+                todo.extend(calls.into_iter().map(|name| (name, Some(through.clone()))));
+            }
+        }
+    }
+
+    /// Check a group of functions that call each other. They're generalized
+    /// together once all of them are checked.
+    fn fn_group(&mut self, defs: &[&AstNode]) {
+        let declared: Vec<Type> = defs.iter().map(|_| self.subst.fresh()).collect();
+        for (def, t) in defs.iter().zip(&declared) {
+            self.declare(fn_name(def), Scheme::mono(t.clone()));
+        }
+        for (def, t) in defs.iter().zip(&declared) {
+            let saved_pos = self.pos;
+            if let AstNode::Located { pos, .. } = def {
+                self.pos = *pos;
+            }
+            let actual = self.function(unlocated(def), &[]);
+            let name = fn_name(def);
+            self.expect(&actual, t, |used, defined| format!(
+                "{}() is used as {}, but is defined as {}", name, used, defined,
+            ));
+            self.pos = saved_pos;
+        }
+        for def in defs {
+            self.scopes.last_mut().unwrap().remove(fn_name(def));
+        }
+        let env_vars = self.env_vars();
+        for (def, t) in defs.iter().zip(&declared) {
+            let scheme = self.subst.generalize(t, &env_vars);
+            self.declare(fn_name(def), scheme);
+        }
+    }
+
+    /// Declare the methods defined in a body, so they can be called before
+    /// their definitions have been checked
+    fn declare_methods(&mut self, body: &[AstNode]) {
+        for ast in body {
+            if let AstNode::MethodDef { name, for_types, args, .. } = unlocated(ast) {
+                let saved = std::mem::take(&mut self.type_vars);
+                let mut params: Vec<Type> = args.iter().map(|_| self.subst.fresh()).collect();
+                for (param, t) in params.iter_mut().zip(for_types) {
+                    *param = self.annotation(t);
+                }
+                self.type_vars = saved;
+                let t = Type::Fn(params, Box::new(self.subst.fresh()));
+                self.methods.insert(method_key(name, for_types), Scheme::mono(t));
+            }
+        }
+    }
+
+    /// Infer the type of a function or method definition. Methods have the
+    /// type of their first arg given by what they're for.
+    fn function(&mut self, def: &AstNode, for_types: &[TypeExpr]) -> Type {
+        let (name, args, arg_types, ret_type, body) = match def {
+            AstNode::FnDef { name, args, arg_types, ret_type, body, .. }
+            | AstNode::MethodDef { name, args, arg_types, ret_type, body, .. } => {
+                (name, args, arg_types, ret_type, body)
+            },
+            _ => unreachable!("expected a function definition, got {:?}", def),
+        };
+        // Type variables are scoped to the definition they're used in
+        let saved = std::mem::take(&mut self.type_vars);
+        let for_types: Vec<Type> = for_types.iter().map(|t| self.annotation(t)).collect();
+        let params: Vec<Type> = arg_types.iter()
+            .map(|t| match t {
+                Some(t) => self.annotation(t),
+                None => self.subst.fresh(),
+            })
+            .collect();
+        if for_types.len() > params.len() {
+            self.error(format!(
+                "method {} is for {} types, but only takes {} arg{}",
+                name, for_types.len(), params.len(), if params.len() == 1 { "" } else { "s" },
+            ));
+        }
+        for (i, (param, for_type)) in params.iter().zip(&for_types).enumerate() {
+            self.expect(param, for_type, |want, got| format!(
+                "arg {} of method {} should be {}, but is annotated {}", i + 1, name, want, got,
+            ));
+        }
+
+        self.scopes.push(HashMap::new());
+        for (arg, t) in args.iter().zip(&params) {
+            self.declare(arg, Scheme::mono(t.clone()));
+        }
+        let mut ret = self.block(body);
+        self.scopes.pop();
+
+        if let Some(ret_type) = ret_type {
+            let want = self.annotation(ret_type);
+            let name = display_name(name);
+            self.expect(&ret, &want, |want, got| format!(
+                "{} is annotated to return {}, but returns {}", name, want, got,
+            ));
+            ret = want;
+        }
+        self.type_vars = saved;
+        Type::Fn(params, Box::new(ret))
+    }
+
+    fn stmt(&mut self, ast: &AstNode) -> Type {
+        match ast {
+            AstNode::Located { pos, stmt, .. } => {
+                let saved_pos = self.pos;
+                self.pos = *pos;
+                let t = self.stmt(stmt);
+                self.pos = saved_pos;
+                t
+            },
+            AstNode::VarDeclaration(Term::Ident(var), e) => {
+                let t = self.expr(e);
+                // Only functions are generalized, since a variable holding
+                // e.g. an empty list could have anything put in it later
+                let scheme = match **e {
+                    AstNode::FnDef { .. } => self.subst.generalize(&t, &self.env_vars()),
+                    _ => Scheme::mono(t),
+                };
+                self.declare(var, scheme);
+                self.none()
+            },
+            AstNode::Assignment(Term::Ident(var), e) => {
+                let t = self.expr(e);
+                if let Some(current) = self.lookup(var) {
+                    self.expect(&t, &current, |want, got| format!(
+                        "can't assign {} to {}, which holds {}", got, var, want,
+                    ));
+                }
+                self.none()
+            },
+            AstNode::VarDeclaration(..) | AstNode::Assignment(..) => self.none(),
+            AstNode::MethodDef { name, for_types, .. } => {
+                let key = method_key(name, for_types);
+                let actual = self.function(ast, for_types);
+                let declared = self.subst.instantiate(&self.methods[&key]);
+                self.expect(&actual, &declared, |used, defined| format!(
+                    "method {} is used as {}, but is defined as {}", name, used, defined,
+                ));
+                let scheme = self.subst.generalize(&declared, &self.env_vars());
+                self.methods.insert(key, scheme);
+                self.none()
+            },
+            AstNode::WhileStmt(cond, body) => {
+                let t = self.expr(cond);
+                self.expect(&t, &Type::Bool, |want, got| format!("while condition should be {}, got {}", want, got));
+                self.scopes.push(HashMap::new());
+                self.block(body);
+                self.scopes.pop();
+                self.none()
+            },
+            AstNode::ForStmt(var, iterable, body) => {
+                let t = self.expr(iterable);
+                let item = self.item_type(&t).unwrap_or_else(|| {
+                    let shown = self.subst.show(&[&t]);
+                    self.error(format!("can't loop over {}", shown[0]));
+                    Type::Any
+                });
+                self.scopes.push(HashMap::new());
+                self.declare(var, Scheme::mono(item));
+                self.block(body);
+                self.scopes.pop();
+                self.none()
+            },
+            _ => self.expr(ast),
+        }
+    }
+
+    fn expr(&mut self, ast: &AstNode) -> Type {
+        match ast {
+            AstNode::Term(Term::Integer(_)) => Type::Int,
+            AstNode::Term(Term::DoublePrecisionFloat(_)) => Type::Float,
+            AstNode::Term(Term::Str(_)) => Type::Str,
+            AstNode::Term(Term::Bool(_)) => Type::Bool,
+            AstNode::Term(Term::None) => self.none(),
+            AstNode::Term(Term::Ident(var)) => match self.lookup(var) {
+                Some(t) => t,
+                None => self.builtin_value(var),
+            },
+            AstNode::Interpolation(parts) => {
+                for part in parts {
+                    self.expr(part);
+                }
+                Type::Str
+            },
+            AstNode::FnCall { name, args } => self.call(name, args),
+            AstNode::MethodCall { name, args } => self.method_call(name, args),
+            AstNode::FnDef { .. } => self.function(ast, &[]),
+            AstNode::If { cond_expr, body, else_if, else_body, .. } => {
+                let mut branches = vec![(&**cond_expr, body)];
+                for ast in else_if {
+                    if let AstNode::ElseIf { cond_expr, body, .. } = ast {
+                        branches.push((cond_expr, body));
+                    }
+                }
+                let mut types = vec![];
+                for (cond, body) in branches {
+                    let t = self.expr(cond);
+                    self.expect(&t, &Type::Bool, |want, got| format!("if condition should be {}, got {}", want, got));
+                    types.push(self.block(body));
+                }
+                types.push(self.block(else_body));
+
+                // The branches don't have to agree, unless the value is used
+                // in a way that needs them to
+                let ret = self.subst.fresh();
+                if types.iter().all(|t| self.subst.try_unify(&ret, t)) {
+                    ret
+                } else {
+                    Type::Any
+                }
+            },
+            AstNode::Infix(l, op, r) => self.infix(l, op, r),
+            AstNode::Unary(op, r) => self.unary(op, r),
+            AstNode::Located { .. } | AstNode::VarDeclaration(..) | AstNode::Assignment(..)
+            | AstNode::MethodDef { .. } | AstNode::WhileStmt(..) | AstNode::ForStmt(..) => self.stmt(ast),
+            AstNode::ElseIf { .. } => Type::Any,
+        }
+    }
+
+    fn infix(&mut self, l: &AstNode, op: &Op, r: &AstNode) -> Type {
+        let lt = self.expr(l);
+        let rt = self.expr(r);
+        let symbol = symbol(op);
+        match op {
+            Op::And | Op::Or => {
+                for t in &[lt, rt] {
+                    self.expect(t, &Type::Bool, |want, got| format!("`{}` needs {}s, got {}", symbol, want, got));
+                }
+                Type::Bool
+            },
+            Op::Equal | Op::NotEqual => Type::Bool,
+            Op::LessThan | Op::LessThanEqual | Op::GreaterThan | Op::GreaterThanEqual => {
+                self.expect(&rt, &lt, |l, r| format!("can't compare {} with {}", l, r));
+                Type::Bool
+            },
+            Op::MemberAccess => self.index(&lt, &rt),
+            Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Mod | Op::Exp => self.arithmetic(op, &lt, &rt),
+            _ => {
+                for t in &[lt, rt] {
+                    self.expect(t, &Type::Int, |want, got| format!("`{}` needs {}s, got {}", symbol, want, got));
+                }
+                Type::Int
+            },
+        }
+    }
+
+    /// Arithmetic works on numbers, mixing ints and floats, and + also joins strings
+    fn arithmetic(&mut self, op: &Op, lt: &Type, rt: &Type) -> Type {
+        let is_number = |t: &Type| matches!(t, Type::Int | Type::Float);
+        let fits = |t: &Type| is_number(t) || (*op == Op::Add && *t == Type::Str);
+        match (self.subst.resolve(lt), self.subst.resolve(rt)) {
+            (Type::Any, _) | (_, Type::Any) => Type::Any,
+            (Type::Int, Type::Int) => Type::Int,
+            (l, r) if is_number(&l) && is_number(&r) => Type::Float,
+            (Type::Str, Type::Str) if *op == Op::Add => Type::Str,
+            (Type::Var(_), Type::Var(_)) => {
+                self.subst.try_unify(lt, rt);
+                lt.clone()
+            },
+            (Type::Var(_), t) | (t, Type::Var(_)) if fits(&t) => {
+                self.subst.try_unify(lt, rt);
+                t
+            },
+            _ => {
+                let shown = self.subst.show(&[lt, rt]);
+                self.error(format!("can't use `{}` on {} and {}", symbol(op), shown[0], shown[1]));
+                Type::Any
+            },
+        }
+    }
+
+    fn index(&mut self, lt: &Type, rt: &Type) -> Type {
+        match self.subst.resolve(lt) {
+            Type::List(item) => {
+                self.expect(rt, &Type::Int, |want, got| format!("list index should be {}, got {}", want, got));
+                *item
+            },
+            Type::Str => {
+                self.expect(rt, &Type::Int, |want, got| format!("string index should be {}, got {}", want, got));
+                Type::Str
+            },
+            Type::Dict(k, v) => {
+                self.expect(rt, &k, |want, got| format!("dict key should be {}, got {}", want, got));
+                *v
+            },
+            Type::Var(_) | Type::Any | Type::Native(_) => Type::Any,
+            t => {
+                let shown = self.subst.show(&[&t]);
+                self.error(format!("can't index into {}", shown[0]));
+                Type::Any
+            },
+        }
+    }
+
+    fn unary(&mut self, op: &Op, r: &AstNode) -> Type {
+        let t = self.expr(r);
+        match op {
+            Op::Not => {
+                self.expect(&t, &Type::Bool, |want, got| format!("`not` needs a {}, got {}", want, got));
+                Type::Bool
+            },
+            Op::BitwiseNot => {
+                self.expect(&t, &Type::Int, |want, got| format!("`!` needs an {}, got {}", want, got));
+                Type::Int
+            },
+            _ => match self.subst.resolve(&t) {
+                Type::Int | Type::Float | Type::Var(_) | Type::Any => t,
+                other => {
+                    let shown = self.subst.show(&[&other]);
+                    self.error(format!("can't negate {}", shown[0]));
+                    Type::Any
+                },
+            },
+        }
+    }
+
+    /// The type of the items we get looping over something, or None if it
+    /// can't be looped over
+    fn item_type(&self, t: &Type) -> Option<Type> {
+        match self.subst.resolve(t) {
+            Type::List(item) | Type::Set(item) | Type::Iterator(item) => Some(*item),
+            Type::Str => Some(Type::Str),
+            Type::Dict(k, _) => Some(*k),
+            Type::Var(_) | Type::Any | Type::Native(_) => Some(Type::Any),
+            // Anything with an Iter method can be looped over
+            other => other.method_type()
+                .filter(|name| self.has_method("Iter", name))
+                .map(|_| Type::Any),
+        }
+    }
+
+    fn call(&mut self, name: &str, args: &[AstNode]) -> Type {
+        // List and dict literals are calls too, but can't be shadowed
+        if name == "list" {
+            return self.list(args);
+        }
+        if let Some(entries) = dict_literal(name, args) {
+            return self.dict(entries);
+        }
+        let arg_types: Vec<Type> = args.iter().map(|arg| self.expr(arg)).collect();
+        // Anything in scope shadows builtins, as it does when running
+        if let Some(t) = self.lookup(name) {
+            return self.apply(name, &t, &arg_types);
+        }
+        if builtins::is_builtin(name) && builtins::method_types(name).is_none() {
+            return self.call_builtin(name, &arg_types);
+        }
+        if let Some(t) = self.call_method(name, &arg_types, None) {
+            return t;
+        }
+        match self.builtin_method_fits(name, &arg_types) {
+            true => self.call_builtin(name, &arg_types),
+            false => self.no_method(name, &arg_types),
+        }
+    }
+
+    /// Whether a builtin method could be the one called for some args
+    fn builtin_method_fits(&self, name: &str, arg_types: &[Type]) -> bool {
+        let types = match builtins::method_types(name) {
+            Some(types) => types,
+            None => return false,
+        };
+        match arg_types.first().map(|t| self.subst.resolve(t).method_type()) {
+            Some(Some(method_type)) => types.contains(&method_type.as_str()),
+            // We don't know the type yet, so it could be
+            Some(None) => true,
+            None => false,
+        }
+    }
+
+    /// A call written `x.name(args)`: methods are looked for first, then
+    /// functions. `Type.name(args)` calls the method for that type.
+    fn method_call(&mut self, name: &str, args: &[AstNode]) -> Type {
+        if let AstNode::Term(Term::Ident(receiver)) = &args[0] {
+            if self.lookup(receiver).is_none() && is_type_name(receiver) {
+                let arg_types: Vec<Type> = args[1..].iter().map(|arg| self.expr(arg)).collect();
+                return match self.call_method(name, &arg_types, Some(receiver)) {
+                    Some(t) => t,
+                    None => {
+                        self.error(format!("no method {} for {}", name, receiver));
+                        Type::Any
+                    },
+                };
+            }
+        }
+        let arg_types: Vec<Type> = args.iter().map(|arg| self.expr(arg)).collect();
+        if let Some(t) = self.call_method(name, &arg_types, None) {
+            return t;
+        }
+        if self.builtin_method_fits(name, &arg_types) {
+            return self.call_builtin(name, &arg_types);
+        }
+        if let Some(t) = self.lookup(name) {
+            return self.apply(name, &t, &arg_types);
+        }
+        match builtins::is_builtin(name) && builtins::method_types(name).is_none() {
+            true => self.call_builtin(name, &arg_types),
+            false => self.no_method(name, &arg_types),
+        }
+    }
+
+    /// Call a value of type t
+    fn apply(&mut self, name: &str, t: &Type, arg_types: &[Type]) -> Type {
+        match self.subst.resolve(t) {
+            Type::Fn(params, ret) => {
+                if params.len() != arg_types.len() {
+                    self.error(format!(
+                        "{}() takes {} arg{}, got {}",
+                        name, params.len(), if params.len() == 1 { "" } else { "s" }, arg_types.len(),
+                    ));
+                    return *ret;
+                }
+                for (i, (arg, param)) in arg_types.iter().zip(&params).enumerate() {
+                    self.expect(arg, param, |want, got| format!(
+                        "argument {} of {}() should be {}, got {}", i + 1, name, want, got,
+                    ));
+                }
+                *ret
+            },
+            Type::Var(_) => {
+                let ret = self.subst.fresh();
+                let called = Type::Fn(arg_types.to_vec(), Box::new(ret.clone()));
+                self.expect(&called, t, |want, got| format!("{} is {}, but is called as {}", name, want, got));
+                ret
+            },
+            Type::Any => Type::Any,
+            other => {
+                let shown = self.subst.show(&[&other]);
+                self.error(format!("{} is {}, not a function", name, shown[0]));
+                Type::Any
+            },
+        }
+    }
+
+    fn call_builtin(&mut self, name: &str, arg_types: &[Type]) -> Type {
+        let sigs = builtins::signatures(name, &mut self.subst);
+        let sig = match sigs.iter().find(|sig| sig.accepts(arg_types.len())) {
+            Some(sig) => sig,
+            None => {
+                let arities: Vec<String> = sigs.iter().map(|sig| sig.arity()).collect();
+                self.error(format!("{}() takes {}, got {}", name, arities.join(" or "), arg_types.len()));
+                return Type::Any;
+            },
+        };
+        for (i, arg) in arg_types.iter().enumerate() {
+            let param = sig.params.get(i).or(sig.rest.as_ref()).unwrap();
+            let what = || format!("argument {} of {}()", i + 1, name);
+            match param {
+                Type::Iterable(item) => match self.item_type(arg) {
+                    Some(actual) => self.expect(&actual, item, |want, got| format!(
+                        "{} should have {} items, got {}", what(), want, got,
+                    )),
+                    None => {
+                        let shown = self.subst.show(&[arg]);
+                        self.error(format!("{} should be something to loop over, got {}", what(), shown[0]));
+                    },
+                },
+                _ => self.expect(arg, param, |want, got| format!("{} should be {}, got {}", what(), want, got)),
+            }
+        }
+        match &sig.ret {
+            Type::Seq(item) => match arg_types.first().map(|t| self.subst.resolve(t)) {
+                Some(Type::Iterator(_)) => Type::Iterator(item.clone()),
+                _ => Type::List(item.clone()),
+            },
+            ret => ret.clone(),
+        }
+    }
+
+    /// A builtin used as a value, like `map(xs, str)`
+    fn builtin_value(&mut self, name: &str) -> Type {
+        let mut sigs = builtins::signatures(name, &mut self.subst);
+        if sigs.len() != 1 {
+            return Type::Any;
+        }
+        let sig = sigs.remove(0);
+        let simple = sig.rest.is_none() && sig.required == sig.params.len()
+            && !matches!(sig.ret, Type::Seq(_))
+            && !sig.params.iter().any(|t| matches!(t, Type::Iterable(_)));
+        match simple {
+            true => Type::Fn(sig.params, Box::new(sig.ret)),
+            false => Type::Any,
+        }
+    }
+
+    /// Methods are found by the types of the args they're for, optionally
+    /// only out of those whose first type is written as given. None if no
+    /// method could fit. If more than one could, which is called is only known
+    /// when running.
+    fn call_method(&mut self, name: &str, arg_types: &[Type], for_type: Option<&str>) -> Option<Type> {
+        let candidates: Vec<(usize, Scheme)> = self.methods.iter()
+            .filter(|((n, types), _)| n == name && for_type.is_none_or(|t| is_kind_of(&types[0], t)))
+            .map(|((_, types), scheme)| (types.len(), scheme.clone()))
+            .collect();
+        let self_type = self.subst.resolve(arg_types.first()?);
+        let method_type = match self_type.method_type() {
+            Some(method_type) => method_type,
+            None if candidates.is_empty() => return None,
+            None => return Some(Type::Any),
+        };
+        let mut fits = vec![];
+        for (n, scheme) in &candidates {
+            let t = self.subst.instantiate(scheme);
+            if let Type::Fn(params, _) = &t {
+                let mut trial = self.subst.clone();
+                let fit = arg_types.len() >= *n && params.iter().zip(arg_types).take(*n)
+                    .all(|(param, arg)| trial.unify(param, arg).is_ok());
+                if fit {
+                    fits.push(t);
+                }
+            }
+        }
+        match fits.len() {
+            1 => Some(self.apply(name, &fits[0], arg_types)),
+            // Host programs can add methods for their own types, and Iter is
+            // built in for anything that can be looped over
+            0 if method_type.starts_with(char::is_uppercase) || name == "Iter" => Some(Type::Any),
+            0 => None,
+            _ => Some(Type::Any),
+        }
+    }
+
+    /// Report a call to something that's neither a function nor a method
+    /// for its args. Calls to names we know nothing about could be to
+    /// functions the host adds, so only calls to methods are reported.
+    fn no_method(&mut self, name: &str, arg_types: &[Type]) -> Type {
+        let n = self.methods.keys()
+            .filter(|(n, _)| n == name)
+            .map(|(_, types)| types.len())
+            .max()
+            .or_else(|| builtins::method_types(name).map(|_| 1));
+        if let Some(n) = n {
+            let shown = self.subst.show(&arg_types.iter().take(n).collect::<Vec<_>>());
+            let shown = match shown.as_slice() {
+                [t] => t.to_string(),
+                _ => format!("({})", shown.join(", ")),
+            };
+            self.error(format!("no method {} for {}", name, shown));
+        }
+        Type::Any
+    }
+
+    /// List items needn't all be the same type. If they are, we know the
+    /// type of the list's items; if not, they could be anything.
+    fn list(&mut self, args: &[AstNode]) -> Type {
+        let item = self.subst.fresh();
+        let mut same = true;
+        for arg in args {
+            let t = self.expr(arg);
+            same = same && self.subst.try_unify(&item, &t);
+        }
+        Type::list(if same { item } else { Type::Any })
+    }
+
+    /// A dict literal, from its [key, value] entries
+    fn dict(&mut self, entries: Vec<(&AstNode, &AstNode)>) -> Type {
+        let (k, v) = (self.subst.fresh(), self.subst.fresh());
+        let (mut same_keys, mut same_values) = (true, true);
+        for (key, val) in entries {
+            let (kt, vt) = (self.expr(key), self.expr(val));
+            same_keys = same_keys && self.subst.try_unify(&k, &kt);
+            same_values = same_values && self.subst.try_unify(&v, &vt);
+        }
+        Type::Dict(
+            Box::new(if same_keys { k } else { Type::Any }),
+            Box::new(if same_values { v } else { Type::Any }),
+        )
+    }
+}
+
+/// Whether a name could be a type, as in `int.name(x)`
+fn is_type_name(name: &str) -> bool {
+    matches!(name, "int" | "float" | "str" | "bool" | "list" | "dict" | "set" | "function" | "Option")
+        || name.starts_with(char::is_uppercase)
+}
+
+/// Whether a type as written is the given type, or a more specific kind of
+/// it, like list[int] for list
+fn is_kind_of(written: &str, t: &str) -> bool {
+    written == t || written.strip_prefix(t).is_some_and(|rest| rest.starts_with('['))
+}
+
+/// How methods are looked up: by name, and the types they're for
+fn method_key(name: &str, for_types: &[TypeExpr]) -> (String, Vec<String>) {
+    (name.to_string(), for_types.iter().map(|t| t.to_string()).collect())
+}
+
+fn unlocated(ast: &AstNode) -> &AstNode {
+    match ast {
+        AstNode::Located { stmt, .. } => unlocated(stmt),
+        _ => ast,
+    }
+}
+
+fn fn_name(def: &AstNode) -> &str {
+    match unlocated(def) {
+        AstNode::FnDef { name, .. } => name,
+        _ => unreachable!("expected a function definition, got {:?}", def),
+    }
+}
+
+/// How to refer to a function in messages
+fn display_name(name: &str) -> String {
+    match name {
+        "anon" => "anonymous function".to_string(),
+        _ => format!("{}()", name),
+    }
+}
+
+fn symbol(op: &Op) -> &'static str {
+    match op {
+        Op::Add => "+",
+        Op::Sub => "-",
+        Op::Mul => "*",
+        Op::Div => "/",
+        Op::Mod => "%",
+        Op::Exp => "**",
+        Op::Shl => "<<",
+        Op::Shr => ">>",
+        Op::And => "and",
+        Op::Or => "or",
+        Op::BitwiseAnd => "&",
+        Op::BitwiseOr => "|",
+        Op::BitwiseXor => "^",
+        _ => "?",
+    }
+}
+
+/// The entries of a dict literal, which is parsed as dict(list(list(k, v), ...))
+fn dict_literal<'a>(name: &str, args: &'a [AstNode]) -> Option<Vec<(&'a AstNode, &'a AstNode)>> {
+    match (name, args) {
+        ("dict", [AstNode::FnCall { name, args: entries }]) if name == "list" => entries.iter()
+            .map(|entry| match entry {
+                AstNode::FnCall { name, args } if name == "list" && args.len() == 2 => Some((&args[0], &args[1])),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+/// The named functions defined directly in a body, as groups of indexes into
+/// it. Each group is a set of functions that call each other, and groups come
+/// after the ones they call, so that each can be generalized before it's used.
+fn fn_groups(body: &[AstNode]) -> Vec<Vec<usize>> {
+    let defs: Vec<usize> = (0..body.len())
+        .filter(|&i| matches!(unlocated(&body[i]), AstNode::FnDef { name, .. } if name != "anon"))
+        .collect();
+    let names: HashMap<&str, usize> = defs.iter().enumerate().map(|(n, &i)| (fn_name(&body[i]), n)).collect();
+    let calls: Vec<Vec<usize>> = defs.iter()
+        .map(|&i| {
+            let mut refs = HashSet::new();
+            references(&body[i], &mut refs);
+            let mut calls: Vec<usize> = refs.iter().filter_map(|name| names.get(name.as_str()).cloned()).collect();
+            calls.sort_unstable();
+            calls
+        })
+        .collect();
+    strongly_connected(&calls).into_iter()
+        .map(|group| group.into_iter().map(|n| defs[n]).collect())
+        .collect()
+}
+
+/// Every name referred to inside a node
+fn references(ast: &AstNode, out: &mut HashSet<String>) {
+    match ast {
+        AstNode::FnCall { name, .. } | AstNode::MethodCall { name, .. } | AstNode::Term(Term::Ident(name)) => {
+            out.insert(name.to_string());
+        },
+        _ => {},
+    }
+    for child in ast.children() {
+        references(child, out);
+    }
+}
+
+/// The names a node refers to as it runs, leaving out the bodies of any
+/// functions it defines, which only run when they're called
+fn immediate_references(ast: &AstNode, out: &mut HashSet<String>) {
+    match ast {
+        AstNode::FnDef { .. } | AstNode::MethodDef { .. } => return,
+        AstNode::FnCall { name, .. } | AstNode::Term(Term::Ident(name)) => {
+            out.insert(name.to_string());
+        },
+        _ => {},
+    }
+    for child in ast.children() {
+        immediate_references(child, out);
+    }
+}
+
+/// Tarjan's algorithm: the strongly connected components of a graph given as
+/// adjacency lists, with each component after the ones it has edges to
+fn strongly_connected(edges: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    struct State<'a> {
+        edges: &'a [Vec<usize>],
+        index: Vec<Option<usize>>,
+        low: Vec<usize>,
+        stack: Vec<usize>,
+        on_stack: Vec<bool>,
+        next: usize,
+        components: Vec<Vec<usize>>,
+    }
+
+    fn visit(s: &mut State, v: usize) {
+        s.index[v] = Some(s.next);
+        s.low[v] = s.next;
+        s.next += 1;
+        s.stack.push(v);
+        s.on_stack[v] = true;
+        for &w in &s.edges[v] {
+            match s.index[w] {
+                None => {
+                    visit(s, w);
+                    s.low[v] = s.low[v].min(s.low[w]);
+                },
+                Some(w_index) if s.on_stack[w] => s.low[v] = s.low[v].min(w_index),
+                Some(_) => {},
+            }
+        }
+        if Some(s.low[v]) == s.index[v] {
+            let mut component = vec![];
+            while let Some(w) = s.stack.pop() {
+                s.on_stack[w] = false;
+                component.push(w);
+                if w == v {
+                    break;
+                }
+            }
+            component.sort_unstable();
+            s.components.push(component);
+        }
+    }
+
+    let n = edges.len();
+    let mut s = State {
+        edges,
+        index: vec![None; n],
+        low: vec![0; n],
+        stack: vec![],
+        on_stack: vec![false; n],
+        next: 0,
+        components: vec![],
+    };
+    for v in 0..n {
+        if s.index[v].is_none() {
+            visit(&mut s, v);
+        }
+    }
+    s.components
+}
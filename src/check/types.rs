@@ -0,0 +1,358 @@
+//! Types as the checker sees them, and unification of them.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// The static type of an expression. `Any` is for things we can't know
+/// statically, like the result of json_parse(); it fits with every type.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Type {
+    Int,
+    Float,
+    Str,
+    Bool,
+    List(Box<Type>),
+    Dict(Box<Type>, Box<Type>),
+    Set(Box<Type>),
+    Option(Box<Type>),
+    Iterator(Box<Type>),
+    Fn(Vec<Type>, Box<Type>),
+    /// A host type, like Regex
+    Native(String),
+    /// A type we haven't worked out yet
+    Var(usize),
+    Any,
+    /// Anything that can be iterated over, with this item type. Only used in
+    /// the signatures of builtins.
+    Iterable(Box<Type>),
+    /// A list, or an Iterator if the first arg is one. Only used as the
+    /// return type of builtins like map that keep iterators lazy.
+    Seq(Box<Type>),
+}
+
+impl Type {
+    pub fn list(t: Type) -> Type {
+        Type::List(Box::new(t))
+    }
+
+    pub fn option(t: Type) -> Type {
+        Type::Option(Box::new(t))
+    }
+
+    /// The name methods are declared for with `for`, like "int" or "list"
+    pub fn method_type(&self) -> Option<String> {
+        let name = match self {
+            Type::Int => "int",
+            Type::Float => "float",
+            Type::Str => "str",
+            Type::Bool => "bool",
+            Type::List(_) => "list",
+            Type::Dict(..) => "dict",
+            Type::Set(_) => "set",
+            Type::Option(_) => "Option",
+            Type::Iterator(_) => "Iterator",
+            Type::Fn(..) => "function",
+            Type::Native(name) => name,
+            _ => return None,
+        };
+        Some(name.to_string())
+    }
+
+    /// The type variables in a type, in the order they appear
+    fn vars(&self, out: &mut Vec<usize>) {
+        match self {
+            Type::Var(v) if !out.contains(v) => out.push(*v),
+            Type::List(t) | Type::Set(t) | Type::Option(t) | Type::Iterator(t)
+            | Type::Iterable(t) | Type::Seq(t) => t.vars(out),
+            Type::Dict(k, v) => {
+                k.vars(out);
+                v.vars(out);
+            },
+            Type::Fn(params, ret) => {
+                params.iter().for_each(|t| t.vars(out));
+                ret.vars(out);
+            },
+            _ => {},
+        }
+    }
+
+    /// Replace type variables according to the given mapping
+    fn replace(&self, mapping: &HashMap<usize, Type>) -> Type {
+        let replace = |t: &Type| Box::new(t.replace(mapping));
+        match self {
+            Type::Var(v) => mapping.get(v).cloned().unwrap_or(Type::Var(*v)),
+            Type::List(t) => Type::List(replace(t)),
+            Type::Set(t) => Type::Set(replace(t)),
+            Type::Option(t) => Type::Option(replace(t)),
+            Type::Iterator(t) => Type::Iterator(replace(t)),
+            Type::Iterable(t) => Type::Iterable(replace(t)),
+            Type::Seq(t) => Type::Seq(replace(t)),
+            Type::Dict(k, v) => Type::Dict(replace(k), replace(v)),
+            Type::Fn(params, ret) => Type::Fn(params.iter().map(|t| t.replace(mapping)).collect(), replace(ret)),
+            t => t.clone(),
+        }
+    }
+}
+
+/// Type variables are shown as a, b, c...
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::Str => write!(f, "str"),
+            Type::Bool => write!(f, "bool"),
+            Type::List(t) => write!(f, "list[{}]", t),
+            Type::Dict(k, v) => write!(f, "dict[{}, {}]", k, v),
+            Type::Set(t) => write!(f, "set[{}]", t),
+            Type::Option(t) => write!(f, "Option[{}]", t),
+            Type::Iterator(t) => write!(f, "Iterator[{}]", t),
+            Type::Fn(params, ret) => {
+                let params: Vec<String> = params.iter().map(|t| t.to_string()).collect();
+                write!(f, "fn({}) -> {}", params.join(", "), ret)
+            },
+            Type::Native(name) => write!(f, "{}", name),
+            Type::Var(v) => {
+                let letter = (b'a' + (v % 26) as u8) as char;
+                match v / 26 {
+                    0 => write!(f, "{}", letter),
+                    n => write!(f, "{}{}", letter, n),
+                }
+            },
+            Type::Any => write!(f, "any"),
+            Type::Iterable(t) => write!(f, "iterable[{}]", t),
+            Type::Seq(t) => write!(f, "list[{}]", t),
+        }
+    }
+}
+
+/// A type that may be used at different types each time it's referred to,
+/// like the type of `fn id(x) x end`, which is fn(a) -> a for any a
+#[derive(Clone, Debug)]
+pub struct Scheme {
+    pub vars: Vec<usize>,
+    pub typ: Type,
+}
+
+impl Scheme {
+    /// A type that's the same wherever it's used
+    pub fn mono(typ: Type) -> Scheme {
+        Scheme { vars: vec![], typ }
+    }
+}
+
+/// What each type variable has been worked out to be so far
+#[derive(Clone, Default)]
+pub struct Substitution {
+    bindings: Vec<Option<Type>>,
+}
+
+impl Substitution {
+    pub fn fresh(&mut self) -> Type {
+        self.bindings.push(None);
+        Type::Var(self.bindings.len() - 1)
+    }
+
+    /// Follow a type's variables to what they're bound to, at the top level
+    fn shallow(&self, t: &Type) -> Type {
+        let mut t = t.clone();
+        while let Type::Var(v) = t {
+            match &self.bindings[v] {
+                Some(bound) => t = bound.clone(),
+                None => break,
+            }
+        }
+        t
+    }
+
+    /// Fully apply the substitution to a type
+    pub fn resolve(&self, t: &Type) -> Type {
+        let resolve = |t: &Type| Box::new(self.resolve(t));
+        match self.shallow(t) {
+            Type::List(t) => Type::List(resolve(&t)),
+            Type::Set(t) => Type::Set(resolve(&t)),
+            Type::Option(t) => Type::Option(resolve(&t)),
+            Type::Iterator(t) => Type::Iterator(resolve(&t)),
+            Type::Iterable(t) => Type::Iterable(resolve(&t)),
+            Type::Seq(t) => Type::Seq(resolve(&t)),
+            Type::Dict(k, v) => Type::Dict(resolve(&k), resolve(&v)),
+            Type::Fn(params, ret) => Type::Fn(params.iter().map(|t| self.resolve(t)).collect(), resolve(&ret)),
+            t => t,
+        }
+    }
+
+    /// Make two types the same, binding type variables as needed. Ints and
+    /// floats are interchangeable, as they are at runtime.
+    pub fn unify(&mut self, a: &Type, b: &Type) -> Result<(), ()> {
+        match (self.shallow(a), self.shallow(b)) {
+            (Type::Any, _) | (_, Type::Any) => Ok(()),
+            (Type::Var(v), Type::Var(w)) if v == w => Ok(()),
+            (Type::Var(v), t) | (t, Type::Var(v)) => {
+                // A type can't contain itself. Programs that need that can't
+                // be typed, so leave them be rather than report them.
+                let mut vars = vec![];
+                self.resolve(&t).vars(&mut vars);
+                if !vars.contains(&v) {
+                    self.bindings[v] = Some(t);
+                }
+                Ok(())
+            },
+            (Type::Int, Type::Int) | (Type::Float, Type::Float) | (Type::Str, Type::Str)
+            | (Type::Bool, Type::Bool) | (Type::Int, Type::Float) | (Type::Float, Type::Int) => Ok(()),
+            (Type::List(a), Type::List(b)) | (Type::Set(a), Type::Set(b))
+            | (Type::Option(a), Type::Option(b)) | (Type::Iterator(a), Type::Iterator(b)) => self.unify(&a, &b),
+            (Type::Dict(ak, av), Type::Dict(bk, bv)) => {
+                self.unify(&ak, &bk)?;
+                self.unify(&av, &bv)
+            },
+            (Type::Fn(a_params, a_ret), Type::Fn(b_params, b_ret)) if a_params.len() == b_params.len() => {
+                for (a, b) in a_params.iter().zip(&b_params) {
+                    self.unify(a, b)?;
+                }
+                self.unify(&a_ret, &b_ret)
+            },
+            (Type::Native(a), Type::Native(b)) if a == b => Ok(()),
+            _ => Err(()),
+        }
+    }
+
+    /// Unify, but leave everything as it was if the types don't fit
+    pub fn try_unify(&mut self, a: &Type, b: &Type) -> bool {
+        let saved = self.bindings.clone();
+        let ok = self.unify(a, b).is_ok();
+        if !ok {
+            self.bindings = saved;
+        }
+        ok
+    }
+
+    /// Give a scheme's variables fresh ones, for one use of it
+    pub fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        let mapping = scheme.vars.iter().map(|&v| (v, self.fresh())).collect();
+        scheme.typ.replace(&mapping)
+    }
+
+    /// Make a scheme out of a type, generalizing over the variables that
+    /// aren't fixed by the environment
+    pub fn generalize(&self, t: &Type, env_vars: &HashSet<usize>) -> Scheme {
+        let typ = self.resolve(t);
+        let mut vars = vec![];
+        typ.vars(&mut vars);
+        vars.retain(|v| !env_vars.contains(v));
+        Scheme { vars, typ }
+    }
+
+    /// The unbound variables of a scheme, not counting those it's generalized over
+    pub fn free_vars(&self, scheme: &Scheme, out: &mut HashSet<usize>) {
+        let mut vars = vec![];
+        self.resolve(&scheme.typ).vars(&mut vars);
+        out.extend(vars.into_iter().filter(|v| !scheme.vars.contains(v)));
+    }
+
+    /// Resolve types for showing to the user, with their variables renamed
+    /// a, b, c... in the order they appear
+    pub fn show(&self, types: &[&Type]) -> Vec<String> {
+        let resolved: Vec<Type> = types.iter().map(|t| self.resolve(t)).collect();
+        let mut vars = vec![];
+        resolved.iter().for_each(|t| t.vars(&mut vars));
+        let mapping = vars.into_iter().enumerate().map(|(i, v)| (v, Type::Var(i))).collect();
+        resolved.iter().map(|t| t.replace(&mapping).to_string()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn func(params: Vec<Type>, ret: Type) -> Type {
+        Type::Fn(params, Box::new(ret))
+    }
+
+    #[test]
+    fn unify_binds_variables() {
+        let mut subst = Substitution::default();
+        let (a, b) = (subst.fresh(), subst.fresh());
+        assert_eq!(subst.unify(&Type::list(a.clone()), &Type::list(Type::Int)), Ok(()));
+        assert_eq!(subst.unify(&b, &a), Ok(()));
+        assert_eq!(subst.resolve(&b), Type::Int);
+        assert_eq!(subst.resolve(&func(vec![a], Type::option(b))), func(vec![Type::Int], Type::option(Type::Int)));
+    }
+
+    #[test]
+    fn unify_rejects_types_that_dont_fit() {
+        let mut subst = Substitution::default();
+        assert_eq!(subst.unify(&Type::Str, &Type::Int), Err(()));
+        assert_eq!(subst.unify(&Type::list(Type::Str), &Type::Set(Box::new(Type::Str))), Err(()));
+        let dict = |k, v| Type::Dict(Box::new(k), Box::new(v));
+        assert_eq!(subst.unify(&dict(Type::Str, Type::Int), &dict(Type::Str, Type::Bool)), Err(()));
+        // Functions have to take the same number of args
+        assert_eq!(subst.unify(&func(vec![Type::Int], Type::Int), &func(vec![], Type::Int)), Err(()));
+        assert_eq!(subst.unify(&Type::Native("Regex".into()), &Type::Native("Db".into())), Err(()));
+    }
+
+    #[test]
+    fn ints_floats_and_any_fit_together() {
+        let mut subst = Substitution::default();
+        assert_eq!(subst.unify(&Type::Int, &Type::Float), Ok(()));
+        assert_eq!(subst.unify(&Type::list(Type::Any), &Type::list(Type::Str)), Ok(()));
+        assert_eq!(subst.unify(&Type::Any, &func(vec![], Type::Bool)), Ok(()));
+    }
+
+    #[test]
+    fn unify_leaves_types_that_contain_themselves_unbound() {
+        let mut subst = Substitution::default();
+        let a = subst.fresh();
+        assert_eq!(subst.unify(&a, &Type::list(a.clone())), Ok(()));
+        assert_eq!(subst.resolve(&a), a);
+    }
+
+    #[test]
+    fn try_unify_undoes_partial_bindings() {
+        let mut subst = Substitution::default();
+        let (a, b) = (subst.fresh(), subst.fresh());
+        // a gets bound to int before the second param fails
+        let (left, right) = (func(vec![a.clone(), Type::Str], b.clone()), func(vec![Type::Int, Type::Bool], b.clone()));
+        assert!(!subst.try_unify(&left, &right));
+        assert_eq!(subst.resolve(&a), a);
+        assert!(subst.try_unify(&a, &Type::Str));
+        assert_eq!(subst.resolve(&a), Type::Str);
+    }
+
+    #[test]
+    fn generalize_skips_variables_fixed_by_the_environment() {
+        let mut subst = Substitution::default();
+        let (a, b) = (subst.fresh(), subst.fresh());
+        let env: HashSet<usize> = vec![1].into_iter().collect();
+        let scheme = subst.generalize(&func(vec![a.clone(), b.clone()], a.clone()), &env);
+        assert_eq!(scheme.vars, vec![0]);
+        let mut free = HashSet::new();
+        subst.free_vars(&scheme, &mut free);
+        assert_eq!(free, env);
+
+        // Bound variables are resolved before generalizing
+        subst.unify(&b, &Type::Int).unwrap();
+        let scheme = subst.generalize(&func(vec![a, b], Type::Bool), &HashSet::new());
+        assert_eq!(scheme.vars, vec![0]);
+        assert_eq!(scheme.typ, func(vec![Type::Var(0), Type::Int], Type::Bool));
+    }
+
+    #[test]
+    fn instantiate_gives_fresh_variables_each_time() {
+        let mut subst = Substitution::default();
+        let a = subst.fresh();
+        let id = subst.generalize(&func(vec![a.clone()], a), &HashSet::new());
+        let first = subst.instantiate(&id);
+        let second = subst.instantiate(&id);
+        assert_ne!(first, second);
+        subst.unify(&first, &func(vec![Type::Int], Type::Int)).unwrap();
+        subst.unify(&second, &func(vec![Type::Str], Type::Str)).unwrap();
+        assert_eq!(subst.show(&[&first, &second]), vec!["fn(int) -> int", "fn(str) -> str"]);
+    }
+
+    #[test]
+    fn show_renames_variables_in_order() {
+        let mut subst = Substitution::default();
+        let (a, b) = (subst.fresh(), subst.fresh());
+        assert_eq!(subst.show(&[&func(vec![b.clone()], a.clone()), &a]), vec!["fn(a) -> b", "b"]);
+    }
+}
@@ -0,0 +1,325 @@
+//! `iris debug`: runs a program a statement at a time. It stops before the
+//! first statement, and then wherever it's told to: at breakpoints, or after
+//! stepping. While stopped, the variables in each scope around the current
+//! statement can be looked at, along with the calls that led there.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+
+use crate::error::IrisError;
+use crate::parse;
+use crate::run::{Hook, Interpreter, Scope, Value};
+
+const HELP: &str = "\
+s, step           run to the next statement, stepping into calls
+n, next           run to the next statement in this function
+o, out            run until this function returns
+c, continue       run to the next breakpoint
+b, break LINE     stop before running the given line
+d, delete LINE    remove the breakpoint on the given line
+p, print NAME     show a variable
+v, vars           show the variables in each scope, innermost first
+bt, backtrace     show the calls and loops in progress
+l, list           show the source around the current line
+q, quit           stop the program";
+
+/// Debug some source code, reading commands from `input` and writing what's
+/// going on to `output`. Gives the value of the last statement, like
+/// Interpreter::eval_str.
+pub fn debug<R, W>(interpreter: &mut Interpreter, source: &str, input: R, output: W) -> Result<Value, IrisError>
+where R: BufRead + 'static, W: Write + 'static {
+    let ast = parse::parse_str(source)?;
+    let debugger = Debugger {
+        source: source.to_string(),
+        input,
+        output,
+        breakpoints: HashSet::new(),
+        // Stop before the first statement, to give a chance to set breakpoints
+        mode: Mode::Step,
+    };
+    interpreter.set_hook(Rc::new(RefCell::new(debugger)));
+    interpreter.run(source, &ast)
+}
+
+/// Where to stop next
+enum Mode {
+    /// At the next statement
+    Step,
+    /// At the next statement with at most this many calls in progress
+    Next(usize),
+    /// At the next statement with fewer than this many calls in progress
+    Out(usize),
+    /// Only at breakpoints
+    Continue,
+}
+
+struct Debugger<R, W> {
+    source: String,
+    input: R,
+    output: W,
+    /// Lines to stop before, counting from 1
+    breakpoints: HashSet<usize>,
+    mode: Mode,
+}
+
+impl<R: BufRead, W: Write> Hook for Debugger<R, W> {
+    fn before_stmt(&mut self, position: usize, scope: &Rc<RefCell<Scope>>) -> Result<(), IrisError> {
+        let (line, _) = parse::line_col(&self.source, position);
+        let depth = scope.borrow().runtime().borrow().depth();
+        let stop = match self.mode {
+            Mode::Step => true,
+            Mode::Next(max) => depth <= max,
+            Mode::Out(below) => depth < below,
+            Mode::Continue => false,
+        };
+        if !stop && !self.breakpoints.contains(&line) {
+            return Ok(());
+        }
+        self.show_line(line, "=>")?;
+        self.prompt(line, depth, scope)
+    }
+}
+
+impl<R: BufRead, W: Write> Debugger<R, W> {
+    fn say(&mut self, text: &str) -> Result<(), IrisError> {
+        writeln!(self.output, "{}", text).map_err(io_error)
+    }
+
+    fn show_line(&mut self, line: usize, marker: &str) -> Result<(), IrisError> {
+        let text = self.source.lines().nth(line - 1).unwrap_or_default().to_string();
+        self.say(&format!("{:>2} {:>4} {}", marker, line, text))
+    }
+
+    /// Take commands until one of them carries on running the program
+    fn prompt(&mut self, line: usize, depth: usize, scope: &Rc<RefCell<Scope>>) -> Result<(), IrisError> {
+        loop {
+            write!(self.output, "(iris) ").and_then(|_| self.output.flush()).map_err(io_error)?;
+            let mut command = String::new();
+            if self.input.read_line(&mut command).map_err(io_error)? == 0 {
+                // Nobody's giving commands any more, so let the program finish
+                self.mode = Mode::Continue;
+                self.breakpoints.clear();
+                return Ok(());
+            }
+            let mut words = command.split_whitespace();
+            let (command, arg) = (words.next().unwrap_or_default(), words.next());
+            match command {
+                "s" | "step" => self.mode = Mode::Step,
+                "n" | "next" => self.mode = Mode::Next(depth),
+                "o" | "out" => self.mode = Mode::Out(depth),
+                "c" | "continue" => self.mode = Mode::Continue,
+                "q" | "quit" => return Err(IrisError::Exit(0)),
+                "b" | "break" | "d" | "delete" => {
+                    match arg.and_then(|arg| arg.parse::<usize>().ok()) {
+                        Some(line) if command.starts_with('b') => {
+                            self.breakpoints.insert(line);
+                            self.say(&format!("breakpoint on line {}", line))?;
+                        },
+                        Some(line) if self.breakpoints.remove(&line) => {
+                            self.say(&format!("removed the breakpoint on line {}", line))?;
+                        },
+                        Some(line) => self.say(&format!("there's no breakpoint on line {}", line))?,
+                        None => self.say(&format!("usage: {} LINE", command))?,
+                    }
+                    continue;
+                },
+                "p" | "print" => {
+                    let text = match arg {
+                        Some(name) => match scope.borrow().get_var(name) {
+                            Some(val) => format!("{} = {}", name, val.repr()),
+                            None => format!("no variable {} here", name),
+                        },
+                        None => "usage: print NAME".to_string(),
+                    };
+                    self.say(&text)?;
+                    continue;
+                },
+                "v" | "vars" => {
+                    self.vars(scope)?;
+                    continue;
+                },
+                "bt" | "backtrace" => {
+                    self.backtrace(scope)?;
+                    continue;
+                },
+                "l" | "list" => {
+                    let last = self.source.lines().count();
+                    for n in line.saturating_sub(5).max(1)..=(line + 5).min(last) {
+                        let marker = match (n == line, self.breakpoints.contains(&n)) {
+                            (true, _) => "=>",
+                            (false, true) => "*",
+                            _ => "",
+                        };
+                        self.show_line(n, marker)?;
+                    }
+                    continue;
+                },
+                "h" | "help" => {
+                    self.say(HELP)?;
+                    continue;
+                },
+                "" => continue,
+                _ => {
+                    self.say(&format!("unknown command {}, try help", command))?;
+                    continue;
+                },
+            }
+            return Ok(());
+        }
+    }
+
+    /// Show the variables in the scope, then in each scope it's nested in
+    fn vars(&mut self, scope: &Rc<RefCell<Scope>>) -> Result<(), IrisError> {
+        let mut next = Some(Rc::clone(scope));
+        while let Some(scope) = next {
+            let lines: Vec<String> = {
+                let s = scope.borrow();
+                std::iter::once(format!("in {}:", s.context()))
+                    .chain(s.vars().into_iter().map(|(name, val)| format!("    {} = {}", name, val.repr())))
+                    .collect()
+            };
+            for line in lines {
+                self.say(&line)?;
+            }
+            next = scope.borrow().parent();
+        }
+        Ok(())
+    }
+
+    /// Show the calls and loops in progress, innermost first, with where
+    /// each is up to
+    fn backtrace(&mut self, scope: &Rc<RefCell<Scope>>) -> Result<(), IrisError> {
+        let frames = {
+            let runtime = scope.borrow().runtime();
+            let runtime = runtime.borrow();
+            runtime.describe(runtime.frames(), runtime.position)
+        };
+        for frame in frames.iter().rev() {
+            self.say(&format!("  {}", frame))?;
+        }
+        Ok(())
+    }
+}
+
+fn io_error(e: std::io::Error) -> IrisError {
+    IrisError::runtime(format!("debugger input or output failed: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: &str = "fn double(x)\n  let y = x * 2\n  y\nend\nlet a = 1\nlet b = double(a)\nb + 1";
+
+    /// Output that can still be read after the debugger's been handed it
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Debug the program with the given commands, one per line, giving the
+    /// result and everything the debugger said
+    fn run(source: &str, commands: &'static str) -> (Result<Value, IrisError>, String) {
+        let output = Shared::default();
+        let res = debug(&mut Interpreter::new(), source, commands.as_bytes(), output.clone());
+        let text = String::from_utf8(output.0.borrow().clone()).unwrap();
+        (res, text)
+    }
+
+    /// The lines the debugger stopped at, in order
+    fn stops(output: &str) -> Vec<usize> {
+        output.lines()
+            .filter_map(|line| line.trim_start_matches("(iris) ").strip_prefix("=>"))
+            .map(|rest| rest.split_whitespace().next().unwrap().parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn stops_before_the_first_statement_and_runs_on_at_the_end_of_input() {
+        let (res, out) = run(PROGRAM, "");
+        assert_eq!(res, Ok(Value::Integer(3)));
+        assert_eq!(out, "=>    1 fn double(x)\n(iris) ");
+    }
+
+    #[test]
+    fn step_goes_into_calls_and_next_goes_over_them() {
+        let (res, out) = run(PROGRAM, "s\ns\ns\ns\ns\ns\nc\n");
+        assert_eq!(res, Ok(Value::Integer(3)));
+        assert_eq!(stops(&out), vec![1, 5, 6, 2, 3, 7]);
+        let (_, out) = run(PROGRAM, "n\nn\nn\nc\n");
+        assert_eq!(stops(&out), vec![1, 5, 6, 7]);
+    }
+
+    #[test]
+    fn out_runs_until_the_function_returns() {
+        let (res, out) = run(PROGRAM, "b 2\nc\no\nc\n");
+        assert_eq!(res, Ok(Value::Integer(3)));
+        assert_eq!(stops(&out), vec![1, 2, 7]);
+    }
+
+    #[test]
+    fn breakpoints_stop_every_time_until_deleted() {
+        let source = "let total = 0\nfor i in range(3) do\n  total = total + i\nend\ntotal";
+        let (res, out) = run(source, "b 3\nc\nc\nd 3\nc\n");
+        assert_eq!(res, Ok(Value::Integer(3)));
+        assert_eq!(stops(&out), vec![1, 3, 3]);
+        assert!(out.contains("breakpoint on line 3\n"), "{}", out);
+        assert!(out.contains("removed the breakpoint on line 3\n"), "{}", out);
+    }
+
+    #[test]
+    fn breakpoint_commands_check_their_line() {
+        let (_, out) = run(PROGRAM, "d 9\nb\ndelete x\n");
+        assert!(out.contains("there's no breakpoint on line 9\n"), "{}", out);
+        assert!(out.contains("usage: b LINE\n"), "{}", out);
+        assert!(out.contains("usage: delete LINE\n"), "{}", out);
+    }
+
+    #[test]
+    fn variables_can_be_printed_from_every_scope() {
+        let (_, out) = run(PROGRAM, "b 3\nc\np y\np a\np nope\nv\n");
+        assert!(out.contains("y = 2\n"), "{}", out);
+        assert!(out.contains("a = 1\n"), "{}", out);
+        assert!(out.contains("no variable nope here\n"), "{}", out);
+        let vars = "in fn double:\n    x = 1\n    y = 2\nin <top level>:\n    a = 1\n    double = function \"double\"\n";
+        assert!(out.contains(vars), "{}", out);
+    }
+
+    #[test]
+    fn backtrace_shows_the_calls_innermost_first() {
+        let (_, out) = run(PROGRAM, "b 3\nc\nbt\n");
+        assert!(out.contains("  line 3, in fn double\n  line 6, in <top level>\n"), "{}", out);
+    }
+
+    #[test]
+    fn list_shows_the_lines_around_with_breakpoints_marked() {
+        let (_, out) = run(PROGRAM, "b 7\nn\nl\n");
+        let listing = "      1 fn double(x)\n      2   let y = x * 2\n      3   y\n      4 end\n=>    5 let a = 1\n      6 let b = double(a)\n *    7 b + 1\n";
+        assert!(out.contains(listing), "{}", out);
+    }
+
+    #[test]
+    fn quit_stops_the_program() {
+        let (res, out) = run(PROGRAM, "n\nq\nc\n");
+        assert_eq!(res, Err(IrisError::Exit(0)));
+        assert_eq!(stops(&out), vec![1, 5]);
+    }
+
+    #[test]
+    fn unknown_commands_are_reported() {
+        let (res, out) = run(PROGRAM, "wat\n\nhelp\n");
+        assert_eq!(res, Ok(Value::Integer(3)));
+        assert!(out.contains("unknown command wat, try help\n"), "{}", out);
+        assert!(out.contains(HELP), "{}", out);
+    }
+}
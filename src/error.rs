@@ -0,0 +1,51 @@
+use std::fmt;
+
+/// Anything that can go wrong while parsing or running an Iris program.
+/// Errors are plain values so that the caller decides what to do with them,
+/// rather than the whole process going down.
+#[derive(Clone, Debug, PartialEq)]
+pub enum IrisError {
+    /// The source couldn't be read, tokenized or parsed
+    Parse(String),
+    /// A problem in the running program, e.g. a type mismatch or unknown variable
+    Runtime(String),
+    /// The call stack grew deeper than the configured limit
+    RecursionLimit(usize),
+    /// The program ran more steps than the configured budget
+    StepLimit(u64),
+    /// The program allocated more memory than the configured budget
+    MemoryLimit(usize),
+    /// The program nested values inside each other deeper than the configured limit
+    NestingLimit(usize),
+    /// The program called exit(code)
+    Exit(i32),
+}
+
+impl IrisError {
+    /// Shorthand for the most common kind of error
+    pub fn runtime<S: Into<String>>(msg: S) -> IrisError {
+        IrisError::Runtime(msg.into())
+    }
+}
+
+impl fmt::Display for IrisError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IrisError::Parse(msg) => write!(f, "parse error: {}", msg),
+            IrisError::Runtime(msg) => write!(f, "error: {}", msg),
+            IrisError::RecursionLimit(limit) => {
+                write!(f, "maximum recursion depth exceeded (limit: {})", limit)
+            },
+            IrisError::StepLimit(limit) => write!(f, "step budget exceeded (limit: {})", limit),
+            IrisError::MemoryLimit(limit) => {
+                write!(f, "memory budget exceeded (limit: {} bytes)", limit)
+            },
+            IrisError::NestingLimit(limit) => {
+                write!(f, "values nested too deeply (limit: {})", limit)
+            },
+            IrisError::Exit(code) => write!(f, "exited with code {}", code),
+        }
+    }
+}
+
+impl std::error::Error for IrisError {}
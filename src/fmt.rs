@@ -0,0 +1,526 @@
+//! `iris fmt`: prints programs out in one canonical style, with blocks
+//! indented four spaces, one space around infix operators and after commas,
+//! and only the brackets that are needed. Comments and single blank lines
+//! between statements are kept. The formatted source is parsed again and
+//! compared with the original, so formatting never changes what a program does.
+
+use crate::ast::{AstNode, Op, Term, TypeExpr};
+use crate::error::IrisError;
+use crate::parse;
+
+const INDENT: &str = "    ";
+
+/// Format some source code. It's an error if the source doesn't parse.
+pub fn format_str(source: &str) -> Result<String, IrisError> {
+    let ast = parse::parse_str(source)?;
+    let mut starts = vec![];
+    ast.iter().for_each(|stmt| statement_starts(stmt, &mut starts));
+    starts.sort_unstable();
+    let mut formatter = Formatter {
+        source,
+        comments: parse::comments(source),
+        next_comment: 0,
+        strings: parse::string_literals(source),
+        next_string: 0,
+        starts,
+        last_pos: 0,
+        indent: 0,
+        out: String::new(),
+    };
+    formatter.block(&ast, source.len());
+    let formatted = formatter.out;
+
+    let reparsed = parse::parse_str(&formatted).map_err(|e| {
+        IrisError::Parse(format!("formatting gave source that doesn't parse, which is a bug in iris fmt: {}", e))
+    })?;
+    let same = ast.len() == reparsed.len()
+        && ast.iter().zip(&reparsed).all(|(a, b)| without_spans(a) == without_spans(b));
+    if !same {
+        return Err(IrisError::Parse(
+            "formatting would change what the program does, which is a bug in iris fmt".to_string(),
+        ));
+    }
+    Ok(formatted)
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    /// Comments and where they start, in source order
+    comments: Vec<(usize, &'a str)>,
+    next_comment: usize,
+    /// String literals as they were written, in source order. The AST only
+    /// has their values, and they're written out in the same order.
+    strings: Vec<&'a str>,
+    next_string: usize,
+    /// Where every statement starts, in order
+    starts: Vec<usize>,
+    /// Where the last statement written starts
+    last_pos: usize,
+    indent: usize,
+    out: String,
+}
+
+impl<'a> Formatter<'a> {
+    fn write_indent(&mut self) {
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    /// Write the statements of a block, each on their own lines, and then
+    /// any comments left before `end`. Those after the block's last statement
+    /// but before the next statement are taken to be in the block.
+    fn block(&mut self, body: &[AstNode], end: usize) {
+        let mut prev_end = None;
+        for ast in body {
+            let (pos, stmt_end, stmt) = match ast {
+                AstNode::Located { pos, end, stmt } => (*pos, *end, &**stmt),
+                _ => unreachable!("statements in a block always have a location"),
+            };
+            self.comments_before(pos, &mut prev_end);
+            self.blank_line(prev_end, pos);
+            self.last_pos = pos;
+            self.write_indent();
+            self.stmt(stmt, stmt_end);
+            prev_end = Some(stmt_end);
+            // A comment after a statement on the same line stays there
+            if let Some(&(comment_pos, text)) = self.comments.get(self.next_comment) {
+                if comment_pos >= stmt_end && !self.source[stmt_end..comment_pos].contains('\n') {
+                    self.out.push(' ');
+                    self.out.push_str(text);
+                    self.next_comment += 1;
+                    prev_end = Some(comment_pos + text.len());
+                }
+            }
+            self.out.push('\n');
+        }
+        let next_start = self.starts.iter().find(|&&pos| pos > self.last_pos).copied().unwrap_or(end);
+        self.comments_before(end.min(next_start), &mut prev_end);
+    }
+
+    /// Write the comments that start before `limit` on their own lines
+    fn comments_before(&mut self, limit: usize, prev_end: &mut Option<usize>) {
+        while let Some(&(pos, text)) = self.comments.get(self.next_comment) {
+            if pos >= limit {
+                break;
+            }
+            self.blank_line(*prev_end, pos);
+            self.write_indent();
+            self.out.push_str(text);
+            self.out.push('\n');
+            self.next_comment += 1;
+            *prev_end = Some(pos + text.len());
+        }
+    }
+
+    /// Keep a blank line where there were one or more between two things
+    fn blank_line(&mut self, prev_end: Option<usize>, pos: usize) {
+        if let Some(prev_end) = prev_end.filter(|&prev_end| prev_end < pos) {
+            if self.source[prev_end..pos].matches('\n').count() > 1 {
+                self.out.push('\n');
+            }
+        }
+    }
+
+    /// Write a block indented one more than the current line, then `end`
+    fn indented_block(&mut self, body: &[AstNode], end: usize) {
+        self.out.push('\n');
+        self.indent += 1;
+        self.block(body, end);
+        self.indent -= 1;
+        self.write_indent();
+    }
+
+    /// Write a statement, without its indent or the newline after it. Any
+    /// blocks inside end by `end`.
+    fn stmt(&mut self, ast: &AstNode, end: usize) {
+        match ast {
+            AstNode::FnDef { name, args, arg_types, ret_type, body, .. } if name != "anon" => {
+                self.out.push_str("fn ");
+                self.out.push_str(name);
+                self.fn_rest(args, arg_types, ret_type, body, end);
+            },
+            AstNode::MethodDef { name, for_types, args, arg_types, ret_type, body } => {
+                self.out.push_str("method ");
+                self.out.push_str(name);
+                self.params(args, arg_types);
+                if let Some(t) = ret_type {
+                    self.out.push_str(&format!(" -> {}", t));
+                }
+                self.out.push_str(" for ");
+                match for_types.as_slice() {
+                    [t] => self.out.push_str(&t.to_string()),
+                    _ => self.out.push_str(&format!("({})", join(for_types))),
+                }
+                self.indented_block(body, end);
+                self.out.push_str("end");
+            },
+            AstNode::VarDeclaration(Term::Ident(var), e) => {
+                self.out.push_str(&format!("let {} = ", var));
+                self.expr(e, end);
+            },
+            AstNode::Assignment(Term::Ident(var), e) => {
+                self.out.push_str(&format!("{} = ", var));
+                self.expr(e, end);
+            },
+            AstNode::WhileStmt(cond, body) => {
+                self.out.push_str("while ");
+                self.expr(cond, end);
+                self.out.push_str(" do");
+                self.indented_block(body, end);
+                self.out.push_str("end");
+            },
+            AstNode::ForStmt(var, iterable, body) => {
+                self.out.push_str(&format!("for {} in ", var));
+                self.expr(iterable, end);
+                self.out.push_str(" do");
+                self.indented_block(body, end);
+                self.out.push_str("end");
+            },
+            _ => self.expr(ast, end),
+        }
+    }
+
+    /// Write the params, return type and body of a function
+    fn fn_rest(
+        &mut self,
+        args: &[String],
+        arg_types: &[Option<TypeExpr>],
+        ret_type: &Option<TypeExpr>,
+        body: &[AstNode],
+        end: usize,
+    ) {
+        self.params(args, arg_types);
+        if let Some(t) = ret_type {
+            self.out.push_str(&format!(" -> {}", t));
+        }
+        // The body of a one line fn is an expression without a location
+        match body {
+            [body] if !matches!(body, AstNode::Located { .. }) => {
+                self.out.push(' ');
+                self.expr(body, end);
+                self.out.push_str(" end");
+            },
+            _ => {
+                self.indented_block(body, end);
+                self.out.push_str("end");
+            },
+        }
+    }
+
+    fn params(&mut self, args: &[String], arg_types: &[Option<TypeExpr>]) {
+        let params: Vec<String> = args.iter().zip(arg_types)
+            .map(|(arg, t)| match t {
+                Some(t) => format!("{}: {}", arg, t),
+                None => arg.to_string(),
+            })
+            .collect();
+        self.out.push_str(&format!("({})", params.join(", ")));
+    }
+
+    /// Write an expression, in brackets if it binds less tightly than `max`
+    fn operand(&mut self, ast: &AstNode, max: u8, end: usize) {
+        if level(ast) > max {
+            self.out.push('(');
+            self.expr(ast, end);
+            self.out.push(')');
+        } else {
+            self.expr(ast, end);
+        }
+    }
+
+    fn expr(&mut self, ast: &AstNode, end: usize) {
+        match ast {
+            AstNode::Term(term) => self.term(term),
+            AstNode::Interpolation(_) => self.string(None),
+            AstNode::FnCall { name, args } => self.call(name, args, end),
+            AstNode::MethodCall { name, args } => {
+                self.operand(&args[0], 1, end);
+                self.out.push_str(&format!(".{}(", name));
+                self.list(&args[1..], end);
+                self.out.push(')');
+            },
+            AstNode::Infix(l, Op::MemberAccess, r) => {
+                self.operand(l, 1, end);
+                self.out.push('[');
+                self.operand(r, 0, end);
+                self.out.push(']');
+            },
+            AstNode::Infix(l, op, r) => {
+                let (left, right) = match op {
+                    // ** groups to the left, but binds less tightly than
+                    // unary operators on its right
+                    Op::Exp => (3, 2),
+                    // *, / and % don't group, so chains need brackets
+                    Op::Mul | Op::Div | Op::Mod => (3, 3),
+                    _ => (op_level(op), op_level(op) - 1),
+                };
+                self.operand(l, left, end);
+                self.out.push_str(&format!(" {} ", symbol(op)));
+                self.operand(r, right, end);
+            },
+            AstNode::Unary(op, r) => {
+                self.out.push_str(match op {
+                    Op::Not => "not ",
+                    Op::BitwiseNot => "!",
+                    _ => "-",
+                });
+                self.operand(r, 2, end);
+            },
+            AstNode::FnDef { args, arg_types, ret_type, body, .. } => {
+                self.out.push_str("fn");
+                self.fn_rest(args, arg_types, ret_type, body, end);
+            },
+            AstNode::If { cond_expr, body, else_if, else_body, else_pos } => {
+                self.if_expr(cond_expr, body, else_if, else_body, *else_pos, end)
+            },
+            // Statements can't be inside expressions
+            _ => self.stmt(ast, end),
+        }
+    }
+
+    fn if_expr(
+        &mut self,
+        cond: &AstNode,
+        body: &[AstNode],
+        else_if: &[AstNode],
+        else_body: &[AstNode],
+        else_pos: Option<usize>,
+        end: usize,
+    ) {
+        self.out.push_str("if ");
+        self.expr(cond, end);
+        self.out.push_str(" then");
+        // A one line if has expressions without locations in its bodies
+        let one_line = matches!(body, [b] if !matches!(b, AstNode::Located { .. }));
+        if one_line {
+            self.out.push(' ');
+            self.expr(&body[0], end);
+            if let [else_body] = else_body {
+                self.out.push_str(" else ");
+                self.expr(else_body, end);
+            }
+            self.out.push_str(" end");
+            return;
+        }
+        // Each branch ends where the next one's keyword starts, so comments
+        // opening a branch stay in it
+        let mut starts: Vec<usize> = else_if.iter()
+            .filter_map(|clause| match clause {
+                AstNode::ElseIf { pos, .. } => Some(*pos),
+                _ => None,
+            })
+            .chain(else_pos)
+            .collect();
+        starts.push(end);
+        self.indented_block(body, starts[0]);
+        for (clause, &branch_end) in else_if.iter().zip(&starts[1..]) {
+            if let AstNode::ElseIf { cond_expr, body, .. } = clause {
+                self.out.push_str("elif ");
+                self.expr(cond_expr, end);
+                self.out.push_str(" then");
+                self.indented_block(body, branch_end);
+            }
+        }
+        if !else_body.is_empty() {
+            self.out.push_str("else");
+            self.indented_block(else_body, end);
+        }
+        self.out.push_str("end");
+    }
+
+    /// Calls to list and dict are written as literals where they can be
+    fn call(&mut self, name: &str, args: &[AstNode], end: usize) {
+        match (name, args) {
+            ("list", [_, ..]) => {
+                self.out.push('[');
+                self.list(args, end);
+                self.out.push(']');
+            },
+            ("dict", []) => self.out.push_str("{}"),
+            ("dict", [AstNode::FnCall { name, args: entries }])
+                if name == "list" && !entries.is_empty() && entries.iter().all(is_pair) => {
+                self.out.push('{');
+                for (i, entry) in entries.iter().enumerate() {
+                    if let AstNode::FnCall { args: kv, .. } = entry {
+                        if i > 0 {
+                            self.out.push_str(", ");
+                        }
+                        self.expr(&kv[0], end);
+                        self.out.push_str(": ");
+                        self.expr(&kv[1], end);
+                    }
+                }
+                self.out.push('}');
+            },
+            _ => {
+                self.out.push_str(name);
+                self.out.push('(');
+                self.list(args, end);
+                self.out.push(')');
+            },
+        }
+    }
+
+    /// Write expressions separated by commas
+    fn list(&mut self, items: &[AstNode], end: usize) {
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.expr(item, end);
+        }
+    }
+
+    fn term(&mut self, term: &Term) {
+        match term {
+            Term::Integer(n) => self.out.push_str(&n.to_string()),
+            Term::DoublePrecisionFloat(f) => {
+                // Float literals always have a decimal point
+                let s = f.to_string();
+                self.out.push_str(&s);
+                if !s.contains('.') {
+                    self.out.push_str(".0");
+                }
+            },
+            Term::Ident(name) => self.out.push_str(name),
+            Term::Str(s) => self.string(Some(s)),
+            Term::Bool(b) => self.out.push_str(&b.to_string()),
+            Term::None => self.out.push_str("None"),
+        }
+    }
+
+    /// Write the next string literal as it was written. The value is there
+    /// in case the literals run out, which they shouldn't.
+    fn string(&mut self, value: Option<&str>) {
+        match self.strings.get(self.next_string) {
+            Some(literal) => self.out.push_str(literal),
+            None => self.out.push_str(&format!("{:?}", value.unwrap_or_default())),
+        }
+        self.next_string += 1;
+    }
+}
+
+/// How loosely an expression binds, from 0 for things that never need
+/// brackets, like literals and calls, up to 14 for ifs and fns
+fn level(ast: &AstNode) -> u8 {
+    match ast {
+        AstNode::MethodCall { .. } | AstNode::Infix(_, Op::MemberAccess, _) => 1,
+        AstNode::Unary(..) => 2,
+        AstNode::Infix(_, op, _) => op_level(op),
+        AstNode::FnDef { .. } | AstNode::If { .. } => 14,
+        _ => 0,
+    }
+}
+
+/// Follows the Term<n> rules in the grammar
+fn op_level(op: &Op) -> u8 {
+    match op {
+        Op::MemberAccess => 1,
+        Op::Not | Op::BitwiseNot | Op::Negation => 2,
+        Op::Exp => 3,
+        Op::Mul | Op::Div | Op::Mod => 4,
+        Op::Add | Op::Sub => 5,
+        Op::Shl | Op::Shr => 6,
+        Op::GreaterThan | Op::GreaterThanEqual | Op::LessThan | Op::LessThanEqual => 7,
+        Op::Equal | Op::NotEqual => 8,
+        Op::BitwiseAnd => 9,
+        Op::BitwiseXor => 10,
+        Op::BitwiseOr => 11,
+        Op::And => 12,
+        Op::Or => 13,
+    }
+}
+
+fn symbol(op: &Op) -> &'static str {
+    match op {
+        Op::Add => "+",
+        Op::Sub | Op::Negation => "-",
+        Op::Mul => "*",
+        Op::Div => "/",
+        Op::Mod => "%",
+        Op::Shl => "<<",
+        Op::Shr => ">>",
+        Op::And => "and",
+        Op::Or => "or",
+        Op::Exp => "**",
+        Op::Not => "not",
+        Op::GreaterThan => ">",
+        Op::GreaterThanEqual => ">=",
+        Op::LessThan => "<",
+        Op::LessThanEqual => "<=",
+        Op::Equal => "==",
+        Op::NotEqual => "!=",
+        Op::BitwiseAnd => "&",
+        Op::BitwiseOr => "|",
+        Op::BitwiseXor => "^",
+        Op::BitwiseNot => "!",
+        Op::MemberAccess => ".",
+    }
+}
+
+fn join(types: &[TypeExpr]) -> String {
+    types.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+/// Whether a node is a [k, v] entry of a dict literal
+fn is_pair(ast: &AstNode) -> bool {
+    matches!(ast, AstNode::FnCall { name, args } if name == "list" && args.len() == 2)
+}
+
+/// Where each statement in a node starts
+fn statement_starts(ast: &AstNode, out: &mut Vec<usize>) {
+    if let AstNode::Located { pos, .. } = ast {
+        out.push(*pos);
+    }
+    for child in ast.children() {
+        statement_starts(child, out);
+    }
+}
+
+/// A copy of a node without source locations, to compare what two programs do
+fn without_spans(ast: &AstNode) -> AstNode {
+    let all = |nodes: &[AstNode]| nodes.iter().map(without_spans).collect::<Vec<_>>();
+    let boxed = |node: &AstNode| Box::new(without_spans(node));
+    match ast {
+        AstNode::Located { stmt, .. } => AstNode::Located { pos: 0, end: 0, stmt: boxed(stmt) },
+        AstNode::FnCall { name, args } => AstNode::FnCall { name: name.clone(), args: all(args) },
+        AstNode::MethodCall { name, args } => AstNode::MethodCall { name: name.clone(), args: all(args) },
+        AstNode::FnDef { name, args, arg_types, ret_type, body, .. } => AstNode::FnDef {
+            pos: 0,
+            name: name.clone(),
+            args: args.clone(),
+            arg_types: arg_types.clone(),
+            ret_type: ret_type.clone(),
+            body: all(body),
+        },
+        AstNode::MethodDef { name, for_types, args, arg_types, ret_type, body } => AstNode::MethodDef {
+            name: name.clone(),
+            for_types: for_types.clone(),
+            args: args.clone(),
+            arg_types: arg_types.clone(),
+            ret_type: ret_type.clone(),
+            body: all(body),
+        },
+        AstNode::Term(_) => ast.clone(),
+        AstNode::Infix(l, op, r) => AstNode::Infix(boxed(l), op.clone(), boxed(r)),
+        AstNode::Unary(op, r) => AstNode::Unary(op.clone(), boxed(r)),
+        AstNode::VarDeclaration(var, e) => AstNode::VarDeclaration(var.clone(), boxed(e)),
+        AstNode::Assignment(var, e) => AstNode::Assignment(var.clone(), boxed(e)),
+        AstNode::WhileStmt(cond, body) => AstNode::WhileStmt(boxed(cond), all(body)),
+        AstNode::ForStmt(var, iterable, body) => AstNode::ForStmt(var.clone(), boxed(iterable), all(body)),
+        AstNode::If { cond_expr, body, else_if, else_body, else_pos } => AstNode::If {
+            cond_expr: boxed(cond_expr),
+            body: all(body),
+            else_if: all(else_if),
+            else_body: all(else_body),
+            else_pos: else_pos.map(|_| 0),
+        },
+        AstNode::ElseIf { cond_expr, body, .. } => AstNode::ElseIf {
+            pos: 0,
+            cond_expr: boxed(cond_expr),
+            body: all(body),
+        },
+        AstNode::Interpolation(parts) => AstNode::Interpolation(all(parts)),
+    }
+}
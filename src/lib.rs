@@ -0,0 +1,32 @@
+//! Iris: an accessible functional-ish programming language.
+//!
+//! This crate can be embedded to run Iris scripts from Rust, through the
+//! `Interpreter` type. Global variables and functions defined by a script stay
+//! around after it runs, so the host can read them and call into them.
+
+extern crate logos;
+#[macro_use] extern crate lalrpop_util;
+
+mod ast;
+mod check;
+mod debug;
+mod error;
+mod fmt;
+mod lint;
+mod lsp;
+mod parse;
+mod run;
+mod testing;
+
+pub use check::{check_str, Diagnostic};
+pub use debug::debug;
+pub use error::IrisError;
+pub use fmt::format_str;
+pub use lint::lint_str;
+pub use lsp::serve as serve_lsp;
+pub use run::{Dict, Interpreter, Set, Value};
+pub use run::capabilities::Capabilities;
+pub use run::limits::{CountingAllocator, Limits};
+pub use run::registry::{NativeFunction, NativeObject};
+pub use run::typing::{Function, PrimitiveType, TypeDefinition};
+pub use testing::{TestResult, run_tests};
@@ -0,0 +1,404 @@
+//! `iris lint`: warns about code that runs but probably isn't what was meant,
+//! like variables that are never read, or calls to functions that don't
+//! exist. Scopes follow the interpreter's: functions and loops get their own,
+//! while the bodies of ifs share the scope around them. Function bodies only
+//! run once they're called, so they're looked at after the rest of the block
+//! they're defined in, when everything they can see has been declared.
+//! Functions only exist once their definition has run though, so calls made
+//! before that, outside of function bodies, are reported.
+//! Names starting with _ are never reported as unused.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::{AstNode, Term};
+use crate::check::Diagnostic;
+use crate::error::IrisError;
+use crate::parse;
+use crate::run::registry::Registry;
+
+/// Lint some source code, giving the warnings in source order. It's only an
+/// error if the source doesn't parse.
+pub fn lint_str(source: &str) -> Result<Vec<Diagnostic>, IrisError> {
+    let ast = parse::parse_str(source)?;
+    let mut methods = HashSet::new();
+    ast.iter().for_each(|stmt| method_names(stmt, &mut methods));
+    let mut linter = Linter {
+        registry: Registry::new(),
+        methods,
+        scopes: vec![HashMap::new()],
+        deferred: vec![],
+        pos: 0,
+        fn_depth: 0,
+        warnings: vec![],
+    };
+    linter.block(&ast);
+    linter.pop_scope();
+
+    let mut warnings = linter.warnings;
+    warnings.sort_by_key(|(pos, _)| *pos);
+    warnings.dedup();
+    Ok(warnings.into_iter()
+        .map(|(pos, message)| {
+            let (line, col) = parse::line_col(source, pos);
+            Diagnostic { line, col, message }
+        })
+        .collect())
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Var,
+    Fn,
+    /// Function args and loop variables, which only get reported for shadowing
+    Arg,
+}
+
+struct Var {
+    kind: Kind,
+    /// Where the statement declaring it starts
+    pos: usize,
+    read: bool,
+    assigned: bool,
+    /// Whether a function's definition has been run yet
+    defined: bool,
+    /// How many function bodies in it was declared
+    fn_depth: usize,
+}
+
+struct Linter<'a> {
+    registry: Registry,
+    /// The names of all the methods in the program. Methods can be called
+    /// from anywhere, so these don't need scoping.
+    methods: HashSet<String>,
+    /// The variables in scope, innermost last
+    scopes: Vec<HashMap<String, Var>>,
+    /// Function bodies waiting for the end of the block they're defined in,
+    /// with where the statement defining them starts
+    deferred: Vec<(usize, &'a AstNode)>,
+    /// Where the statement being linted starts
+    pos: usize,
+    /// How many function bodies in the statement being linted is
+    fn_depth: usize,
+    warnings: Vec<(usize, String)>,
+}
+
+impl<'a> Linter<'a> {
+    fn warn(&mut self, message: String) {
+        self.warnings.push((self.pos, message));
+    }
+
+    /// Lint the statements of a block, then the functions defined in it
+    fn block(&mut self, body: &'a [AstNode]) {
+        let deferred_from = self.deferred.len();
+        // Named functions can be called before they're defined, from other
+        // function bodies
+        for ast in body {
+            if let AstNode::Located { pos, stmt, .. } = ast {
+                if let AstNode::FnDef { name, .. } = &**stmt {
+                    if name != "anon" {
+                        self.pos = *pos;
+                        self.declare(name, Kind::Fn);
+                    }
+                }
+            }
+        }
+        let mut reachable = true;
+        let mut warned = false;
+        for ast in body {
+            if let AstNode::Located { pos, .. } = ast {
+                self.pos = *pos;
+            }
+            // Only the first unreachable statement is worth a warning
+            if !reachable && !warned {
+                self.warn("unreachable code after exit()".to_string());
+                warned = true;
+            }
+            self.stmt(ast);
+            reachable = reachable && !self.exits(ast);
+        }
+        while self.deferred.len() > deferred_from {
+            let (pos, func) = self.deferred.remove(deferred_from);
+            self.pos = pos;
+            self.fn_body(func);
+        }
+    }
+
+    fn stmt(&mut self, ast: &'a AstNode) {
+        match ast {
+            AstNode::Located { stmt, .. } => self.stmt(stmt),
+            // Already declared at the start of the block
+            AstNode::FnDef { name, .. } if name != "anon" => {
+                if let Some(v) = self.lookup(name) {
+                    v.defined = true;
+                }
+                self.deferred.push((self.pos, ast));
+            },
+            AstNode::MethodDef { .. } => self.deferred.push((self.pos, ast)),
+            AstNode::VarDeclaration(Term::Ident(var), e) => {
+                self.expr(e);
+                self.declare(var, Kind::Var);
+            },
+            AstNode::Assignment(Term::Ident(var), e) => {
+                self.expr(e);
+                if let Some(v) = self.lookup(var) {
+                    v.assigned = true;
+                }
+            },
+            AstNode::WhileStmt(cond, body) => {
+                self.condition("while", cond);
+                self.expr(cond);
+                self.scopes.push(HashMap::new());
+                self.block(body);
+                self.pop_scope();
+            },
+            AstNode::ForStmt(var, iterable, body) => {
+                self.expr(iterable);
+                self.scopes.push(HashMap::new());
+                self.declare(var, Kind::Arg);
+                self.block(body);
+                self.pop_scope();
+            },
+            _ => self.expr(ast),
+        }
+    }
+
+    fn expr(&mut self, ast: &'a AstNode) {
+        match ast {
+            AstNode::Term(Term::Ident(name)) => {
+                if let Some(v) = self.lookup(name) {
+                    v.read = true;
+                }
+                if self.too_early(name) {
+                    self.warn(format!("function {} is used before it's defined", name));
+                }
+            },
+            AstNode::FnCall { name, args } => {
+                if !self.read_fn(name) && !self.methods.contains(name) && !self.registry.has_method(name) {
+                    self.warn(format!("call to undefined function {}", name));
+                }
+                args.iter().for_each(|arg| self.expr(arg));
+            },
+            AstNode::MethodCall { name, args } => {
+                if !self.methods.contains(name) && !self.registry.has_method(name) && !self.read_fn(name) {
+                    self.warn(format!("call to undefined method or function {}", name));
+                }
+                args.iter().for_each(|arg| self.expr(arg));
+            },
+            AstNode::FnDef { .. } => self.deferred.push((self.pos, ast)),
+            AstNode::If { cond_expr, body, else_if, else_body, .. } => {
+                self.condition("if", cond_expr);
+                self.expr(cond_expr);
+                self.block(body);
+                for clause in else_if {
+                    if let AstNode::ElseIf { pos, cond_expr, body } = clause {
+                        self.pos = *pos;
+                        self.condition("elif", cond_expr);
+                        self.expr(cond_expr);
+                        self.block(body);
+                    }
+                }
+                self.block(else_body);
+            },
+            _ => ast.children().into_iter().for_each(|child| self.stmt(child)),
+        }
+    }
+
+    /// Lint the body of a function or method, in a scope of its own
+    fn fn_body(&mut self, func: &'a AstNode) {
+        let (args, body) = match func {
+            AstNode::FnDef { args, body, .. } | AstNode::MethodDef { args, body, .. } => (args, body),
+            _ => unreachable!("only functions are deferred"),
+        };
+        self.fn_depth += 1;
+        self.scopes.push(HashMap::new());
+        for arg in args {
+            self.declare(arg, Kind::Arg);
+        }
+        self.block(body);
+        self.pop_scope();
+        self.fn_depth -= 1;
+    }
+
+    /// Warn about conditions that can never be true or false
+    fn condition(&mut self, keyword: &str, cond: &AstNode) {
+        let what = match cond {
+            AstNode::Term(Term::Integer(_)) => "an int",
+            AstNode::Term(Term::DoublePrecisionFloat(_)) => "a float",
+            AstNode::Term(Term::Str(_)) | AstNode::Interpolation(_) => "a str",
+            AstNode::Term(Term::None) => "None",
+            AstNode::FnCall { name, .. } if name == "list" => "a list",
+            AstNode::FnCall { name, .. } if name == "dict" => "a dict",
+            AstNode::FnDef { .. } => "a function",
+            _ => return,
+        };
+        self.warn(format!("{} condition is always {}, not a bool", keyword, what));
+    }
+
+    /// Whether a statement always stops the program. Iris has no return, so
+    /// exit() is the only thing that code can't get past.
+    fn exits(&mut self, ast: &AstNode) -> bool {
+        match ast {
+            AstNode::Located { stmt, .. } => self.exits(stmt),
+            AstNode::FnCall { name, .. } => name == "exit" && self.lookup(name).is_none(),
+            AstNode::If { body, else_if, else_body, .. } => {
+                let exits = |linter: &mut Self, body: &[AstNode]| body.iter().any(|stmt| linter.exits(stmt));
+                exits(self, body) && exits(self, else_body) && else_if.iter().all(|clause| match clause {
+                    AstNode::ElseIf { body, .. } => exits(self, body),
+                    _ => false,
+                })
+            },
+            _ => false,
+        }
+    }
+
+    /// Declare a name in the innermost scope
+    fn declare(&mut self, name: &str, kind: Kind) {
+        let (innermost, outer) = self.scopes.split_last_mut().expect("there's always a global scope");
+        if innermost.contains_key(name) {
+            return;
+        }
+        if outer.iter().any(|scope| scope.contains_key(name)) {
+            self.warnings.push((self.pos, format!("{} shadows a variable from an outer scope", name)));
+        }
+        let var = Var {
+            kind,
+            pos: self.pos,
+            read: false,
+            assigned: false,
+            defined: kind != Kind::Fn,
+            fn_depth: self.fn_depth,
+        };
+        self.scopes.last_mut().unwrap().insert(name.to_string(), var);
+    }
+
+    /// Find the innermost variable with a name
+    fn lookup(&mut self, name: &str) -> Option<&mut Var> {
+        self.scopes.iter_mut().rev().find_map(|scope| scope.get_mut(name))
+    }
+
+    /// Mark a function as used by a call, if it's defined
+    fn read_fn(&mut self, name: &str) -> bool {
+        if self.too_early(name) {
+            self.warn(format!("call to function {} before it's defined", name));
+        }
+        match self.lookup(name) {
+            Some(v) => {
+                v.read = true;
+                true
+            },
+            None => self.registry.get_fn(name).is_some(),
+        }
+    }
+
+    /// Whether a name is a function whose definition hasn't run yet. Inside
+    /// the body of a function defined later on, it may well have.
+    fn too_early(&mut self, name: &str) -> bool {
+        let fn_depth = self.fn_depth;
+        self.lookup(name).is_some_and(|v| !v.defined && v.fn_depth == fn_depth)
+    }
+
+    /// Leave a scope, warning about what in it was never used
+    fn pop_scope(&mut self) {
+        let scope = self.scopes.pop().expect("popped more scopes than were pushed");
+        for (name, var) in scope {
+            // Tests are called by iris test
+            let test = var.kind == Kind::Fn && name.starts_with("test_");
+            if var.read || var.kind == Kind::Arg || name.starts_with('_') || test {
+                continue;
+            }
+            let message = match (var.kind, var.assigned) {
+                (Kind::Fn, _) => format!("unused function {}", name),
+                (_, true) => format!("variable {} is assigned but never read", name),
+                _ => format!("unused variable {}", name),
+            };
+            self.warnings.push((var.pos, message));
+        }
+    }
+}
+
+/// Collect the names of all the methods defined in a program
+fn method_names(ast: &AstNode, names: &mut HashSet<String>) {
+    if let AstNode::MethodDef { name, .. } = ast {
+        names.insert(name.to_string());
+    }
+    for child in ast.children() {
+        method_names(child, names);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{check_str, Interpreter, Value};
+
+    /// The warnings for some source, as "line: message"
+    fn lint(source: &str) -> Vec<String> {
+        lint_str(source).unwrap().into_iter().map(|d| format!("{}: {}", d.line, d.message)).collect()
+    }
+
+    #[test]
+    fn unused_variables() {
+        assert_eq!(lint("let x = 1\nlet y = 2\nprint(y)"), vec!["1: unused variable x"]);
+        assert_eq!(lint("let x = 1\nx = 2"), vec!["1: variable x is assigned but never read"]);
+        assert_eq!(lint("let _x = 1"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn unused_functions() {
+        assert_eq!(lint("fn f()\n  1\nend\nfn test_f()\n  2\nend"), vec!["1: unused function f"]);
+        // Unused args are fine
+        assert_eq!(lint("fn f(x)\n  1\nend\nf(2)"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn shadowing() {
+        assert_eq!(
+            lint("let x = 1\nfn f()\n  let x = 2\n  x\nend\nprint(x, f())"),
+            vec!["3: x shadows a variable from an outer scope"],
+        );
+        assert_eq!(
+            lint("let x = 1\nfor x in [1] do\n  print(x)\nend\nprint(x)"),
+            vec!["2: x shadows a variable from an outer scope"],
+        );
+    }
+
+    #[test]
+    fn calls_to_undefined_functions() {
+        assert_eq!(lint("prnt(1)"), vec!["1: call to undefined function prnt"]);
+        assert_eq!(lint("[1].lenn()"), vec!["1: call to undefined method or function lenn"]);
+        // Builtins, methods and functions defined later on are all fine
+        let source = "fn f()\n  g([1].double())\nend\nmethod double(self) for list\n  len(self)\nend\n\
+                      fn g(x)\n  x\nend\nf()";
+        assert_eq!(lint(source), Vec::<String>::new());
+        assert_eq!(check_str(source), Ok(vec![]));
+        assert_eq!(Interpreter::new().eval_str(source), Ok(Value::Integer(1)));
+    }
+
+    #[test]
+    fn calls_before_the_definition() {
+        assert_eq!(lint("f()\nfn f()\n  1\nend"), vec!["1: call to function f before it's defined"]);
+    }
+
+    #[test]
+    fn unreachable_code() {
+        assert_eq!(lint("exit(0)\nprint(1)\nprint(2)"), vec!["2: unreachable code after exit()"]);
+        assert_eq!(
+            lint("if true then\n  exit(1)\nelse\n  exit(0)\nend\nprint(1)"),
+            vec!["6: unreachable code after exit()"],
+        );
+        // Only one branch exits
+        assert_eq!(lint("let c = true\nif c then\n  exit(1)\nend\nprint(1)"), Vec::<String>::new());
+        // Nor does a shadowed exit
+        assert_eq!(lint("fn exit(n)\n  n\nend\nexit(0)\nprint(1)"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn conditions_that_arent_bools() {
+        assert_eq!(lint("if 1 then\n  print(1)\nend"), vec!["1: if condition is always an int, not a bool"]);
+        assert_eq!(lint("while \"a\" do\n  print(1)\nend"), vec!["1: while condition is always a str, not a bool"]);
+        assert_eq!(
+            lint("let c = true\nif c then\n  print(1)\nelif [1] then\n  print(2)\nend"),
+            vec!["4: elif condition is always a list, not a bool"],
+        );
+    }
+}
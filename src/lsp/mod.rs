@@ -0,0 +1,497 @@
+//! `iris lsp`: a language server, speaking the Language Server Protocol over
+//! stdin and stdout so editors can show diagnostics, jump to definitions,
+//! show signatures on hover and complete names. Messages are JSON, which is
+//! read and written as Iris values with json_parse() and json_stringify().
+
+mod symbols;
+
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+use crate::check::{self, builtins::signature_source};
+use crate::error::IrisError;
+use crate::lint;
+use crate::parse;
+use crate::run::{Dict, Value};
+use crate::run::json::{json_parse, json_stringify};
+use crate::run::registry::Registry;
+use symbols::{Kind, Symbol};
+
+const KEYWORDS: &[&str] = &[
+    "fn", "method", "for", "in", "do", "while", "if", "then", "elif", "else", "end", "let",
+    "and", "or", "not", "true", "false", "None",
+];
+
+/// Serve LSP messages from `input` until the client says to exit. Responses
+/// and diagnostics are written to `output`.
+pub fn serve<R: BufRead, W: Write>(input: R, output: W) -> Result<(), IrisError> {
+    let mut server = Server { input, output, documents: HashMap::new(), registry: Registry::new() };
+    while let Some(message) = server.read_message()? {
+        let method = match get(&message, &["method"]) {
+            Some(Value::Str(method)) => method.clone(),
+            // Clients can send responses to requests, but we don't make any
+            _ => continue,
+        };
+        if method == "exit" {
+            break;
+        }
+        let params = get(&message, &["params"]).cloned().unwrap_or(Value::None);
+        let result = server.handle(&method, &params)?;
+        // Notifications have no id, and get no response
+        if let Some(id) = get(&message, &["id"]) {
+            let response = match result {
+                Some(result) => object(vec![("jsonrpc", string("2.0")), ("id", id.clone()), ("result", result)]),
+                None => object(vec![
+                    ("jsonrpc", string("2.0")),
+                    ("id", id.clone()),
+                    ("error", object(vec![
+                        ("code", Value::Integer(-32601)),
+                        ("message", string(&format!("unsupported method {}", method))),
+                    ])),
+                ]),
+            };
+            server.write_message(&response)?;
+        }
+    }
+    Ok(())
+}
+
+struct Server<R, W> {
+    input: R,
+    output: W,
+    /// The source of each open document, by URI
+    documents: HashMap<String, String>,
+    registry: Registry,
+}
+
+impl<R: BufRead, W: Write> Server<R, W> {
+    /// Read the next message, or None at the end of the input
+    fn read_message(&mut self) -> Result<Option<Value>, IrisError> {
+        let mut length = None;
+        loop {
+            let mut header = String::new();
+            if self.input.read_line(&mut header).map_err(io_error)? == 0 {
+                return Ok(None);
+            }
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length:") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+        let length = length.ok_or_else(|| IrisError::runtime("LSP message without a Content-Length"))?;
+        let mut body = vec![0; length];
+        self.input.read_exact(&mut body).map_err(io_error)?;
+        let body = String::from_utf8(body).map_err(|e| IrisError::runtime(e.to_string()))?;
+        json_parse(&[Value::Str(body)]).map(Some)
+    }
+
+    fn write_message(&mut self, message: &Value) -> Result<(), IrisError> {
+        let body = match json_stringify(std::slice::from_ref(message))? {
+            Value::Str(body) => body,
+            other => unreachable!("json_stringify gave {:?}", other),
+        };
+        write!(self.output, "Content-Length: {}\r\n\r\n{}", body.len(), body).map_err(io_error)?;
+        self.output.flush().map_err(io_error)
+    }
+
+    /// Handle a request or notification, giving the result to respond with,
+    /// or None if the method isn't supported
+    fn handle(&mut self, method: &str, params: &Value) -> Result<Option<Value>, IrisError> {
+        let uri = match get(params, &["textDocument", "uri"]) {
+            Some(Value::Str(uri)) => uri.clone(),
+            _ => String::new(),
+        };
+        let result = match method {
+            "initialize" => object(vec![
+                ("capabilities", object(vec![
+                    // Clients send the whole document whenever it changes
+                    ("textDocumentSync", Value::Integer(1)),
+                    ("definitionProvider", Value::Bool(true)),
+                    ("hoverProvider", Value::Bool(true)),
+                    ("completionProvider", object(vec![("triggerCharacters", Value::List(vec![string(".")]))])),
+                ])),
+                ("serverInfo", object(vec![("name", string("iris"))])),
+            ]),
+            "textDocument/didOpen" => {
+                if let Some(Value::Str(text)) = get(params, &["textDocument", "text"]) {
+                    self.documents.insert(uri.clone(), text.clone());
+                }
+                self.publish_diagnostics(&uri)?;
+                Value::None
+            },
+            "textDocument/didChange" => {
+                if let Some(Value::List(changes)) = get(params, &["contentChanges"]) {
+                    if let Some(Value::Str(text)) = changes.last().and_then(|change| get(change, &["text"])) {
+                        self.documents.insert(uri.clone(), text.clone());
+                    }
+                }
+                self.publish_diagnostics(&uri)?;
+                Value::None
+            },
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                self.publish_diagnostics(&uri)?;
+                Value::None
+            },
+            "textDocument/definition" => self.definition(&uri, params),
+            "textDocument/hover" => self.hover(&uri, params),
+            "textDocument/completion" => self.completion(&uri, params),
+            "initialized" | "shutdown" => Value::None,
+            _ => return Ok(None),
+        };
+        Ok(Some(result))
+    }
+
+    /// Send the parse error, or the type errors and lint warnings, in a document
+    fn publish_diagnostics(&mut self, uri: &str) -> Result<(), IrisError> {
+        let source = self.documents.get(uri).map_or("", String::as_str);
+        let mut diagnostics = vec![];
+        match parse::try_parse(source) {
+            Err((offset, msg)) => diagnostics.push(diagnostic(source, offset, 1, &format!("parse error: {}", msg))),
+            Ok(_) => {
+                for (severity, found) in [(1, check::check_str(source)), (2, lint::lint_str(source))] {
+                    for d in found.unwrap_or_default() {
+                        let offset = offset_of_line_col(source, d.line, d.col);
+                        diagnostics.push(diagnostic(source, offset, severity, &d.message));
+                    }
+                }
+            },
+        }
+        let notification = object(vec![
+            ("jsonrpc", string("2.0")),
+            ("method", string("textDocument/publishDiagnostics")),
+            ("params", object(vec![("uri", string(uri)), ("diagnostics", Value::List(diagnostics))])),
+        ]);
+        self.write_message(&notification)
+    }
+
+    /// The document, its symbols and the name at the position in the request
+    fn lookup<'s>(&'s self, uri: &str, params: &Value) -> Option<(&'s str, Vec<Symbol>, usize)> {
+        let source = self.documents.get(uri)?;
+        let line = get_int(params, &["position", "line"])?;
+        let character = get_int(params, &["position", "character"])?;
+        let offset = offset_of_position(source, line, character);
+        // Nothing can be found while the document doesn't parse
+        let ast = parse::try_parse(source).ok()?;
+        Some((source, symbols::index(source, &ast), offset))
+    }
+
+    fn definition(&self, uri: &str, params: &Value) -> Value {
+        let (source, symbols, offset) = match self.lookup(uri, params) {
+            Some(found) => found,
+            None => return Value::None,
+        };
+        let (_, name) = match symbols::word_at(source, offset) {
+            Some(word) => word,
+            None => return Value::None,
+        };
+        let locations = symbols::resolve(&symbols, name, offset).into_iter()
+            .map(|symbol| object(vec![
+                ("uri", string(uri)),
+                ("range", range(source, symbol.def, symbol.def + symbol.name.len())),
+            ]))
+            .collect();
+        Value::List(locations)
+    }
+
+    fn hover(&self, uri: &str, params: &Value) -> Value {
+        let (source, symbols, offset) = match self.lookup(uri, params) {
+            Some(found) => found,
+            None => return Value::None,
+        };
+        let (start, name) = match symbols::word_at(source, offset) {
+            Some(word) => word,
+            None => return Value::None,
+        };
+        let found = symbols::resolve(&symbols, name, offset);
+        let text = if !found.is_empty() {
+            found.iter().map(|symbol| symbol.detail.as_str()).collect::<Vec<_>>().join("\n")
+        } else if let Some(sig) = signature_source(name) {
+            format!("{}: {} (builtin)", name, sig)
+        } else if self.registry.names().any(|n| n == name) {
+            format!("{} (builtin)", name)
+        } else {
+            return Value::None;
+        };
+        object(vec![
+            ("contents", object(vec![("kind", string("markdown")), ("value", string(&format!("```iris\n{}\n```", text)))])),
+            ("range", range(source, start, start + name.len())),
+        ])
+    }
+
+    fn completion(&self, uri: &str, params: &Value) -> Value {
+        let (_, symbols, offset) = match self.lookup(uri, params) {
+            Some(found) => found,
+            None => return Value::List(vec![]),
+        };
+        // Kinds of completion item, as numbered by the protocol
+        let (function, method, variable, keyword) = (3, 2, 6, 14);
+        let mut items: Vec<(String, i32, String)> = symbols.iter()
+            .filter(|s| s.visible.0 <= offset && offset <= s.visible.1)
+            .map(|s| {
+                let kind = match s.kind {
+                    Kind::Function => function,
+                    Kind::Method => method,
+                    Kind::Variable => variable,
+                };
+                (s.name.clone(), kind, s.detail.clone())
+            })
+            .collect();
+        let mut builtins: Vec<&str> = self.registry.names().collect();
+        builtins.sort_unstable();
+        builtins.dedup();
+        for name in builtins {
+            let detail = signature_source(name).unwrap_or("builtin");
+            items.push((name.to_string(), function, detail.to_string()));
+        }
+        items.extend(KEYWORDS.iter().map(|k| (k.to_string(), keyword, "keyword".to_string())));
+        let mut seen = std::collections::HashSet::new();
+        items.retain(|(name, _, _)| seen.insert(name.clone()));
+        Value::List(items.into_iter()
+            .map(|(label, kind, detail)| object(vec![
+                ("label", string(&label)),
+                ("kind", Value::Integer(kind)),
+                ("detail", string(&detail)),
+            ]))
+            .collect())
+    }
+}
+
+fn io_error(e: std::io::Error) -> IrisError {
+    IrisError::runtime(format!("LSP connection failed: {}", e))
+}
+
+fn string(s: &str) -> Value {
+    Value::Str(s.to_string())
+}
+
+/// A JSON object with the given fields
+fn object(fields: Vec<(&str, Value)>) -> Value {
+    let mut dict = Dict::new();
+    for (key, val) in fields {
+        dict.insert(string(key), val).expect("strings can always be keys");
+    }
+    Value::Dict(dict)
+}
+
+/// Follow a path of keys into JSON objects
+fn get<'v>(val: &'v Value, path: &[&str]) -> Option<&'v Value> {
+    path.iter().try_fold(val, |val, key| match val {
+        Value::Dict(dict) => dict.get(&string(key)),
+        _ => None,
+    })
+}
+
+fn get_int(val: &Value, path: &[&str]) -> Option<usize> {
+    match get(val, path)? {
+        Value::Integer(n) if *n >= 0 => Some(*n as usize),
+        _ => None,
+    }
+}
+
+/// A diagnostic covering the rest of the line from an offset. Checking
+/// and linting only know which statement a problem is in.
+fn diagnostic(source: &str, offset: usize, severity: i32, message: &str) -> Value {
+    let len = source[offset..].find(['\r', '\n']).unwrap_or(source.len() - offset);
+    object(vec![
+        ("range", range(source, offset, offset + len)),
+        ("severity", Value::Integer(severity)),
+        ("source", string("iris")),
+        ("message", string(message)),
+    ])
+}
+
+fn range(source: &str, start: usize, end: usize) -> Value {
+    object(vec![("start", position(source, start)), ("end", position(source, end))])
+}
+
+/// LSP positions count lines from 0, and characters in UTF-16 code units
+fn position(source: &str, offset: usize) -> Value {
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let character: usize = before[line_start..].chars().map(char::len_utf16).sum();
+    object(vec![
+        ("line", Value::Integer(before.matches('\n').count() as i32)),
+        ("character", Value::Integer(character as i32)),
+    ])
+}
+
+fn offset_of_position(source: &str, line: usize, character: usize) -> usize {
+    let line_start = line_start(source, line);
+    let mut units = 0;
+    for (i, c) in source[line_start..].char_indices() {
+        if units >= character || c == '\n' {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    source.len()
+}
+
+/// The offset of a line and column as given by parse::line_col
+fn offset_of_line_col(source: &str, line: usize, col: usize) -> usize {
+    let line_start = line_start(source, line - 1);
+    source[line_start..].char_indices().nth(col - 1).map_or(source.len(), |(i, _)| line_start + i)
+}
+
+/// Where a line starts, counting from 0
+fn line_start(source: &str, line: usize) -> usize {
+    match line {
+        0 => 0,
+        _ => source.match_indices('\n').nth(line - 1).map_or(source.len(), |(i, _)| i + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URI: &str = "file:///test.iris";
+    const SOURCE: &str = "fn double(x: int) -> int\n  x * 2\nend\nlet total = double(2)\nprint(total + \"a\")\nprnt(total)\n";
+
+    /// Frame a JSON message the way clients send them
+    fn message(json: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{}", json.len(), json)
+    }
+
+    fn request(id: i32, method: &str, params: &str) -> String {
+        message(&format!(r#"{{"jsonrpc": "2.0", "id": {}, "method": "{}", "params": {}}}"#, id, method, params))
+    }
+
+    fn notification(method: &str, params: &str) -> String {
+        message(&format!(r#"{{"jsonrpc": "2.0", "method": "{}", "params": {}}}"#, method, params))
+    }
+
+    fn at(line: usize, character: usize) -> String {
+        format!(r#"{{"textDocument": {{"uri": "{}"}}, "position": {{"line": {}, "character": {}}}}}"#, URI, line, character)
+    }
+
+    /// Open SOURCE, send the requests, then shut down, giving everything the
+    /// server wrote
+    fn session(requests: &[String]) -> Vec<Value> {
+        let open = format!(
+            r#"{{"textDocument": {{"uri": "{}", "languageId": "iris", "version": 1, "text": {}}}}}"#,
+            URI,
+            match json_stringify(&[string(SOURCE)]).unwrap() {
+                Value::Str(text) => text,
+                other => panic!("json_stringify gave {:?}", other),
+            },
+        );
+        let mut input = request(0, "initialize", r#"{"capabilities": {}}"#);
+        input += &notification("initialized", "{}");
+        input += &notification("textDocument/didOpen", &open);
+        input.extend(requests.iter().map(String::as_str));
+        input += &request(99, "shutdown", "null");
+        input += &notification("exit", "null");
+        // Nothing after exit is read
+        input += &request(100, "shutdown", "null");
+
+        let mut output = vec![];
+        serve(input.as_bytes(), &mut output).unwrap();
+        let mut output = String::from_utf8(output).unwrap();
+        let mut messages = vec![];
+        while !output.is_empty() {
+            let (header, rest) = output.split_once("\r\n\r\n").expect("every message has a header");
+            let length: usize = header.strip_prefix("Content-Length: ").unwrap().parse().unwrap();
+            messages.push(json_parse(&[string(&rest[..length])]).unwrap());
+            output = rest[length..].to_string();
+        }
+        messages
+    }
+
+    /// The result of the response to the request with the given id
+    fn result(messages: &[Value], id: i32) -> Value {
+        messages.iter()
+            .find(|m| get(m, &["id"]) == Some(&Value::Integer(id)))
+            .and_then(|m| get(m, &["result"]))
+            .cloned()
+            .unwrap_or_else(|| panic!("no result for request {}", id))
+    }
+
+    fn str_at<'v>(val: &'v Value, path: &[&str]) -> &'v str {
+        match get(val, path) {
+            Some(Value::Str(s)) => s,
+            other => panic!("expected a string at {:?}, got {:?}", path, other),
+        }
+    }
+
+    #[test]
+    fn initialize_and_shutdown() {
+        let messages = session(&[]);
+        let capabilities = get(&result(&messages, 0), &["capabilities"]).cloned().unwrap();
+        assert_eq!(get(&capabilities, &["definitionProvider"]), Some(&Value::Bool(true)));
+        assert_eq!(get(&capabilities, &["hoverProvider"]), Some(&Value::Bool(true)));
+        assert_eq!(result(&messages, 99), Value::None);
+        // Nothing is answered after exit
+        assert!(messages.iter().all(|m| get(m, &["id"]) != Some(&Value::Integer(100))));
+    }
+
+    #[test]
+    fn opening_a_document_publishes_diagnostics() {
+        let messages = session(&[]);
+        let published = messages.iter()
+            .find(|m| get(m, &["method"]) == Some(&string("textDocument/publishDiagnostics")))
+            .expect("diagnostics are published");
+        assert_eq!(str_at(published, &["params", "uri"]), URI);
+        let diagnostics = match get(published, &["params", "diagnostics"]) {
+            Some(Value::List(diagnostics)) => diagnostics,
+            other => panic!("expected a list of diagnostics, got {:?}", other),
+        };
+        let found: Vec<(Option<&Value>, Option<&Value>)> = diagnostics.iter()
+            .map(|d| (get(d, &["severity"]), get(d, &["range", "start", "line"])))
+            .collect();
+        // The type error is an error, and the misspelt function a warning
+        assert!(found.contains(&(Some(&Value::Integer(1)), Some(&Value::Integer(4)))), "{:?}", diagnostics);
+        assert!(found.contains(&(Some(&Value::Integer(2)), Some(&Value::Integer(5)))), "{:?}", diagnostics);
+    }
+
+    #[test]
+    fn definition_finds_where_a_function_is_defined() {
+        let messages = session(&[request(1, "textDocument/definition", &at(3, 13))]);
+        let locations = match result(&messages, 1) {
+            Value::List(locations) => locations,
+            other => panic!("expected a list of locations, got {:?}", other),
+        };
+        assert_eq!(locations.len(), 1);
+        assert_eq!(str_at(&locations[0], &["uri"]), URI);
+        assert_eq!(get(&locations[0], &["range", "start", "line"]), Some(&Value::Integer(0)));
+        assert_eq!(get(&locations[0], &["range", "start", "character"]), Some(&Value::Integer(3)));
+    }
+
+    #[test]
+    fn hover_shows_signatures() {
+        let messages = session(&[
+            request(1, "textDocument/hover", &at(3, 13)),
+            request(2, "textDocument/hover", &at(4, 1)),
+            request(3, "textDocument/hover", &at(2, 1)),
+        ]);
+        assert!(str_at(&result(&messages, 1), &["contents", "value"]).contains("double"));
+        assert!(str_at(&result(&messages, 2), &["contents", "value"]).contains("print"));
+        assert!(str_at(&result(&messages, 2), &["contents", "value"]).contains("(builtin)"));
+        // Nothing to say about keywords
+        assert_eq!(result(&messages, 3), Value::None);
+    }
+
+    #[test]
+    fn completion_offers_names_in_scope_builtins_and_keywords() {
+        let messages = session(&[request(1, "textDocument/completion", &at(5, 6))]);
+        let items = match result(&messages, 1) {
+            Value::List(items) => items,
+            other => panic!("expected a list of completions, got {:?}", other),
+        };
+        let labels: Vec<&str> = items.iter().map(|item| str_at(item, &["label"])).collect();
+        for label in &["double", "total", "print", "while"] {
+            assert!(labels.contains(label), "{} missing from {:?}", label, labels);
+        }
+    }
+
+    #[test]
+    fn unsupported_requests_get_an_error() {
+        let messages = session(&[request(1, "textDocument/rename", &at(0, 0))]);
+        let response = messages.iter().find(|m| get(m, &["id"]) == Some(&Value::Integer(1))).unwrap();
+        assert_eq!(get(response, &["error", "code"]), Some(&Value::Integer(-32601)));
+    }
+}
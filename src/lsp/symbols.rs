@@ -0,0 +1,167 @@
+//! Where things are defined in a program, and where they can be seen from.
+//! The AST only knows where statements are, so the names themselves are
+//! found by searching the source of the statement that defines them.
+
+use crate::ast::{AstNode, Term, TypeExpr};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Function,
+    Method,
+    Variable,
+}
+
+#[derive(Clone, Debug)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: Kind,
+    /// Where the name is in the definition, as a byte offset
+    pub def: usize,
+    /// The part of the source where the name can be used
+    pub visible: (usize, usize),
+    /// What it looks like where it's defined, for hovers
+    pub detail: String,
+}
+
+/// All the definitions in a program
+pub fn index(source: &str, ast: &[AstNode]) -> Vec<Symbol> {
+    let mut indexer = Indexer { source, stmt: (0, source.len()), symbols: vec![] };
+    for node in ast {
+        indexer.node(node, (0, source.len()));
+    }
+    indexer.symbols
+}
+
+/// The symbols a name at `offset` could refer to, innermost first. All of
+/// them are given for methods, since a call could pick any of them.
+pub fn resolve<'s>(symbols: &'s [Symbol], name: &str, offset: usize) -> Vec<&'s Symbol> {
+    let mut found: Vec<&Symbol> = symbols.iter()
+        .filter(|s| s.name == name && s.visible.0 <= offset && offset <= s.visible.1)
+        .collect();
+    // Innermost scope first, and then the latest definition before the name
+    found.sort_by_key(|s| (std::cmp::Reverse(s.visible.0), s.def > offset, std::cmp::Reverse(s.def)));
+    match found.first() {
+        Some(s) if s.kind != Kind::Method => found.truncate(1),
+        _ => found.retain(|s| s.kind == Kind::Method),
+    }
+    found
+}
+
+/// The identifier at or just before a byte offset, and where it starts
+pub fn word_at(source: &str, offset: usize) -> Option<(usize, &str)> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let offset = offset.min(source.len());
+    let start = source[..offset].rfind(|c| !is_word(c)).map_or(0, |i| i + 1);
+    let end = source[offset..].find(|c| !is_word(c)).map_or(source.len(), |i| offset + i);
+    match &source[start..end] {
+        "" => None,
+        word => Some((start, word)),
+    }
+}
+
+struct Indexer<'a> {
+    source: &'a str,
+    /// The statement being indexed
+    stmt: (usize, usize),
+    symbols: Vec<Symbol>,
+}
+
+impl<'a> Indexer<'a> {
+    fn add(&mut self, name: &str, kind: Kind, from: usize, visible: (usize, usize), detail: String) {
+        let def = find_word(self.source, name, from).unwrap_or(from);
+        self.symbols.push(Symbol { name: name.to_string(), kind, def, visible, detail });
+    }
+
+    /// Index a node, whose definitions can be seen over `visible`
+    fn node(&mut self, ast: &AstNode, visible: (usize, usize)) {
+        match ast {
+            AstNode::Located { pos, end, stmt } => {
+                let outer = self.stmt;
+                self.stmt = (*pos, *end);
+                self.node(stmt, visible);
+                self.stmt = outer;
+            },
+            AstNode::FnDef { name, args, arg_types, ret_type, body, .. } => {
+                let sig = signature(args, arg_types, ret_type);
+                let start = self.stmt.0;
+                if name != "anon" {
+                    self.add(name, Kind::Function, start, visible, format!("fn {}{}", name, sig));
+                }
+                self.params(args, &sig);
+                let inner = self.stmt;
+                body.iter().for_each(|node| self.node(node, inner));
+            },
+            AstNode::MethodDef { name, for_types, args, arg_types, ret_type, body } => {
+                let sig = signature(args, arg_types, ret_type);
+                let for_types: Vec<String> = for_types.iter().map(TypeExpr::to_string).collect();
+                let for_types = match for_types.as_slice() {
+                    [t] => t.to_string(),
+                    _ => format!("({})", for_types.join(", ")),
+                };
+                // Methods can be called from anywhere
+                let everywhere = (0, self.source.len());
+                let detail = format!("method {}{} for {}", name, sig, for_types);
+                self.add(name, Kind::Method, self.stmt.0, everywhere, detail);
+                self.params(args, &sig);
+                let inner = self.stmt;
+                body.iter().for_each(|node| self.node(node, inner));
+            },
+            AstNode::VarDeclaration(Term::Ident(var), e) => {
+                let (start, end) = self.stmt;
+                let line = self.source[start..end].lines().next().unwrap_or_default();
+                self.add(var, Kind::Variable, start, visible, line.trim().to_string());
+                self.node(e, visible);
+            },
+            AstNode::ForStmt(var, iterable, body) => {
+                let inner = self.stmt;
+                self.add(var, Kind::Variable, inner.0, inner, format!("for {} in ...", var));
+                self.node(iterable, visible);
+                body.iter().for_each(|node| self.node(node, inner));
+            },
+            AstNode::WhileStmt(cond, body) => {
+                let inner = self.stmt;
+                self.node(cond, visible);
+                body.iter().for_each(|node| self.node(node, inner));
+            },
+            _ => ast.children().into_iter().for_each(|node| self.node(node, visible)),
+        }
+    }
+
+    /// Index the args of the function defined by the current statement
+    fn params(&mut self, args: &[String], sig: &str) {
+        let (start, end) = self.stmt;
+        let after_paren = self.source[start..end].find('(').map_or(start, |i| start + i);
+        for arg in args {
+            self.add(arg, Kind::Variable, after_paren, self.stmt, format!("{} (argument of fn{})", arg, sig));
+        }
+    }
+}
+
+/// How a function's params and return type are written
+fn signature(args: &[String], arg_types: &[Option<TypeExpr>], ret_type: &Option<TypeExpr>) -> String {
+    let params: Vec<String> = args.iter().zip(arg_types)
+        .map(|(arg, t)| match t {
+            Some(t) => format!("{}: {}", arg, t),
+            None => arg.to_string(),
+        })
+        .collect();
+    match ret_type {
+        Some(t) => format!("({}) -> {}", params.join(", "), t),
+        None => format!("({})", params.join(", ")),
+    }
+}
+
+/// Where a name first appears as a whole word from an offset
+fn find_word(source: &str, word: &str, from: usize) -> Option<usize> {
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    let mut at = from;
+    while let Some(i) = source[at..].find(word) {
+        let start = at + i;
+        let end = start + word.len();
+        if !is_word(source[..start].chars().next_back()) && !is_word(source[end..].chars().next()) {
+            return Some(start);
+        }
+        at = end;
+    }
+    None
+}
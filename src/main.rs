@@ -1,23 +1,229 @@
-extern crate logos;
-#[macro_use] extern crate lalrpop_util;
+use std::env;
+use std::fs;
+use std::process;
 
-mod ast;
-mod parse;
-mod run;
+use iris::{Capabilities, CountingAllocator, Diagnostic, debug, Interpreter, IrisError, Limits, check_str, format_str, lint_str, run_tests, serve_lsp};
 
-use std::env;
+// Count allocations so that --max-memory can be enforced
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const USAGE: &str = "usage: iris [--max-depth=N] [--max-steps=N] [--max-memory=BYTES] [--max-nesting=N] \
+[--allow-read=DIR] [--allow-write=DIR] [--allow-env] <file> [args...]
+       iris check <file>
+       iris lint <file>
+       iris lsp
+       iris test <file>...
+       iris debug <file> [args...]
+       iris fmt [--check] <file>...";
+
+/// Read the value of a --flag=value command line option
+fn flag_value<T: std::str::FromStr>(flag: &str, value: &str) -> T {
+    value.parse().unwrap_or_else(|_| {
+        eprintln!("invalid value for {}: {:?}", flag, value);
+        process::exit(2);
+    })
+}
+
+/// Read a source file named on the command line, or exit
+fn read_source(fname: &str) -> String {
+    fs::read_to_string(fname).unwrap_or_else(|e| {
+        eprintln!("cannot read iris file {}: {}", fname, e);
+        process::exit(2);
+    })
+}
+
+/// `iris check <file>` reports type errors, and `iris lint <file>` reports
+/// likely mistakes, without running anything
+fn diagnose(args: &[String], analyse: fn(&str) -> Result<Vec<Diagnostic>, IrisError>) {
+    let fname = match args {
+        [fname] => fname,
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        },
+    };
+    match analyse(&read_source(fname)) {
+        Ok(diagnostics) if diagnostics.is_empty() => {},
+        Ok(diagnostics) => {
+            for diagnostic in &diagnostics {
+                eprintln!("{}:{}", fname, diagnostic);
+            }
+            process::exit(1);
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        },
+    }
+}
+
+/// `iris fmt <file>...` rewrites files in the canonical style. With --check,
+/// it only reports the files that aren't formatted.
+fn fmt(args: &[String]) {
+    let check = args.iter().any(|arg| arg == "--check");
+    let fnames: Vec<&String> = args.iter().filter(|arg| *arg != "--check").collect();
+    if fnames.is_empty() {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    }
+    let mut failed = false;
+    for fname in fnames {
+        let source = read_source(fname);
+        let formatted = match format_str(&source) {
+            Ok(formatted) => formatted,
+            Err(e) => {
+                eprintln!("{}: {}", fname, e);
+                failed = true;
+                continue;
+            },
+        };
+        if formatted == source {
+            continue;
+        }
+        if check {
+            eprintln!("{} is not formatted", fname);
+            failed = true;
+        } else if let Err(e) = fs::write(fname, formatted) {
+            eprintln!("cannot write iris file {}: {}", fname, e);
+            failed = true;
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+/// `iris lsp` is a language server for editors, talking over stdin and stdout
+fn lsp() {
+    let stdin = std::io::stdin();
+    let stdout = std::io::stdout();
+    if let Err(e) = serve_lsp(stdin.lock(), stdout.lock()) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+/// `iris test <file>...` runs every test_ function in the files, and fails
+/// if any of them do
+fn test(fnames: &[String]) {
+    if fnames.is_empty() {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    }
+    let (mut passed, mut failed) = (0, 0);
+    for fname in fnames {
+        let results = match run_tests(&read_source(fname)) {
+            Ok(results) => results,
+            Err(e) => {
+                eprintln!("{}: {}", fname, e);
+                failed += 1;
+                continue;
+            },
+        };
+        for result in results {
+            let location = format!("{}:{}:{}", fname, result.line, result.col);
+            match result.error {
+                None => {
+                    println!("PASS {} ({})", result.name, location);
+                    passed += 1;
+                },
+                Some(e) => {
+                    println!("FAIL {} ({}): {}", result.name, location, e);
+                    failed += 1;
+                },
+            }
+        }
+    }
+    println!("{} passed, {} failed", passed, failed);
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+/// `iris debug <file> [args...]` runs a script under the debugger, which
+/// takes commands from stdin
+fn debug_file(args: &[String]) {
+    let (fname, script_args) = match args.split_first() {
+        Some(split) => split,
+        None => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        },
+    };
+    let source = read_source(fname);
+    let mut interpreter = Interpreter::new();
+    interpreter.set_args(script_args.to_vec());
+    let stdin = std::io::stdin();
+    match debug(&mut interpreter, &source, stdin.lock(), std::io::stdout()) {
+        Ok(_) => {},
+        Err(IrisError::Exit(code)) => process::exit(code),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        },
+    }
+}
 
 fn main() {
-    // Get filename from cli
-    let args: Vec<_> = env::args().collect();
-    if args.len() < 2 {
-        panic!("No input files given");
+    let cli_args: Vec<String> = env::args().skip(1).collect();
+    match cli_args.first().map(String::as_str) {
+        Some("check") => return diagnose(&cli_args[1..], check_str),
+        Some("lint") => return diagnose(&cli_args[1..], lint_str),
+        Some("lsp") => return lsp(),
+        Some("test") => return test(&cli_args[1..]),
+        Some("debug") => return debug_file(&cli_args[1..]),
+        Some("fmt") => return fmt(&cli_args[1..]),
+        _ => {},
     }
-    let fname = &args[1];
 
-    // Parse and run the program
-    let ast_list = parse::parse(&fname);
-    // println!("{:#?}", ast_list);
-    run::run(ast_list);
+    // Get filename and options from cli
+    let mut limits = Limits::default();
+    // Scripts run from the command line can always read stdin, but files and
+    // the environment have to be allowed explicitly
+    let mut capabilities = Capabilities { stdin: true, ..Capabilities::default() };
+    let mut fname = None;
+    let mut script_args = vec![];
+    for arg in cli_args {
+        // Everything after the file name is for the script
+        if fname.is_some() {
+            script_args.push(arg);
+            continue;
+        }
+        let (flag, value) = match arg.find('=') {
+            Some(idx) => (&arg[..idx], &arg[idx + 1..]),
+            None => (arg.as_str(), ""),
+        };
+        match flag {
+            "--max-depth" => limits.max_depth = flag_value(flag, value),
+            "--max-steps" => limits.max_steps = Some(flag_value(flag, value)),
+            "--max-memory" => limits.max_memory = Some(flag_value(flag, value)),
+            "--max-nesting" => limits.max_nesting = flag_value(flag, value),
+            "--allow-read" => capabilities.read.push(flag_value(flag, value)),
+            "--allow-write" => capabilities.write.push(flag_value(flag, value)),
+            "--allow-env" => capabilities.env = true,
+            _ if flag.starts_with("--") => {
+                eprintln!("unknown option {}\n{}", flag, USAGE);
+                process::exit(2);
+            },
+            _ => fname = Some(arg.clone()),
+        }
+    }
+    let fname = fname.unwrap_or_else(|| {
+        eprintln!("No input files given\n{}", USAGE);
+        process::exit(2);
+    });
 
+    // Parse and run the program
+    let mut interpreter = Interpreter::with_limits(limits);
+    interpreter.set_capabilities(capabilities);
+    interpreter.set_args(script_args);
+    match interpreter.eval_file(&fname) {
+        Ok(_) => {},
+        Err(IrisError::Exit(code)) => process::exit(code),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        },
+    }
 }
@@ -1,32 +1,129 @@
+mod strings;
 mod tok;
 
+use std::path::Path;
+
 use logos::Logos;
+use lalrpop_util::ParseError;
 use crate::ast::AstNode;
+use crate::error::IrisError;
 
 // This macro pulls in the grammar defined in grammar.lalrpop
-lalrpop_mod!(pub grammar);
+lalrpop_mod!(#[allow(clippy::all)] pub grammar);
 
-/// Read the given file and attempt to parse it
-pub fn parse(fname: &str) -> Vec<AstNode> {
-    let unparsed_file = std::fs::read_to_string(&fname)
-        .expect("cannot read iris file");
+/// Read the given source file
+pub fn read<P: AsRef<Path>>(fname: P) -> Result<String, IrisError> {
+    let fname = fname.as_ref();
+    std::fs::read_to_string(fname).map_err(|e| {
+        IrisError::Parse(format!("cannot read iris file {}: {}", fname.display(), e))
+    })
+}
 
-    // Logos' spanned() gives us a vector with type Iterator<Item, Range>
-    // LALRPOP wants Iterator<Location, Item, Location> - transform to that
-    let mut lexer: Vec<(usize,tok::Tok,usize)> = tok::Tok::lexer(&unparsed_file).spanned()
-        .map(|(tok, range)| (range.start, tok, range.end))
-        .collect();
+/// Attempt to parse the given source code
+pub fn parse_str(source: &str) -> Result<Vec<AstNode>, IrisError> {
+    try_parse(source).map_err(|(location, msg)| {
+        let (line, col) = line_col(source, location);
+        IrisError::Parse(format!("{} at {}:{}", msg, line, col))
+    })
+}
+
+/// Parse source code, giving where any error is as an offset
+pub fn try_parse(source: &str) -> Result<Vec<AstNode>, (usize, String)> {
+    let mut lexer = lex(source);
 
     // This next bit is really awful: if the last token isn't a Crlf, just append one
     // This is just a hack because I wanted to stop fighting the parser generator
     // to get inputs to work when there's no trailing newline.
-    if lexer.len() == 0 {
-        return vec![];
+    if lexer.is_empty() {
+        return Ok(vec![]);
     }
     let (_,last,_) = lexer.last().unwrap();
     if last != &tok::Tok::Crlf {
-        lexer.push((0, tok::Tok::Crlf, 0));
+        lexer.push((source.len(), tok::Tok::Crlf, source.len()));
     }
 
-    grammar::IrisParser::new().parse(&unparsed_file, lexer).unwrap()
+    grammar::IrisParser::new().parse(source, lexer).map_err(describe)
+}
+
+/// Parse a single expression, such as one interpolated into a string.
+/// Errors are located relative to the start of the expression.
+pub fn parse_expr(source: &str) -> Result<AstNode, (usize, String)> {
+    grammar::ExprParser::new().parse(source, lex(source)).map_err(describe)
+}
+
+fn lex(source: &str) -> Vec<(usize, tok::Tok<'_>, usize)> {
+    // Logos' spanned() gives us a vector with type Iterator<Item, Range>
+    // LALRPOP wants Iterator<Location, Item, Location> - transform to that
+    let mut tokens: Vec<_> = tok::Tok::lexer(source).spanned()
+        .map(|(tok, range)| (range.start, tok, range.end))
+        .collect();
+    for i in 1..tokens.len() {
+        let attached = tokens[i - 1].2 == tokens[i].0;
+        if attached && tokens[i].1 == tok::Tok::LBracket && matches!(tokens[i - 1].1, tok::Tok::Ident(_)) {
+            tokens[i].1 = tok::Tok::NameLBracket;
+        }
+    }
+    tokens
+}
+
+/// The comments in some source, with the byte offsets they start at. Comments
+/// are skipped by the lexer, so they're whatever is between tokens that
+/// isn't whitespace.
+pub fn comments(source: &str) -> Vec<(usize, &str)> {
+    let tokens = lex(source);
+    let mut gaps = vec![];
+    let mut prev_end = 0;
+    for (start, _, end) in &tokens {
+        gaps.push((prev_end, *start));
+        prev_end = *end;
+    }
+    gaps.push((prev_end, source.len()));
+
+    let mut comments = vec![];
+    for (start, end) in gaps {
+        let mut pos = start;
+        while let Some(idx) = source[pos..end].find('#') {
+            let comment_start = pos + idx;
+            let comment_end = source[comment_start..end].find(['\r', '\n'])
+                .map_or(end, |i| comment_start + i);
+            comments.push((comment_start, source[comment_start..comment_end].trim_end()));
+            pos = comment_end;
+        }
+    }
+    comments
+}
+
+/// The string literals in some source, as they're written, in order
+pub fn string_literals(source: &str) -> Vec<&str> {
+    lex(source).into_iter()
+        .filter_map(|(_, tok, _)| match tok {
+            tok::Tok::SingleLiteralString(s) | tok::Tok::DoubleLiteralString(s) => Some(s),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Describe a parse error, along with where it happened
+fn describe(e: ParseError<usize, tok::Tok<'_>, (usize, String)>) -> (usize, String) {
+    match e {
+        ParseError::InvalidToken { location } => (location, "invalid token".to_string()),
+        ParseError::UnrecognizedEOF { location, .. } => {
+            (location, "unexpected end of file".to_string())
+        },
+        ParseError::UnrecognizedToken { token: (start, tok, _), .. } => {
+            (start, format!("unexpected {:?}", tok))
+        },
+        ParseError::ExtraToken { token: (start, tok, _) } => {
+            (start, format!("extra token {:?}", tok))
+        },
+        ParseError::User { error } => error,
+    }
+}
+
+/// Turn a byte offset into a line and column, both counting from 1
+pub fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let col = before[before.rfind('\n').map_or(0, |i| i + 1)..].chars().count() + 1;
+    (line, col)
 }
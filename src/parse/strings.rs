@@ -0,0 +1,227 @@
+//! Processing of string literals: escape sequences, and interpolation of
+//! expressions like "hello {name}" in double quoted strings.
+//! Single quoted strings are raw, like in Ruby.
+//! `{{` and `}}` are kept as they are, like placeholders, for format().
+
+use crate::ast::{AstNode, Term};
+
+/// Parse the contents of a double quoted string literal, which may contain
+/// `{expr}` interpolations. `offset` is where the contents start in the source,
+/// for error reporting.
+pub fn interpolate(s: &str, offset: usize) -> Result<AstNode, (usize, String)> {
+    let mut parts = vec![];
+    let mut literal = String::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '\\' => {
+                // Leave escapes for unescape() to handle, but skip over the
+                // escaped chars so that \{ and \u{...} don't start an interpolation
+                literal.push(c);
+                if let Some((_, escaped)) = chars.next() {
+                    literal.push(escaped);
+                    if escaped == 'u' && chars.peek().map(|(_, c)| *c) == Some('{') {
+                        for (_, c) in chars.by_ref() {
+                            literal.push(c);
+                            if c == '}' {
+                                break;
+                            }
+                        }
+                    }
+                }
+            },
+            '{' | '}' if chars.peek().map(|(_, next)| *next) == Some(c) => {
+                // Literal braces for format(), not an interpolation
+                chars.next();
+                literal.push('\\');
+                literal.push(c);
+                literal.push('\\');
+                literal.push(c);
+            },
+            '{' => {
+                let end = closing_brace(s, idx)
+                    .ok_or_else(|| (offset + idx, format!("unclosed {{ in string ({})", LITERAL_BRACE_HINT)))?;
+                let expr = &s[idx + 1..end];
+                if is_placeholder(expr) {
+                    // Keep format() placeholders like {} and {:>8} as they are
+                    literal.push_str("\\{");
+                    literal.push_str(expr);
+                    literal.push_str("\\}");
+                } else {
+                    if !literal.is_empty() {
+                        parts.push(literal_part(&literal, offset + idx)?);
+                        literal.clear();
+                    }
+                    let ast = super::parse_expr(expr).map_err(|(loc, msg)| (offset + idx + 1 + loc, format!(
+                        "{} in a string interpolation ({})", msg, LITERAL_BRACE_HINT,
+                    )))?;
+                    parts.push(ast);
+                }
+                for (next_idx, _) in chars.by_ref() {
+                    if next_idx == end {
+                        break;
+                    }
+                }
+            },
+            '}' => return Err((offset + idx, format!("unmatched }} in string ({})", LITERAL_BRACE_HINT))),
+            _ => literal.push(c),
+        }
+    }
+
+    match parts.len() {
+        0 => literal_part(&literal, offset),
+        _ => {
+            if !literal.is_empty() {
+                parts.push(literal_part(&literal, offset + s.len())?);
+            }
+            Ok(AstNode::Interpolation(parts))
+        }
+    }
+}
+
+/// How to write braces that aren't interpolations, like in JSON
+const LITERAL_BRACE_HINT: &str = "use \\{ and \\} for literal braces, or a single quoted string";
+
+/// Whether the contents of a {} are a format() placeholder rather than an
+/// expression: empty, a format spec like :>8, or an arg index like 0 or 1:.2
+fn is_placeholder(s: &str) -> bool {
+    let index_end = s.find(':').unwrap_or(s.len());
+    s[..index_end].chars().all(|c| c.is_ascii_digit())
+}
+
+fn literal_part(s: &str, offset: usize) -> Result<AstNode, (usize, String)> {
+    let decoded = unescape(s).map_err(|msg| (offset, msg))?;
+    Ok(AstNode::Term(Term::Str(decoded)))
+}
+
+/// Find the index of the brace closing the one at `open`, skipping over any
+/// nested braces and single quoted strings inside the expression
+fn closing_brace(s: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (idx, c) in s[open..].char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '\'' => in_string = false,
+                _ => {},
+            }
+            continue;
+        }
+        match c {
+            '\'' => in_string = true,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + idx);
+                }
+            },
+            _ => {},
+        }
+    }
+    None
+}
+
+/// Decode a single quoted string literal. These are taken literally, apart
+/// from \' and \\, so backslashes can be written freely, e.g. in regexes.
+pub fn unescape_single(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(&next) = chars.peek() {
+                if next == '\'' || next == '\\' {
+                    ret.push(next);
+                    chars.next();
+                    continue;
+                }
+            }
+        }
+        ret.push(c);
+    }
+    ret
+}
+
+/// Decode the escape sequences in the contents of a string literal
+pub fn unescape(s: &str) -> Result<String, String> {
+    let mut ret = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            ret.push(c);
+            continue;
+        }
+        let escaped = chars.next().ok_or("string ends with a lone \\")?;
+        match escaped {
+            'n' => ret.push('\n'),
+            't' => ret.push('\t'),
+            'r' => ret.push('\r'),
+            '0' => ret.push('\0'),
+            '\\' | '\'' | '"' | '{' | '}' => ret.push(escaped),
+            'u' => {
+                if chars.next() != Some('{') {
+                    return Err("expected \\u{...} unicode escape".to_string());
+                }
+                let mut hex = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => hex.push(c),
+                        None => return Err("unclosed \\u{...} unicode escape".to_string()),
+                    }
+                }
+                let code = u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(std::char::from_u32)
+                    .ok_or_else(|| format!("invalid unicode escape \\u{{{}}}", hex))?;
+                ret.push(code);
+            },
+            _ => return Err(format!("unknown escape sequence \\{}", escaped)),
+        }
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Interpreter, Value};
+
+    fn str_term(s: &str) -> AstNode {
+        AstNode::Term(Term::Str(s.to_string()))
+    }
+
+    fn eval(source: &str) -> Value {
+        Interpreter::new().eval_str(source).unwrap()
+    }
+
+    #[test]
+    fn double_braces_are_kept_for_format() {
+        assert_eq!(interpolate("{{}}", 0), Ok(str_term("{{}}")));
+        assert_eq!(interpolate("a {{b}} c", 0), Ok(str_term("a {{b}} c")));
+        assert_eq!(eval("format(\"{{}}\", 1)"), Value::Str("{}".to_string()));
+        assert_eq!(eval("format(\"{{{}}}\", 1)"), Value::Str("{1}".to_string()));
+        assert_eq!(eval("let x = 2\n\"{{x}} is {x}\""), Value::Str("{{x}} is 2".to_string()));
+    }
+
+    #[test]
+    fn escaped_braces_are_literal() {
+        assert_eq!(interpolate("\\{x\\}", 0), Ok(str_term("{x}")));
+        assert_eq!(eval("let x = 1\n\"\\{x\\}\""), Value::Str("{x}".to_string()));
+    }
+
+    #[test]
+    fn json_in_double_quotes_suggests_single_quotes() {
+        let (_, msg) = interpolate("{\\\"a\\\": 1}", 10).unwrap_err();
+        assert!(msg.contains("single quoted string"), "{}", msg);
+        let (pos, msg) = interpolate("{\"a\": 1", 10).unwrap_err();
+        assert_eq!(pos, 10);
+        assert!(msg.contains("unclosed {"), "{}", msg);
+        let (pos, msg) = interpolate("a}", 10).unwrap_err();
+        assert_eq!(pos, 11);
+        assert!(msg.contains("\\}"), "{}", msg);
+    }
+}
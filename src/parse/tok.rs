@@ -48,6 +48,12 @@ pub enum Tok<'input> {
     #[token("[")]
     LBracket,
 
+    /// A `[` right after a name with no space between, as in `xs[0]` or
+    /// `list[int]`. The lexer never makes these; `lex` turns an LBracket into
+    /// one, so a one line fn like `fn f() -> list [1] end` can be told apart
+    /// from a return type of `list[int]`.
+    NameLBracket,
+
     #[token("]")]
     RBracket,
 
@@ -66,6 +72,9 @@ pub enum Tok<'input> {
     #[token(",")]
     Comma,
 
+    #[token(":")]
+    Colon,
+
     #[token(";")]
     Semicolon,
 
@@ -165,10 +174,10 @@ pub enum Tok<'input> {
     #[token("do")]
     Do,
 
-    #[regex(r#"'([^'\\]|\\t|\\u|\\n|\\')*'"#)]
+    #[regex(r#"'([^'\\]|\\.)*'"#)]
     SingleLiteralString(&'input str),
 
-    #[regex(r#""([^"\\]|\\t|\\u|\\n|\\")*""#)]
+    #[regex(r#""([^"\\]|\\.)*""#)]
     DoubleLiteralString(&'input str),
 
     #[regex("[a-zA-Z_]+[a-zA-Z0-9_]*")]
@@ -177,10 +186,14 @@ pub enum Tok<'input> {
     #[regex("[0-9]+", |lex| lex.slice().parse())]
     Number(i32),
 
+    #[regex(r"[0-9]+\.[0-9]+", |lex| lex.slice().parse())]
+    Float(f64),
+
     #[regex(r"[\r\n]+")]
     Crlf,
 
-    #[regex(r"#.*(\r|\n)", logos::skip)]
+    // Comments run to the end of the line, but leave the newline to end the statement
+    #[regex(r"#[^\r\n]*", logos::skip)]
     Comment,
 
     #[error]
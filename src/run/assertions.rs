@@ -0,0 +1,66 @@
+//! Assertions, for tests run by `iris test`. A failed assertion is an error,
+//! so it stops the program wherever it's used.
+
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use crate::error::IrisError;
+use super::builtins::{expect_args, expect_args_between, expect_str, repr};
+use super::ops::equals;
+use super::registry::Registry;
+use super::scope::Scope;
+use super::{Value, call_in, test_bool_val};
+
+pub fn register(registry: &mut Registry) {
+    registry.register_fn("assert", assert);
+    registry.register_fn("assert_eq", assert_eq);
+    registry.register_scoped_fn("assert_raises", assert_raises);
+}
+
+fn failed(msg: String) -> IrisError {
+    IrisError::runtime(format!("assertion failed: {}", msg))
+}
+
+/// assert(cond, message) fails if cond is false. The message is optional.
+fn assert(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args_between("assert", args, 1, 2)?;
+    if test_bool_val(args[0].clone())? {
+        return Ok(Value::None);
+    }
+    match args.get(1) {
+        Some(msg) => Err(failed(expect_str("assert", msg)?.to_string())),
+        None => Err(IrisError::runtime("assertion failed")),
+    }
+}
+
+/// assert_eq(a, b) fails unless a == b
+fn assert_eq(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("assert_eq", args, 2)?;
+    if equals(&args[0], &args[1])? {
+        return Ok(Value::None);
+    }
+    Err(failed(format!("{} != {}", repr(&args[0]), repr(&args[1]))))
+}
+
+/// assert_raises(f, text) calls f with no args, and fails unless that gives
+/// an error. If text is given, the error message has to contain it.
+fn assert_raises(scope: &Rc<RefCell<Scope>>, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args_between("assert_raises", args, 1, 2)?;
+    let want = args.get(1).map(|text| expect_str("assert_raises", text)).transpose()?;
+    let runtime = scope.borrow().runtime();
+    let position = runtime.borrow().position;
+    match call_in(scope, &args[0], vec![]) {
+        Ok(val) => Err(failed(format!("expected an error, but got {}", repr(&val)))),
+        // Leaving the program isn't an error to be caught
+        Err(e @ IrisError::Exit(_)) => Err(e),
+        Err(e) => {
+            runtime.borrow_mut().caught(position);
+            match want {
+                Some(want) if !e.to_string().contains(want) => {
+                    Err(failed(format!("expected an error containing {:?}, but got \"{}\"", want, e)))
+                },
+                _ => Ok(Value::None),
+            }
+        },
+    }
+}
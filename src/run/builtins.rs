@@ -1,35 +1,191 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::error::IrisError;
 use crate::run::Value;
+use super::registry::Registry;
+use super::scope::Scope;
+
+/// Add all of the builtin functions to the registry
+pub fn register(registry: &mut Registry) {
+    registry.register_fn("print", print);
+    registry.register_fn("list", |args| Ok(Value::List(args.to_vec())));
+    registry.register_fn("len", len);
+    registry.register_fn("repr", |args| {
+        expect_args("repr", args, 1)?;
+        Ok(Value::Str(repr(&args[0])))
+    });
+    registry.register_fn("str", |args| {
+        expect_args("str", args, 1)?;
+        Ok(Value::Str(fmt(&args[0])))
+    });
+    registry.register_fn("slice", slice);
+    registry.register_fn("Some", some);
+    registry.register_fn("unwrap", unwrap);
+    registry.register_fn("is_some", is_some);
+    registry.register_fn("is_none", is_none);
+}
+
+/// Format the given value into a string, the way print shows it.
+/// Strings are shown as they are, but anything inside a list, dict or
+/// Some is shown with repr, so that "1" and 1 can be told apart.
+pub fn fmt(val: &Value) -> String {
+    match val {
+        Value::Str(x) => x.to_string(),
+        _ => repr(val),
+    }
+}
 
-/// Format the given value into a string
-fn fmt(val: &Value) -> String {
+/// Format the given value the way it would be written in source, with
+/// strings quoted
+pub fn repr(val: &Value) -> String {
     match val {
         Value::Integer(x) => format!("{}", x),
-        Value::Str(x) => format!("{}", x),
+        // Debug formatting keeps the ".0" on whole numbers
+        Value::DoublePrecisionFloat(x) => format!("{:?}", x),
+        Value::Str(x) => repr_str(x),
         Value::Bool(x) => format!("{}", x),
         Value::Function(f) => format!("function \"{}\"", f.name),
+        Value::NativeFunction(f) => format!("native function \"{}\"", f.name),
+        Value::Native(obj) => format!("<{}>", obj.type_name),
         Value::List(vs) => {
-            let strings: Vec<String> = vs.iter().map(|v| fmt(v)).collect();
+            let strings: Vec<String> = vs.iter().map(repr).collect();
             format!("[{}]", strings.join(", "))
         },
+        Value::Dict(d) => {
+            let strings: Vec<String> = d.iter()
+                .map(|(k, v)| format!("{}: {}", repr(k), repr(v)))
+                .collect();
+            format!("{{{}}}", strings.join(", "))
+        },
+        Value::Set(s) => {
+            let strings: Vec<String> = s.iter().map(repr).collect();
+            format!("set([{}])", strings.join(", "))
+        },
         Value::None => "None".to_string(),
-        Value::Some(s) => fmt(s),
+        Value::Some(s) => format!("Some({})", repr(s)),
+    }
+}
+
+/// Quote a string as a double quoted literal, escaping anything that the
+/// parser would otherwise treat specially
+fn repr_str(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len() + 2);
+    ret.push('"');
+    for c in s.chars() {
+        match c {
+            '"' | '\\' | '{' | '}' => {
+                ret.push('\\');
+                ret.push(c);
+            },
+            '\n' => ret.push_str("\\n"),
+            '\t' => ret.push_str("\\t"),
+            '\r' => ret.push_str("\\r"),
+            '\0' => ret.push_str("\\0"),
+            c if c.is_control() => ret.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => ret.push(c),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+/// Check a builtin has room in the memory budget for a result of the given
+/// size in bytes, before making it. None means the size doesn't even fit in
+/// a usize.
+pub fn reserve(scope: &Rc<RefCell<Scope>>, name: &str, bytes: Option<usize>) -> Result<(), IrisError> {
+    match bytes {
+        Some(bytes) => scope.borrow().runtime().borrow().reserve(bytes),
+        None => Err(IrisError::runtime(format!("{}() result is too big", name))),
+    }
+}
+
+/// Check that a builtin was called with the right number of args
+pub fn expect_args(name: &str, args: &[Value], n: usize) -> Result<(), IrisError> {
+    if args.len() != n {
+        return Err(IrisError::runtime(format!(
+            "{}() accepts exactly {} argument{}, got {}",
+            name, n, if n == 1 { "" } else { "s" }, args.len(),
+        )));
+    }
+    Ok(())
+}
+
+/// Check that a builtin was called with between min and max args
+pub fn expect_args_between(name: &str, args: &[Value], min: usize, max: usize) -> Result<(), IrisError> {
+    if args.len() < min || args.len() > max {
+        return Err(IrisError::runtime(format!(
+            "{}() accepts {} to {} arguments, got {}", name, min, max, args.len(),
+        )));
+    }
+    Ok(())
+}
+
+pub fn expect_str<'a>(name: &str, val: &'a Value) -> Result<&'a str, IrisError> {
+    match val {
+        Value::Str(s) => Ok(s),
+        _ => Err(IrisError::runtime(format!("{}() expected a string, got {:?}", name, val))),
+    }
+}
+
+pub fn expect_int(name: &str, val: &Value) -> Result<i32, IrisError> {
+    match val {
+        Value::Integer(x) => Ok(*x),
+        _ => Err(IrisError::runtime(format!("{}() expected an int, got {:?}", name, val))),
+    }
+}
+
+pub fn expect_list<'a>(name: &str, val: &'a Value) -> Result<&'a [Value], IrisError> {
+    match val {
+        Value::List(xs) => Ok(xs),
+        _ => Err(IrisError::runtime(format!("{}() expected a list, got {:?}", name, val))),
     }
 }
 
 /// Builtin "print" function
-pub fn print(args: Vec<Value>) -> Value {
-    let print_strs: Vec<String> = args.iter().map(|arg| fmt(&arg)).collect();
+pub fn print(args: &[Value]) -> Result<Value, IrisError> {
+    let print_strs: Vec<String> = args.iter().map(fmt).collect();
     let joined = print_strs.join(" ");
     println!("{}", joined);
-    Value::None
+    Ok(Value::None)
 }
 
 /// Builtin len() for collections
-pub fn len(args: Vec<Value>) -> Value {
-    assert_eq!(args.len(), 1, "len() accepts exactly one argument");
+pub fn len(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("len", args, 1)?;
+    match &args[0] {
+        Value::List(xs) => Ok(Value::Integer(xs.len() as i32)),
+        Value::Dict(d) => Ok(Value::Integer(d.len() as i32)),
+        Value::Set(s) => Ok(Value::Integer(s.len() as i32)),
+        Value::Str(s) => Ok(Value::Integer(s.chars().count() as i32)),
+        _ => Err(IrisError::runtime(format!("Can't get len() of a {:?}", args[0]))),
+    }
+}
+
+/// Builtin slice(x, start, end) for strings and lists. Negative indexes count
+/// from the end, and end may be left off to slice to the end.
+pub fn slice(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args_between("slice", args, 2, 3)?;
+    let len = match &args[0] {
+        Value::List(xs) => xs.len(),
+        Value::Str(s) => s.chars().count(),
+        _ => return Err(IrisError::runtime(format!("Can't slice() a {:?}", args[0]))),
+    };
+    // Clamp to the bounds, like Python does
+    let index = |val: &Value| -> Result<usize, IrisError> {
+        let i = expect_int("slice", val)? as i64;
+        let i = if i < 0 { len as i64 + i } else { i };
+        Ok(i.clamp(0, len as i64) as usize)
+    };
+    let start = index(&args[1])?;
+    let end = match args.get(2) {
+        Some(val) => index(val)?,
+        None => len,
+    }.max(start);
     match &args[0] {
-        Value::List(xs) => Value::Integer(xs.len() as i32),
-        _ => panic!("Can't get len() of a {:?}", args[0]),
+        Value::List(xs) => Ok(Value::List(xs[start..end].to_vec())),
+        Value::Str(s) => Ok(Value::Str(s.chars().skip(start).take(end - start).collect())),
+        _ => unreachable!(),
     }
 }
 
@@ -37,36 +193,110 @@ pub fn len(args: Vec<Value>) -> Value {
 /// This and the following function provide an interace to work with an
 /// Option type, like in rust. These are roughed in like this at the moment
 /// to support iterators.
-pub fn some(args: Vec<Value>) -> Value {
-    assert_eq!(args.len(), 1, "Some() accepts exactly one argument");
-    Value::Some(Box::new(args[0].clone()))
+pub fn some(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("Some", args, 1)?;
+    Ok(Value::Some(Box::new(args[0].clone())))
 }
 
-pub fn unwrap(args: Vec<Value>) -> Value {
-    assert_eq!(args.len(), 1, "unwrap() accepts exactly one argument");
+pub fn unwrap(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("unwrap", args, 1)?;
     let val = args[0].clone();
     match val {
-        Value::Some(val) => *val,
-        _ => panic!("Tried to unwrap() {:?}", val),
+        Value::Some(val) => Ok(*val),
+        _ => Err(IrisError::runtime(format!("Tried to unwrap() {:?}", val))),
     }
 }
 
-pub fn is_some(args: Vec<Value>) -> Value {
-    assert_eq!(args.len(), 1, "is_some() accepts exactly one argument");
+pub fn is_some(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("is_some", args, 1)?;
     let val = &args[0];
     match val {
-        Value::Some(_) => Value::Bool(true),
-        Value::None => Value::Bool(false),
-        _ => panic!("called is_some() on {:?}", val)
+        Value::Some(_) => Ok(Value::Bool(true)),
+        Value::None => Ok(Value::Bool(false)),
+        _ => Err(IrisError::runtime(format!("called is_some() on {:?}", val))),
     }
 }
 
-pub fn is_none(args: Vec<Value>) -> Value {
-    assert_eq!(args.len(), 1, "is_none() accepts exactly one argument");
+pub fn is_none(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("is_none", args, 1)?;
     let val = &args[0];
     match val {
-        Value::None => Value::Bool(true),
-        Value::Some(_) => Value::Bool(false),
-        _ => panic!("called is_none() on {:?}", val)
+        Value::None => Ok(Value::Bool(true)),
+        Value::Some(_) => Ok(Value::Bool(false)),
+        _ => Err(IrisError::runtime(format!("called is_none() on {:?}", val))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Interpreter;
+
+    fn eval(source: &str) -> Value {
+        match Interpreter::new().eval_str(source) {
+            Ok(val) => val,
+            Err(e) => panic!("{}: {}", source, e),
+        }
     }
-}
\ No newline at end of file
+
+    fn shown(source: &str) -> String {
+        match eval(source) {
+            Value::Str(s) => s,
+            val => panic!("{} gave {:?}", source, val),
+        }
+    }
+
+    #[test]
+    fn repr_quotes_strings_and_shows_some() {
+        assert_eq!(repr(&Value::from("a\"b")), r#""a\"b""#);
+        assert_eq!(repr(&Value::from("{x}\n")), r#""\{x\}\n""#);
+        assert_eq!(repr(&Value::from(vec!["1"])), r#"["1"]"#);
+        assert_eq!(repr(&Value::from(Some(3))), "Some(3)");
+        assert_eq!(repr(&Value::from(1.0)), "1.0");
+        assert_eq!(shown("repr({\"k\": [None, Some(\"v\")]})"), r#"{"k": [None, Some("v")]}"#);
+        assert_eq!(shown("repr(set([1]))"), "set([1])");
+    }
+
+    #[test]
+    fn display_only_leaves_the_outer_string_unquoted() {
+        assert_eq!(fmt(&Value::from("a\"b")), "a\"b");
+        assert_eq!(fmt(&Value::from(vec![Value::from("1"), Value::from(1)])), r#"["1", 1]"#);
+        assert_eq!(fmt(&Value::from(Some("s"))), r#"Some("s")"#);
+        assert_eq!(shown("str(Some(3))"), "Some(3)");
+        assert_eq!(shown("str(\"a\")"), "a");
+        assert_eq!(shown("str(2.5)"), "2.5");
+    }
+
+    #[test]
+    fn repr_round_trips() {
+        for source in &[
+            "\"quote \\\" brace \\{ backslash \\\\ tab \\t\"",
+            "[1, 2.0, \"3\", None, Some([true])]",
+            "{\"a\": {\"b\": \"\\{\\}\"}}",
+            "\"\\u{1}\"",
+        ] {
+            let val = eval(source);
+            assert_eq!(eval(&repr(&val)), val, "{}", source);
+        }
+    }
+
+    #[test]
+    fn interpolation_displays_values() {
+        assert_eq!(shown("let s = \"hi\"\n\"{s}!\""), "hi!");
+        assert_eq!(shown("let xs = [\"a\", 1]\n\"xs: {xs}\""), r#"xs: ["a", 1]"#);
+        assert_eq!(shown("\"{Some('a')} {None}\""), r#"Some("a") None"#);
+        assert_eq!(shown("\"{repr('a')}\""), "\"a\"");
+        assert_eq!(shown("let x = 1.0\n\"{x}\""), "1.0");
+    }
+
+    #[test]
+    fn interpolation_and_format_go_together() {
+        // Placeholders are left for format(), and interpolations are filled in first
+        assert_eq!(shown("let w = \"x\"\nformat(\"{w}: {:>4}|{:?}\", 1, \"s\")"), "x:    1|\"s\"");
+        assert_eq!(shown("format(\"{1}{0}\", \"a\", \"b\")"), "ba");
+        // An interpolated string with braces in it is a format template too
+        assert_eq!(shown("let t = \"{}\"\nformat(\"<{t}>\", 5)"), "<5>");
+        assert_eq!(shown("format(\"{:?}\", [\"a\"])"), r#"["a"]"#);
+        assert_eq!(shown("format(\"{}\", \"a\")"), "a");
+    }
+}
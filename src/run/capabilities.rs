@@ -0,0 +1,64 @@
+//! What a script is allowed to touch outside the interpreter. Everything is
+//! off by default, so an embedded or untrusted script can compute and print,
+//! but can't read files, write files or look at the environment unless the
+//! host allows it.
+
+use std::path::{Path, PathBuf};
+
+use crate::error::IrisError;
+
+/// Access granted to a running program
+#[derive(Clone, Debug, Default)]
+pub struct Capabilities {
+    /// Directories whose files can be read, including everything below them
+    pub read: Vec<PathBuf>,
+    /// Directories whose files can be written, including everything below them
+    pub write: Vec<PathBuf>,
+    /// Whether env(name) can read environment variables
+    pub env: bool,
+    /// Whether stdin_lines() can read from standard input
+    pub stdin: bool,
+}
+
+impl Capabilities {
+    /// Check that path is inside one of the read directories
+    pub fn check_read(&self, path: &str) -> Result<PathBuf, IrisError> {
+        check(&self.read, path, "read", "--allow-read")
+    }
+
+    /// Check that path is inside one of the write directories
+    pub fn check_write(&self, path: &str) -> Result<PathBuf, IrisError> {
+        check(&self.write, path, "write", "--allow-write")
+    }
+}
+
+/// Resolve a path as far as we can, so that "..", "." and symlinks can't be
+/// used to get out of an allowed directory. Paths that don't exist yet, like
+/// a file about to be written, are resolved through their parent directory.
+/// Symlinks to somewhere that doesn't exist can't be resolved, and writing
+/// to them would follow them anywhere, so they're never allowed.
+fn resolve(path: &Path) -> Option<PathBuf> {
+    if let Ok(resolved) = path.canonicalize() {
+        return Some(resolved);
+    }
+    if path.symlink_metadata().is_ok() {
+        return None;
+    }
+    let name = path.file_name()?;
+    let parent = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    Some(resolve(parent)?.join(name))
+}
+
+fn check(allowed: &[PathBuf], path: &str, access: &str, flag: &str) -> Result<PathBuf, IrisError> {
+    let denied = || IrisError::runtime(format!(
+        "no {} access to {:?}. Use {}=DIR to allow it.", access, path, flag,
+    ));
+    let resolved = resolve(Path::new(path)).ok_or_else(denied)?;
+    let ok = allowed.iter()
+        .filter_map(|dir| dir.canonicalize().ok())
+        .any(|dir| resolved.starts_with(dir));
+    if ok { Ok(resolved) } else { Err(denied()) }
+}
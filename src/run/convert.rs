@@ -0,0 +1,142 @@
+//! Conversions between Rust types and Iris values, for passing data across
+//! the boundary with a host program
+
+use std::convert::TryFrom;
+
+use crate::error::IrisError;
+use super::Value;
+
+impl From<i32> for Value {
+    fn from(x: i32) -> Value {
+        Value::Integer(x)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(x: f64) -> Value {
+        Value::DoublePrecisionFloat(x)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(x: bool) -> Value {
+        Value::Bool(x)
+    }
+}
+
+impl From<String> for Value {
+    fn from(x: String) -> Value {
+        Value::Str(x)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(x: &str) -> Value {
+        Value::Str(x.to_string())
+    }
+}
+
+impl From<()> for Value {
+    fn from(_: ()) -> Value {
+        Value::None
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(xs: Vec<T>) -> Value {
+        Value::List(xs.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(x: Option<T>) -> Value {
+        match x {
+            Some(x) => Value::Some(Box::new(x.into())),
+            None => Value::None,
+        }
+    }
+}
+
+/// The error for a value that isn't the Rust type we wanted
+fn mismatch(expected: &str, val: &Value) -> IrisError {
+    IrisError::runtime(format!("expected {}, got {:?}", expected, val))
+}
+
+impl TryFrom<Value> for i32 {
+    type Error = IrisError;
+
+    fn try_from(val: Value) -> Result<i32, IrisError> {
+        match val {
+            Value::Integer(x) => Ok(x),
+            _ => Err(mismatch("int", &val)),
+        }
+    }
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = IrisError;
+
+    fn try_from(val: Value) -> Result<f64, IrisError> {
+        match val {
+            Value::DoublePrecisionFloat(x) => Ok(x),
+            Value::Integer(x) => Ok(x as f64),
+            _ => Err(mismatch("float", &val)),
+        }
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = IrisError;
+
+    fn try_from(val: Value) -> Result<bool, IrisError> {
+        match val {
+            Value::Bool(x) => Ok(x),
+            _ => Err(mismatch("bool", &val)),
+        }
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = IrisError;
+
+    fn try_from(val: Value) -> Result<String, IrisError> {
+        match val {
+            Value::Str(x) => Ok(x),
+            _ => Err(mismatch("str", &val)),
+        }
+    }
+}
+
+impl TryFrom<Value> for () {
+    type Error = IrisError;
+
+    fn try_from(val: Value) -> Result<(), IrisError> {
+        match val {
+            Value::None => Ok(()),
+            _ => Err(mismatch("None", &val)),
+        }
+    }
+}
+
+impl<T: TryFrom<Value, Error = IrisError>> TryFrom<Value> for Vec<T> {
+    type Error = IrisError;
+
+    fn try_from(val: Value) -> Result<Vec<T>, IrisError> {
+        match val {
+            Value::List(xs) => xs.into_iter().map(T::try_from).collect(),
+            _ => Err(mismatch("list", &val)),
+        }
+    }
+}
+
+impl<T: TryFrom<Value, Error = IrisError>> TryFrom<Value> for Option<T> {
+    type Error = IrisError;
+
+    fn try_from(val: Value) -> Result<Option<T>, IrisError> {
+        match val {
+            Value::Some(x) => Ok(Some(T::try_from(*x)?)),
+            Value::None => Ok(None),
+            _ => Err(mismatch("Option", &val)),
+        }
+    }
+}
@@ -0,0 +1,423 @@
+//! Dicts, written as `{"a": 1, "b": 2}`, and sets, made with set(xs). Like
+//! lists they're values: insert, add and remove give back a new dict or set
+//! rather than changing the one passed in. Entries keep the order they were
+//! first inserted in. Apart from dict() and set(), everything here is a
+//! method, like `d.get(key)` or `s.add(x)`, so it doesn't take common names
+//! like add and get away from other types.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use crate::error::IrisError;
+use super::builtins::{expect_args, expect_args_between};
+use super::iter::iterate;
+use super::ops;
+use super::registry::Registry;
+use super::scope::Scope;
+use super::typing::{PrimitiveType, TypeDefinition};
+use super::Value;
+
+/// An insertion ordered map from Iris values to Iris values
+#[derive(Clone, Default)]
+pub struct Dict {
+    entries: Vec<(Value, Value)>,
+    /// Where each key is in entries
+    index: HashMap<Value, usize>,
+}
+
+impl Dict {
+    pub fn new() -> Dict {
+        Dict::default()
+    }
+
+    pub fn get(&self, key: &Value) -> Option<&Value> {
+        self.index.get(key).map(|&idx| &self.entries[idx].1)
+    }
+
+    /// Set the value for a key. A key that's already there keeps its place.
+    /// Keys can be anything but functions.
+    pub fn insert(&mut self, key: Value, val: Value) -> Result<(), IrisError> {
+        ops::check_hashable(&key)?;
+        match self.index.get(&key) {
+            Some(&idx) => self.entries[idx].1 = val,
+            None => {
+                self.index.insert(key.clone(), self.entries.len());
+                self.entries.push((key, val));
+            },
+        }
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: &Value) -> Option<Value> {
+        let removed = self.index.remove(key)?;
+        for idx in self.index.values_mut() {
+            if *idx > removed {
+                *idx -= 1;
+            }
+        }
+        Some(self.entries.remove(removed).1)
+    }
+
+    pub fn contains_key(&self, key: &Value) -> bool {
+        self.index.contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Value, &Value)> {
+        self.entries.iter().map(|(k, v)| (k, v))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Value> {
+        self.entries.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.entries.iter().map(|(_, v)| v)
+    }
+}
+
+/// Dicts are equal if they have the same entries, in any order
+impl PartialEq for Dict {
+    fn eq(&self, other: &Dict) -> bool {
+        self.len() == other.len() && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+impl fmt::Debug for Dict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// An insertion ordered set of Iris values
+#[derive(Clone, Default, PartialEq)]
+pub struct Set {
+    items: Dict,
+}
+
+impl Set {
+    pub fn new() -> Set {
+        Set::default()
+    }
+
+    /// Add an item, if it isn't already there. Items can be anything but functions.
+    pub fn insert(&mut self, item: Value) -> Result<(), IrisError> {
+        self.items.insert(item, Value::None)
+    }
+
+    pub fn remove(&mut self, item: &Value) -> bool {
+        self.items.remove(item).is_some()
+    }
+
+    pub fn contains(&self, item: &Value) -> bool {
+        self.items.contains_key(item)
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Value> {
+        self.items.keys()
+    }
+}
+
+impl fmt::Debug for Set {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+pub fn register(registry: &mut Registry) {
+    let dict_type = TypeDefinition::PrimitiveType(PrimitiveType::Dict);
+    let set_type = TypeDefinition::PrimitiveType(PrimitiveType::Set);
+    registry.register_scoped_fn("dict", dict);
+    registry.register_method("get", dict_type.clone(), get);
+    registry.register_method("insert", dict_type.clone(), insert);
+    registry.register_method("remove", dict_type.clone(), remove);
+    registry.register_method("has", dict_type.clone(), has);
+    registry.register_method("keys", dict_type.clone(), keys);
+    registry.register_method("values", dict_type.clone(), values);
+    registry.register_method("items", dict_type, items);
+    registry.register_scoped_fn("set", set);
+    registry.register_method("add", set_type.clone(), add);
+    registry.register_method("remove", set_type.clone(), remove);
+    registry.register_method("has", set_type.clone(), has);
+    registry.register_method("union", set_type.clone(), union);
+    registry.register_method("intersection", set_type.clone(), intersection);
+    registry.register_method("difference", set_type, difference);
+}
+
+pub fn expect_dict<'a>(name: &str, val: &'a Value) -> Result<&'a Dict, IrisError> {
+    match val {
+        Value::Dict(d) => Ok(d),
+        _ => Err(IrisError::runtime(format!("{}() expected a dict, got {:?}", name, val))),
+    }
+}
+
+/// dict() makes an empty dict, and dict(pairs) makes one from [key, value] pairs
+pub fn dict(scope: &Rc<RefCell<Scope>>, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args_between("dict", args, 0, 1)?;
+    let mut ret = Dict::new();
+    if let Some(pairs) = args.first() {
+        for pair in iterate(pairs.clone(), scope)? {
+            match pair? {
+                Value::List(kv) if kv.len() == 2 => {
+                    let mut kv = kv.into_iter();
+                    ret.insert(kv.next().unwrap(), kv.next().unwrap())?;
+                },
+                other => return Err(IrisError::runtime(format!(
+                    "dict() expected [key, value] pairs, got {:?}", other,
+                ))),
+            }
+        }
+    }
+    Ok(Value::Dict(ret))
+}
+
+/// get(d, key) gives Some(value), or None if the key isn't there
+pub fn get(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("get", args, 2)?;
+    let d = expect_dict("get", &args[0])?;
+    Ok(match d.get(&args[1]) {
+        Some(val) => Value::Some(Box::new(val.clone())),
+        None => Value::None,
+    })
+}
+
+/// insert(d, key, value) gives a copy of d with key set to value
+pub fn insert(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("insert", args, 3)?;
+    let mut d = expect_dict("insert", &args[0])?.clone();
+    d.insert(args[1].clone(), args[2].clone())?;
+    Ok(Value::Dict(d))
+}
+
+/// remove(d, key) gives a copy of a dict or set without key
+pub fn remove(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("remove", args, 2)?;
+    match &args[0] {
+        Value::Set(s) => {
+            let mut s = s.clone();
+            s.remove(&args[1]);
+            Ok(Value::Set(s))
+        },
+        val => {
+            let mut d = expect_dict("remove", val)?.clone();
+            d.remove(&args[1]);
+            Ok(Value::Dict(d))
+        },
+    }
+}
+
+/// has(d, key) checks if a dict has a key, or a set has an item
+pub fn has(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("has", args, 2)?;
+    match &args[0] {
+        Value::Set(s) => Ok(Value::Bool(s.contains(&args[1]))),
+        val => Ok(Value::Bool(expect_dict("has", val)?.contains_key(&args[1]))),
+    }
+}
+
+pub fn keys(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("keys", args, 1)?;
+    Ok(Value::List(expect_dict("keys", &args[0])?.keys().cloned().collect()))
+}
+
+pub fn values(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("values", args, 1)?;
+    Ok(Value::List(expect_dict("values", &args[0])?.values().cloned().collect()))
+}
+
+/// items(d) lists the entries as [key, value] pairs
+pub fn items(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("items", args, 1)?;
+    let d = expect_dict("items", &args[0])?;
+    Ok(Value::List(
+        d.iter()
+            .map(|(k, v)| Value::List(vec![k.clone(), v.clone()]))
+            .collect()
+    ))
+}
+
+pub fn expect_set<'a>(name: &str, val: &'a Value) -> Result<&'a Set, IrisError> {
+    match val {
+        Value::Set(s) => Ok(s),
+        _ => Err(IrisError::runtime(format!("{}() expected a set, got {:?}", name, val))),
+    }
+}
+
+/// set() makes an empty set, and set(xs) makes one from the items of xs
+pub fn set(scope: &Rc<RefCell<Scope>>, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args_between("set", args, 0, 1)?;
+    let mut ret = Set::new();
+    if let Some(items) = args.first() {
+        for item in iterate(items.clone(), scope)? {
+            ret.insert(item?)?;
+        }
+    }
+    Ok(Value::Set(ret))
+}
+
+/// add(s, x) gives a copy of s with x added
+pub fn add(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("add", args, 2)?;
+    let mut s = expect_set("add", &args[0])?.clone();
+    s.insert(args[1].clone())?;
+    Ok(Value::Set(s))
+}
+
+/// union(a, b) gives the items in either set
+pub fn union(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("union", args, 2)?;
+    let mut ret = expect_set("union", &args[0])?.clone();
+    for item in expect_set("union", &args[1])?.iter() {
+        ret.insert(item.clone())?;
+    }
+    Ok(Value::Set(ret))
+}
+
+/// intersection(a, b) gives the items in both sets
+pub fn intersection(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("intersection", args, 2)?;
+    let a = expect_set("intersection", &args[0])?;
+    let b = expect_set("intersection", &args[1])?;
+    let mut ret = Set::new();
+    for item in a.iter().filter(|item| b.contains(item)) {
+        ret.insert(item.clone())?;
+    }
+    Ok(Value::Set(ret))
+}
+
+/// difference(a, b) gives the items in a that aren't in b
+pub fn difference(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("difference", args, 2)?;
+    let a = expect_set("difference", &args[0])?;
+    let b = expect_set("difference", &args[1])?;
+    let mut ret = Set::new();
+    for item in a.iter().filter(|item| !b.contains(item)) {
+        ret.insert(item.clone())?;
+    }
+    Ok(Value::Set(ret))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Interpreter;
+
+    fn eval(source: &str) -> Result<Value, IrisError> {
+        Interpreter::new().eval_str(source)
+    }
+
+    fn ints(xs: &[i32]) -> Value {
+        Value::List(xs.iter().map(|&x| Value::Integer(x)).collect())
+    }
+
+    fn set_of(xs: &[i32]) -> Value {
+        let mut s = Set::new();
+        for &x in xs {
+            s.insert(Value::Integer(x)).unwrap();
+        }
+        Value::Set(s)
+    }
+
+    #[test]
+    fn entries_keep_their_order() {
+        let mut d = Dict::new();
+        for key in &[3, 1, 2] {
+            d.insert(Value::Integer(*key), Value::None).unwrap();
+        }
+        d.insert(Value::Integer(1), Value::Bool(true)).unwrap();
+        assert_eq!(keys(&[Value::Dict(d.clone())]), Ok(ints(&[3, 1, 2])));
+        assert_eq!(d.remove(&Value::Integer(3)), Some(Value::None));
+        assert_eq!(d.get(&Value::Integer(1)), Some(&Value::Bool(true)));
+        assert_eq!(d.get(&Value::Integer(2)), Some(&Value::None));
+        assert_eq!(d.remove(&Value::Integer(3)), None);
+    }
+
+    #[test]
+    fn dicts_are_equal_in_any_order() {
+        assert_eq!(eval("{\"a\": 1, \"b\": 2} == {\"b\": 2, \"a\": 1}"), Ok(Value::Bool(true)));
+        assert_eq!(eval("{\"a\": 1} == {\"a\": 2}"), Ok(Value::Bool(false)));
+        assert_eq!(eval("set([1, 2]) == set([2, 1, 1])"), Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn dict_methods() {
+        assert_eq!(eval("{\"a\": 1}.get(\"a\")"), Ok(Value::Some(Box::new(Value::Integer(1)))));
+        assert_eq!(eval("{\"a\": 1}.get(\"b\")"), Ok(Value::None));
+        assert_eq!(eval("dict().insert(1, 2).insert(3, 4).values()"), Ok(ints(&[2, 4])));
+        assert_eq!(eval("{1: 2, 3: 4}.remove(1).keys()"), Ok(ints(&[3])));
+        assert_eq!(eval("{1: 2}.remove(5).keys()"), Ok(ints(&[1])));
+        assert_eq!(eval("[{1: 2}.has(1), {1: 2}.has(2)]"), Ok(Value::from(vec![true, false])));
+        assert_eq!(eval("{1: 2}.items()"), Ok(Value::List(vec![ints(&[1, 2])])));
+        assert_eq!(eval("dict([[1, 2], [3, 4]]).keys()"), Ok(ints(&[1, 3])));
+        // They give a new dict, and leave the old one alone
+        assert_eq!(eval("let d = {1: 2}\nd.insert(3, 4)\nd.remove(1)\nd.keys()"), Ok(ints(&[1])));
+    }
+
+    #[test]
+    fn empty_dicts_and_sets() {
+        assert_eq!(eval("dict().keys()"), Ok(ints(&[])));
+        assert_eq!(eval("dict().get(1)"), Ok(Value::None));
+        assert_eq!(eval("len(dict())"), Ok(Value::Integer(0)));
+        assert_eq!(eval("set().has(1)"), Ok(Value::Bool(false)));
+        assert_eq!(eval("set().union(set())"), Ok(set_of(&[])));
+    }
+
+    #[test]
+    fn set_methods() {
+        assert_eq!(eval("set([1, 2]).add(3).add(1)"), Ok(set_of(&[1, 2, 3])));
+        assert_eq!(eval("set([1, 2]).remove(1)"), Ok(set_of(&[2])));
+        assert_eq!(eval("set([1, 2]).union(set([2, 3]))"), Ok(set_of(&[1, 2, 3])));
+        assert_eq!(eval("set([1, 2]).intersection(set([2, 3]))"), Ok(set_of(&[2])));
+        assert_eq!(eval("set([1, 2]).difference(set([2, 3]))"), Ok(set_of(&[1])));
+        assert_eq!(eval("set(\"abca\").has(\"c\")"), Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn whole_floats_are_the_same_key_as_ints() {
+        assert_eq!(eval("{1: \"a\"}.get(1.0)"), Ok(Value::Some(Box::new(Value::from("a")))));
+        assert_eq!(eval("len(set([1, 1.0, 1.5]))"), Ok(Value::Integer(2)));
+    }
+
+    #[test]
+    fn functions_cant_be_keys() {
+        for source in &[
+            "{fn(x) x end: 1}",
+            "dict().insert(print, 1)",
+            "set([1]).add([fn(x) x end])",
+            "set([print])",
+            "dict([[Some(print), 1]])",
+        ] {
+            let err = eval(source).unwrap_err().to_string();
+            assert!(err.contains("can't be used as a dict key or set item"), "{}: {}", source, err);
+        }
+        // Looking one up is fine, it just isn't there
+        assert_eq!(eval("{1: 2}.has(print)"), Ok(Value::Bool(false)));
+    }
+
+    #[test]
+    fn wrong_args_are_errors() {
+        assert!(eval("dict([1, 2])").is_err());
+        assert!(eval("dict([[1, 2, 3]])").is_err());
+        assert!(get(&[Value::from(vec![1]), Value::Integer(0)]).is_err());
+        assert!(union(&[set_of(&[1]), ints(&[2])]).is_err());
+        assert!(eval("{1: 2}.insert(3)").is_err());
+        assert!(eval("set(1)").is_err());
+    }
+}
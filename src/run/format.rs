@@ -0,0 +1,280 @@
+//! format(template, args...), for lining values up in columns and rounding
+//! numbers. Placeholders look like Rust's and Python's: `{}` takes the next
+//! arg, `{1}` takes a given one, and a spec after a colon sets the layout,
+//! e.g. `{:>8}` to right align in 8 columns, or `{:.2}` for 2 decimal places.
+//! `{{` and `}}` are literal braces.
+
+use std::cell::RefCell;
+use std::iter::Peekable;
+use std::rc::Rc;
+use std::str::Chars;
+
+use crate::error::IrisError;
+use super::Value;
+use super::builtins::{expect_str, fmt, repr, reserve};
+use super::registry::Registry;
+use super::scope::Scope;
+
+pub fn register(registry: &mut Registry) {
+    registry.register_scoped_fn("format", format);
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+/// What goes after the colon in a placeholder:
+/// `[[fill]align][0][width][.precision][?]`
+struct Spec {
+    fill: char,
+    align: Option<Align>,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+    /// `?` shows the value with repr
+    debug: bool,
+}
+
+fn format_error(msg: &str) -> IrisError {
+    IrisError::runtime(format!("format(): {}", msg))
+}
+
+fn align_of(c: char) -> Option<Align> {
+    match c {
+        '<' => Some(Align::Left),
+        '>' => Some(Align::Right),
+        '^' => Some(Align::Center),
+        _ => None,
+    }
+}
+
+fn read_number(chars: &mut Peekable<Chars>) -> Option<usize> {
+    let mut digits = String::new();
+    while let Some(c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+        digits.push(*c);
+        chars.next();
+    }
+    digits.parse().ok()
+}
+
+fn parse_spec(s: &str) -> Result<Spec, IrisError> {
+    let mut spec = Spec { fill: ' ', align: None, zero: false, width: 0, precision: None, debug: false };
+    let mut chars = s.chars().peekable();
+
+    // A fill char is only there if it's followed by an alignment
+    let mut lookahead = s.chars();
+    if let (Some(fill), Some(align)) = (lookahead.next(), lookahead.next().and_then(align_of)) {
+        spec.fill = fill;
+        spec.align = Some(align);
+        chars.next();
+        chars.next();
+    } else if let Some(align) = chars.peek().and_then(|c| align_of(*c)) {
+        spec.align = Some(align);
+        chars.next();
+    }
+    if chars.peek() == Some(&'0') {
+        spec.zero = true;
+        chars.next();
+    }
+    spec.width = read_number(&mut chars).unwrap_or(0);
+    if chars.peek() == Some(&'.') {
+        chars.next();
+        spec.precision = Some(read_number(&mut chars)
+            .ok_or_else(|| format_error(&format!("expected a precision after '.' in {{:{}}}", s)))?);
+    }
+    if chars.peek() == Some(&'?') {
+        spec.debug = true;
+        chars.next();
+    }
+    if chars.next().is_some() {
+        return Err(format_error(&format!("invalid format spec {{:{}}}", s)));
+    }
+    Ok(spec)
+}
+
+/// Format one value according to a spec
+fn render(val: &Value, spec: &Spec) -> Result<String, IrisError> {
+    let body = match (val, spec.precision) {
+        _ if spec.debug => repr(val),
+        (Value::DoublePrecisionFloat(x), Some(p)) => format!("{:.*}", p, x),
+        (Value::Integer(x), Some(p)) => format!("{:.*}", p, *x as f64),
+        (Value::Str(s), Some(p)) => s.chars().take(p).collect(),
+        (_, Some(_)) => return Err(format_error(&format!("can't set the precision of {}", repr(val)))),
+        (_, None) => fmt(val),
+    };
+    let is_number = matches!(val, Value::Integer(_) | Value::DoublePrecisionFloat(_));
+    let len = body.chars().count();
+    if len >= spec.width {
+        return Ok(body);
+    }
+    let pad = spec.width - len;
+
+    // Zero padding goes between the sign and the digits
+    if spec.zero && is_number && spec.align.is_none() {
+        let (sign, digits) = match body.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None => ("", body.as_str()),
+        };
+        return Ok(format!("{}{}{}", sign, "0".repeat(pad), digits));
+    }
+
+    // Numbers line up on the right by default, everything else on the left
+    let align = spec.align.unwrap_or(if is_number { Align::Right } else { Align::Left });
+    let (before, after) = match align {
+        Align::Left => (0, pad),
+        Align::Right => (pad, 0),
+        Align::Center => (pad / 2, pad - pad / 2),
+    };
+    let fill = |n| spec.fill.to_string().repeat(n);
+    Ok(format!("{}{}{}", fill(before), body, fill(after)))
+}
+
+/// format(template, args...) fills in the template's placeholders with args
+pub fn format(scope: &Rc<RefCell<Scope>>, args: &[Value]) -> Result<Value, IrisError> {
+    let (template, values) = match args.split_first() {
+        Some((template, values)) => (expect_str("format", template)?, values),
+        None => return Err(format_error("expected a template string")),
+    };
+    let mut out = String::new();
+    let mut next_arg = 0;
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                out.push('{');
+            },
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                out.push('}');
+            },
+            '}' => return Err(format_error("unmatched } in template, use }} for a literal brace")),
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => placeholder.push(c),
+                        None => return Err(format_error("unclosed { in template")),
+                    }
+                }
+                let (index, spec) = match placeholder.find(':') {
+                    Some(idx) => (&placeholder[..idx], &placeholder[idx + 1..]),
+                    None => (placeholder.as_str(), ""),
+                };
+                let index = if index.is_empty() {
+                    next_arg += 1;
+                    next_arg - 1
+                } else {
+                    index.parse()
+                        .map_err(|_| format_error(&format!("invalid placeholder {{{}}}", placeholder)))?
+                };
+                let val = values.get(index).ok_or_else(|| format_error(&format!(
+                    "placeholder {} is out of range, got {} arg{}",
+                    index, values.len(), if values.len() == 1 { "" } else { "s" },
+                )))?;
+                let spec = parse_spec(spec)?;
+                // Padding and decimal places can ask for far more than the
+                // value itself
+                let padding = spec.width.max(spec.precision.unwrap_or(0)).checked_mul(spec.fill.len_utf8());
+                reserve(scope, "format", padding.and_then(|padding| padding.checked_add(out.len())))?;
+                out.push_str(&render(val, &spec)?);
+            },
+            c => out.push(c),
+        }
+    }
+    Ok(Value::Str(out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Interpreter;
+
+    fn format_str(template: &str, args: Vec<Value>) -> Result<Value, IrisError> {
+        let mut interpreter = Interpreter::new();
+        let mut all_args = vec![Value::from(template)];
+        all_args.extend(args);
+        interpreter.call_function("format", all_args)
+    }
+
+    fn formatted(template: &str, args: Vec<Value>) -> String {
+        match format_str(template, args) {
+            Ok(Value::Str(s)) => s,
+            res => panic!("{:?}", res),
+        }
+    }
+
+    fn error(template: &str, args: Vec<Value>) -> String {
+        format_str(template, args).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn specs_are_parsed() {
+        let spec = parse_spec("*^8.3?").unwrap();
+        assert!(spec.fill == '*' && spec.align == Some(Align::Center) && !spec.zero);
+        assert!(spec.width == 8 && spec.precision == Some(3) && spec.debug);
+        let spec = parse_spec("08").unwrap();
+        assert!(spec.fill == ' ' && spec.align.is_none() && spec.zero && spec.width == 8);
+        // A lone alignment char isn't taken as a fill
+        let spec = parse_spec(">5").unwrap();
+        assert!(spec.fill == ' ' && spec.align == Some(Align::Right) && spec.width == 5);
+        let spec = parse_spec("").unwrap();
+        assert!(spec.align.is_none() && spec.width == 0 && spec.precision.is_none() && !spec.debug);
+        assert!(parse_spec(".").is_err());
+        assert!(parse_spec("5x").is_err());
+        // There's no sign flag
+        assert!(error("{:+5}", vec![Value::from(1)]).contains("invalid format spec {:+5}"));
+    }
+
+    #[test]
+    fn placeholders_take_args_in_order_or_by_index() {
+        assert_eq!(formatted("{} and {}", vec![Value::from(1), Value::from("a")]), "1 and a");
+        assert_eq!(formatted("{1} {0} {1}", vec![Value::from(1), Value::from(2)]), "2 1 2");
+        assert_eq!(formatted("{:?} {}", vec![Value::from("a"), Value::from("b")]), "\"a\" b");
+        assert_eq!(formatted("{{{}}}", vec![Value::from(1)]), "{1}");
+    }
+
+    #[test]
+    fn unmatched_braces_are_errors() {
+        assert!(error("a } b", vec![]).contains("unmatched }"));
+        assert!(error("a { b", vec![]).contains("unclosed {"));
+        assert!(error("{x}", vec![Value::from(1)]).contains("invalid placeholder {x}"));
+    }
+
+    #[test]
+    fn placeholders_out_of_range_are_errors() {
+        assert!(error("{} {}", vec![Value::from(1)]).contains("placeholder 1 is out of range, got 1 arg"));
+        assert!(error("{3}", vec![Value::from(1), Value::from(2)]).contains("placeholder 3 is out of range, got 2 args"));
+        assert!(error("{}", vec![]).contains("got 0 args"));
+    }
+
+    #[test]
+    fn zero_padding_goes_after_the_sign() {
+        assert_eq!(formatted("{:05}", vec![Value::from(-42)]), "-0042");
+        assert_eq!(formatted("{:05}", vec![Value::from(42)]), "00042");
+        assert_eq!(formatted("{:08.2}", vec![Value::from(-1.256)]), "-0001.26");
+        assert_eq!(formatted("{:02}", vec![Value::from(-123)]), "-123");
+        // Only numbers are zero padded
+        assert_eq!(formatted("{:05}", vec![Value::from("ab")]), "ab   ");
+    }
+
+    #[test]
+    fn centre_alignment_puts_any_odd_padding_after() {
+        assert_eq!(formatted("{:^6}", vec![Value::from("ab")]), "  ab  ");
+        assert_eq!(formatted("{:^5}", vec![Value::from("ab")]), " ab  ");
+        assert_eq!(formatted("{:*^7}", vec![Value::from(1)]), "***1***");
+        assert_eq!(formatted("{:^1}", vec![Value::from("abc")]), "abc");
+    }
+
+    #[test]
+    fn precision() {
+        assert_eq!(formatted("{:.2}", vec![Value::from(2.0 / 3.0)]), "0.67");
+        assert_eq!(formatted("{:.1}", vec![Value::from(3)]), "3.0");
+        assert_eq!(formatted("{:.2}", vec![Value::from("hello")]), "he");
+        assert!(error("{:.2}", vec![Value::from(true)]).contains("can't set the precision"));
+    }
+}
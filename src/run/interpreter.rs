@@ -0,0 +1,154 @@
+use std::path::Path;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use crate::ast::AstNode;
+use crate::error::IrisError;
+use crate::parse;
+use super::capabilities::Capabilities;
+use super::limits::Limits;
+use super::scope::Scope;
+use super::typing::TypeDefinition;
+use super::{Hook, Value, finish, resolve_call, stmt};
+
+/// An Iris interpreter, for running programs and embedding Iris in other programs.
+/// Global variables, functions and methods persist from one evaluation to the
+/// next, so a host can load a script and then call into it.
+pub struct Interpreter {
+    global_scope: Rc<RefCell<Scope>>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        Interpreter::new()
+    }
+}
+
+impl Interpreter {
+    /// Create an interpreter with the default resource limits
+    pub fn new() -> Interpreter {
+        Interpreter::with_limits(Limits::default())
+    }
+
+    /// Create an interpreter with the given resource limits. The step and memory
+    /// budgets apply to each call into the interpreter separately.
+    pub fn with_limits(limits: Limits) -> Interpreter {
+        let global_scope = Rc::new(RefCell::new(Scope::new(String::from("<top level>"))));
+        global_scope.borrow().runtime().borrow_mut().limits = limits;
+        Interpreter { global_scope }
+    }
+
+    /// Grant the program access to files, the environment or stdin. By default
+    /// it has none.
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.global_scope.borrow().runtime().borrow_mut().capabilities = capabilities;
+    }
+
+    /// Set the arguments scripts see from args()
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.global_scope.borrow().runtime().borrow_mut().args = args;
+    }
+
+    /// Parse and run some source code, returning the value of the last statement
+    pub fn eval_str(&mut self, source: &str) -> Result<Value, IrisError> {
+        let ast_list = parse::parse_str(source)?;
+        self.run(source, &ast_list)
+    }
+
+    /// Parse and run a source file, returning the value of the last statement
+    pub fn eval_file<P: AsRef<Path>>(&mut self, path: P) -> Result<Value, IrisError> {
+        let source = parse::read(path)?;
+        self.eval_str(&source)
+    }
+
+    /// Read a global variable or function
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.global_scope.borrow().get_var(name)
+    }
+
+    /// Set a global variable, declaring it if it doesn't exist yet
+    pub fn set_global(&mut self, name: &str, val: Value) {
+        let mut s = self.global_scope.borrow_mut();
+        let res = if s.var_is_set(name) {
+            s.set_var(name, val)
+        } else {
+            s.declare_var(name, val)
+        };
+        res.expect("global variable is either set or declared");
+    }
+
+    /// Call a global function or builtin by name
+    pub fn call_function(&mut self, name: &str, args: Vec<Value>) -> Result<Value, IrisError> {
+        self.start();
+        let res = resolve_call(name, args, &self.global_scope).and_then(finish);
+        self.traced(res)
+    }
+
+    /// Register a Rust function that scripts can call as a global function.
+    /// Functions and variables of the same name defined in Iris shadow it.
+    pub fn register_fn<F>(&mut self, name: &str, func: F)
+    where F: Fn(&[Value]) -> Result<Value, IrisError> + 'static {
+        let runtime = self.global_scope.borrow().runtime();
+        runtime.borrow_mut().registry.register_fn(name, func);
+    }
+
+    /// Register a Rust function as a method for the given type, so scripts can
+    /// call it like `x.name()`
+    pub fn register_method<F>(&mut self, name: &str, for_type: TypeDefinition, func: F)
+    where F: Fn(&[Value]) -> Result<Value, IrisError> + 'static {
+        let runtime = self.global_scope.borrow().runtime();
+        runtime.borrow_mut().registry.register_method(name, for_type, func);
+    }
+
+    /// Where the last statement run starts, as an offset into its source.
+    /// After an error, that's the statement that went wrong.
+    pub fn position(&self) -> usize {
+        self.global_scope.borrow().runtime().borrow().position
+    }
+
+    /// Where the statement that made the last run fail starts, if it failed
+    pub(crate) fn failed_at(&self) -> Option<usize> {
+        self.global_scope.borrow().runtime().borrow().failed_at
+    }
+
+    /// Have the hook told about each statement before it runs
+    pub(crate) fn set_hook(&mut self, hook: Rc<RefCell<dyn Hook>>) {
+        self.global_scope.borrow().runtime().borrow_mut().hook = Some(hook);
+    }
+
+    /// Run the statements parsed from some source code. The source is for
+    /// turning positions into line numbers.
+    pub(crate) fn run(&mut self, source: &str, ast_list: &[AstNode]) -> Result<Value, IrisError> {
+        self.start();
+        let line_starts = std::iter::once(0).chain(source.match_indices('\n').map(|(i, _)| i + 1)).collect();
+        self.global_scope.borrow().runtime().borrow_mut().line_starts = line_starts;
+        let res = ast_list.iter().try_fold(Value::None, |_, ast_node| stmt(ast_node, &self.global_scope));
+        self.traced(res)
+    }
+
+    /// Add a traceback to a runtime error that came out of any calls or loops
+    fn traced(&self, res: Result<Value, IrisError>) -> Result<Value, IrisError> {
+        if res.is_err() {
+            let runtime = self.global_scope.borrow().runtime();
+            let position = runtime.borrow().error_position();
+            runtime.borrow_mut().failed_at = Some(position);
+        }
+        match res {
+            Err(IrisError::Runtime(msg)) => {
+                let traceback = self.global_scope.borrow().runtime().borrow_mut().take_traceback();
+                match traceback {
+                    Some(frames) => Err(IrisError::Runtime(format!(
+                        "{}\ntraceback (most recent call last):\n  {}", msg, frames.join("\n  "),
+                    ))),
+                    None => Err(IrisError::Runtime(msg)),
+                }
+            },
+            res => res,
+        }
+    }
+
+    /// Reset the step and memory budgets before running anything
+    fn start(&self) {
+        self.global_scope.borrow().runtime().borrow_mut().start();
+    }
+}
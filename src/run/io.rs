@@ -0,0 +1,146 @@
+//! Files, input, and the outside world. Access to the filesystem, the
+//! environment and stdin is gated by the interpreter's Capabilities.
+
+use std::fs;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use crate::error::IrisError;
+use super::Value;
+use super::builtins::{expect_args, expect_int, expect_str};
+use super::capabilities::Capabilities;
+use super::iter;
+use super::registry::Registry;
+use super::scope::Scope;
+
+type ScopeRef = Rc<RefCell<Scope>>;
+
+pub fn register(registry: &mut Registry) {
+    registry.register_scoped_fn("read_file", read_file);
+    registry.register_scoped_fn("write_file", write_file);
+    registry.register_scoped_fn("read_lines", read_lines);
+    registry.register_scoped_fn("stdin_lines", stdin_lines);
+    registry.register_scoped_fn("list_dir", list_dir);
+    registry.register_scoped_fn("exists", exists);
+    registry.register_scoped_fn("args", args);
+    registry.register_scoped_fn("env", env);
+    registry.register_fn("exit", exit);
+}
+
+fn capabilities(scope: &ScopeRef) -> Capabilities {
+    scope.borrow().runtime().borrow().capabilities.clone()
+}
+
+fn io_error(name: &str, path: &str, e: std::io::Error) -> IrisError {
+    IrisError::runtime(format!("{}() failed for {:?}: {}", name, path, e))
+}
+
+/// read_file(path) gives the whole file as a string
+pub fn read_file(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("read_file", args, 1)?;
+    let path = expect_str("read_file", &args[0])?;
+    let resolved = capabilities(scope).check_read(path)?;
+    let contents = fs::read_to_string(resolved).map_err(|e| io_error("read_file", path, e))?;
+    Ok(Value::Str(contents))
+}
+
+/// write_file(path, s) replaces the file's contents with s, creating it if needed
+pub fn write_file(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("write_file", args, 2)?;
+    let path = expect_str("write_file", &args[0])?;
+    let contents = expect_str("write_file", &args[1])?;
+    let resolved = capabilities(scope).check_write(path)?;
+    create(&resolved)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(|e| io_error("write_file", path, e))?;
+    Ok(Value::None)
+}
+
+/// Create or truncate a file for writing. The path has been checked, but a
+/// symlink could have been put there since, so don't follow one.
+fn create(path: &Path) -> std::io::Result<fs::File> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.custom_flags(libc::O_NOFOLLOW);
+    }
+    options.open(path)
+}
+
+/// read_lines(path) gives a list of the file's lines, without line endings
+pub fn read_lines(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("read_lines", args, 1)?;
+    let path = expect_str("read_lines", &args[0])?;
+    let resolved = capabilities(scope).check_read(path)?;
+    let contents = fs::read_to_string(resolved).map_err(|e| io_error("read_lines", path, e))?;
+    Ok(Value::List(contents.lines().map(|line| Value::Str(line.to_string())).collect()))
+}
+
+/// stdin_lines() gives an Iterator over lines of standard input, read as needed
+pub fn stdin_lines(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("stdin_lines", args, 0)?;
+    if !capabilities(scope).stdin {
+        return Err(IrisError::runtime("no access to stdin"));
+    }
+    let lines = std::io::stdin().lock().lines().map(|line| {
+        line.map(Value::Str)
+            .map_err(|e| IrisError::runtime(format!("stdin_lines() failed: {}", e)))
+    });
+    Ok(iter::wrap(lines))
+}
+
+/// list_dir(path) gives the sorted names of the entries in a directory
+pub fn list_dir(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("list_dir", args, 1)?;
+    let path = expect_str("list_dir", &args[0])?;
+    let resolved = capabilities(scope).check_read(path)?;
+    let mut names = vec![];
+    for entry in fs::read_dir(resolved).map_err(|e| io_error("list_dir", path, e))? {
+        let entry = entry.map_err(|e| io_error("list_dir", path, e))?;
+        names.push(entry.file_name().to_string_lossy().into_owned());
+    }
+    names.sort();
+    Ok(Value::List(names.into_iter().map(Value::Str).collect()))
+}
+
+/// exists(path) checks if there's a file or directory at path
+pub fn exists(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("exists", args, 1)?;
+    let path = expect_str("exists", &args[0])?;
+    let resolved = capabilities(scope).check_read(path)?;
+    Ok(Value::Bool(resolved.exists()))
+}
+
+/// args() gives the arguments passed to the script, as a list of strings
+pub fn args(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("args", args, 0)?;
+    let runtime = scope.borrow().runtime();
+    let script_args = runtime.borrow().args.clone();
+    Ok(Value::List(script_args.into_iter().map(Value::Str).collect()))
+}
+
+/// env(name) gives Some(value) of an environment variable, or None if it isn't set
+pub fn env(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("env", args, 1)?;
+    let name = expect_str("env", &args[0])?;
+    if !capabilities(scope).env {
+        return Err(IrisError::runtime(format!(
+            "no access to environment variable {:?}. Use --allow-env to allow it.", name,
+        )));
+    }
+    Ok(match std::env::var(name) {
+        Ok(val) => Value::Some(Box::new(Value::Str(val))),
+        Err(_) => Value::None,
+    })
+}
+
+/// exit(code) stops the program. It's handed back to the host as an error,
+/// so an embedded script can't take the host process down with it.
+pub fn exit(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("exit", args, 1)?;
+    Err(IrisError::Exit(expect_int("exit", &args[0])?))
+}
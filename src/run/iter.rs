@@ -0,0 +1,437 @@
+//! Iteration from Rust, and lazy iterators for Iris. `xs.iter()` wraps any
+//! iterable in an Iterator value, and map, filter, take etc. on an Iterator
+//! give back a new Iterator instead of building a list, so pipelines only do
+//! the work that's needed and can run over endless sources like count().
+
+use std::convert::TryFrom;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use crate::error::IrisError;
+use super::builtins::{expect_args, expect_args_between, expect_int};
+use super::registry::{NativeFunction, NativeObject, Registry};
+use super::runtime::Runtime;
+use super::scope::Scope;
+use super::typing::{PrimitiveType, TypeDefinition};
+use super::{Value, call_in, finish, resolve_method_call, test_bool_val};
+
+/// The type name of lazy iterators, for declaring methods with `for Iterator`
+pub const ITERATOR_TYPE: &str = "Iterator";
+
+type ScopeRef = Rc<RefCell<Scope>>;
+type Items = Box<dyn Iterator<Item = Result<Value, IrisError>>>;
+
+/// Iterates over an Iris value from Rust. Taking an item counts as a step,
+/// so loops in Rust over endless Iterators still stop at the step and memory
+/// budgets.
+pub struct ValueIter {
+    source: Source,
+    /// Not set for the Iter methods of builtin types, which don't know the
+    /// scope they're called from. Whatever takes items from them does.
+    runtime: Option<Rc<RefCell<Runtime>>>,
+}
+
+/// Lists, strings, dicts and Iterators are walked natively; anything else
+/// goes through the Iter protocol: Iter(x) gives a function that returns
+/// Some(item) for each item, then None.
+enum Source {
+    List(std::vec::IntoIter<Value>),
+    /// The chars of a string, from the given byte offset
+    Str(String, usize),
+    Next {
+        next: Value,
+        scope: ScopeRef,
+        done: bool,
+    },
+    /// An Iterator value. It's shared, so taking items from it here also
+    /// takes them from any other reference to it.
+    Shared(NativeObject),
+    /// A source or adaptor implemented in Rust
+    Lazy(Items),
+}
+
+pub fn register(registry: &mut Registry) {
+    registry.register_scoped_fn("iter", iter);
+    registry.register_scoped_fn("collect", collect_fn);
+    registry.register_scoped_fn("next", next);
+    registry.register_fn("range", range);
+    registry.register_fn("count", count);
+    registry.register_scoped_fn("take", take);
+    registry.register_scoped_fn("skip", skip);
+    registry.register_scoped_fn("take_while", take_while);
+    registry.register_scoped_fn("chain", chain);
+    // The builtin types also follow the Iter protocol, for code that calls Iter directly
+    let iterable = [
+        TypeDefinition::PrimitiveType(PrimitiveType::List),
+        TypeDefinition::PrimitiveType(PrimitiveType::Str),
+        TypeDefinition::PrimitiveType(PrimitiveType::Dict),
+        TypeDefinition::PrimitiveType(PrimitiveType::Set),
+        TypeDefinition::Native(ITERATOR_TYPE.to_string()),
+    ];
+    for typ in iterable.iter() {
+        registry.register_method("Iter", typ.clone(), iter_method);
+    }
+}
+
+fn is_native_iterable(val: &Value) -> bool {
+    matches!(val, Value::List(_) | Value::Str(_) | Value::Dict(_) | Value::Set(_)) || is_iterator(val)
+}
+
+/// Iterate over a builtin iterable type, without going through Iter
+fn native_iter(val: Value) -> Source {
+    match val {
+        Value::List(xs) => Source::List(xs.into_iter()),
+        Value::Str(s) => Source::Str(s, 0),
+        // Iterating over a dict gives its keys
+        Value::Dict(d) => Source::List(d.keys().cloned().collect::<Vec<Value>>().into_iter()),
+        Value::Set(s) => Source::List(s.iter().cloned().collect::<Vec<Value>>().into_iter()),
+        Value::Native(obj) if obj.type_name == ITERATOR_TYPE => Source::Shared(obj),
+        _ => unreachable!("{:?} isn't natively iterable", val),
+    }
+}
+
+/// Iter(x) for builtin types gives a function returning each item in turn
+fn iter_method(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("Iter", args, 1)?;
+    let it = wrap_iter(ValueIter { source: native_iter(args[0].clone()), runtime: None });
+    let obj = expect_iterator("Iter", &it)?.clone();
+    Ok(Value::NativeFunction(NativeFunction::new("next", move |_| next_item(&obj))))
+}
+
+/// Start iterating over the given value
+pub fn iterate(val: Value, scope: &ScopeRef) -> Result<ValueIter, IrisError> {
+    let source = if is_native_iterable(&val) {
+        native_iter(val)
+    } else {
+        let next = finish(resolve_method_call("Iter", vec![val], scope)?)?;
+        Source::Next { next, scope: Rc::clone(scope), done: false }
+    };
+    Ok(ValueIter { source, runtime: Some(scope.borrow().runtime()) })
+}
+
+/// Collect all the items of an iterable value into a Vec
+pub fn collect(val: Value, scope: &ScopeRef) -> Result<Vec<Value>, IrisError> {
+    iterate(val, scope)?.collect()
+}
+
+pub fn is_iterator(val: &Value) -> bool {
+    matches!(val, Value::Native(obj) if obj.type_name == ITERATOR_TYPE)
+}
+
+/// Make an Iterator value out of a Rust iterator
+pub(crate) fn wrap<I>(items: I) -> Value
+where I: Iterator<Item = Result<Value, IrisError>> + 'static {
+    wrap_iter(ValueIter { source: Source::Lazy(Box::new(items)), runtime: None })
+}
+
+fn wrap_iter(it: ValueIter) -> Value {
+    Value::Native(NativeObject::new(ITERATOR_TYPE, RefCell::new(it)))
+}
+
+fn expect_iterator<'a>(name: &str, val: &'a Value) -> Result<&'a NativeObject, IrisError> {
+    match val {
+        Value::Native(obj) if obj.type_name == ITERATOR_TYPE => Ok(obj),
+        _ => Err(IrisError::runtime(format!("{}() expected an Iterator, got {:?}", name, val))),
+    }
+}
+
+/// Take the next item from a shared Iterator, as Some(item) or None
+fn next_item(obj: &NativeObject) -> Result<Value, IrisError> {
+    let cell = obj.downcast_ref::<RefCell<ValueIter>>()
+        .expect("Iterator values always hold a ValueIter");
+    let mut it = cell.try_borrow_mut()
+        .map_err(|_| IrisError::runtime("an Iterator can't be used while it's producing an item"))?;
+    match it.next() {
+        Some(item) => Ok(Value::Some(Box::new(item?))),
+        None => Ok(Value::None),
+    }
+}
+
+impl Iterator for ValueIter {
+    type Item = Result<Value, IrisError>;
+
+    fn next(&mut self) -> Option<Result<Value, IrisError>> {
+        if let Some(runtime) = &self.runtime {
+            if let Err(e) = runtime.borrow_mut().step() {
+                return Some(Err(e));
+            }
+        }
+        match &mut self.source {
+            Source::List(xs) => xs.next().map(Ok),
+            Source::Str(s, pos) => {
+                let c = s[*pos..].chars().next()?;
+                *pos += c.len_utf8();
+                Some(Ok(Value::Str(c.to_string())))
+            },
+            Source::Next { next, scope, done } => {
+                if *done {
+                    return None;
+                }
+                let item = match call_in(scope, next, vec![]) {
+                    Ok(Value::Some(item)) => return Some(Ok(*item)),
+                    Ok(Value::None) => None,
+                    Ok(other) => Some(Err(IrisError::runtime(format!(
+                        "Iter functions must return Some(item) or None, got {:?}", other,
+                    )))),
+                    Err(e) => Some(Err(e)),
+                };
+                *done = true;
+                item
+            },
+            Source::Shared(obj) => match next_item(obj) {
+                Ok(Value::Some(item)) => Some(Ok(*item)),
+                Ok(_) => None,
+                Err(e) => Some(Err(e)),
+            },
+            Source::Lazy(items) => items.next(),
+        }
+    }
+}
+
+/// iter(xs) wraps anything iterable in a lazy Iterator
+pub fn iter(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("iter", args, 1)?;
+    if is_iterator(&args[0]) {
+        return Ok(args[0].clone());
+    }
+    Ok(wrap_iter(iterate(args[0].clone(), scope)?))
+}
+
+/// collect(it) runs an Iterator to the end, giving a list of its items
+pub fn collect_fn(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("collect", args, 1)?;
+    Ok(Value::List(collect(args[0].clone(), scope)?))
+}
+
+/// next(it) takes one item from an Iterator, as Some(item) or None at the end
+pub fn next(_scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("next", args, 1)?;
+    next_item(expect_iterator("next", &args[0])?)
+}
+
+/// Counts from start by step, stopping before end if there is one
+struct Range {
+    cur: i64,
+    end: Option<i64>,
+    step: i64,
+}
+
+impl Iterator for Range {
+    type Item = Result<Value, IrisError>;
+
+    fn next(&mut self) -> Option<Result<Value, IrisError>> {
+        let done = match self.end {
+            Some(end) if self.step > 0 => self.cur >= end,
+            Some(end) => self.cur <= end,
+            None => false,
+        };
+        // Counting forever stops once we run out of ints
+        let x = i32::try_from(self.cur).ok().filter(|_| !done)?;
+        self.cur += self.step;
+        Some(Ok(Value::Integer(x)))
+    }
+}
+
+/// range(end), range(start, end) or range(start, end, step) counts up to,
+/// but not including, end. A negative step counts down.
+pub fn range(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args_between("range", args, 1, 3)?;
+    let ints = args.iter()
+        .map(|arg| expect_int("range", arg).map(i64::from))
+        .collect::<Result<Vec<i64>, IrisError>>()?;
+    let (start, end, step) = match ints[..] {
+        [end] => (0, end, 1),
+        [start, end] => (start, end, 1),
+        [start, end, step] => (start, end, step),
+        _ => unreachable!(),
+    };
+    if step == 0 {
+        return Err(IrisError::runtime("range() step can't be 0"));
+    }
+    Ok(wrap(Range { cur: start, end: Some(end), step }))
+}
+
+/// count(start, step) counts up forever, from 0 by 1 if left off
+pub fn count(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args_between("count", args, 0, 2)?;
+    let start = args.first().map_or(Ok(0), |x| expect_int("count", x))?;
+    let step = args.get(1).map_or(Ok(1), |x| expect_int("count", x))?;
+    Ok(wrap(Range { cur: start.into(), end: None, step: step.into() }))
+}
+
+/// repeat(x) gives x forever
+pub fn repeat(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("repeat", args, 1)?;
+    Ok(wrap(std::iter::repeat(args[0].clone()).map(Ok)))
+}
+
+/// Lazy map(it, f)
+pub fn map(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("map", args, 2)?;
+    let (scope, f) = (Rc::clone(scope), args[1].clone());
+    let items = iterate(args[0].clone(), &scope)?;
+    Ok(wrap(items.map(move |x| call_in(&scope, &f, vec![x?]))))
+}
+
+/// Lazy filter(it, f)
+pub fn filter(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("filter", args, 2)?;
+    let (scope, f) = (Rc::clone(scope), args[1].clone());
+    let items = iterate(args[0].clone(), &scope)?;
+    Ok(wrap(items.filter_map(move |x| {
+        let keep = x.clone().and_then(|x| test_bool_val(call_in(&scope, &f, vec![x])?));
+        match keep {
+            Ok(true) => Some(x),
+            Ok(false) => None,
+            Err(e) => Some(Err(e)),
+        }
+    })))
+}
+
+/// Lazy zip(it, ys)
+pub fn zip(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("zip", args, 2)?;
+    let xs = iterate(args[0].clone(), scope)?;
+    let ys = iterate(args[1].clone(), scope)?;
+    Ok(wrap(xs.zip(ys).map(|(x, y)| Ok(Value::List(vec![x?, y?])))))
+}
+
+/// Lazy enumerate(it)
+pub fn enumerate(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("enumerate", args, 1)?;
+    let items = iterate(args[0].clone(), scope)?;
+    Ok(wrap(items.enumerate().map(|(i, x)| Ok(Value::List(vec![Value::Integer(i as i32), x?])))))
+}
+
+fn expect_count(name: &str, val: &Value) -> Result<usize, IrisError> {
+    let n = expect_int(name, val)?;
+    usize::try_from(n)
+        .map_err(|_| IrisError::runtime(format!("{}() count can't be negative, got {}", name, n)))
+}
+
+/// take(xs, n) gives an Iterator of the first n items
+pub fn take(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("take", args, 2)?;
+    let n = expect_count("take", &args[1])?;
+    Ok(wrap(iterate(args[0].clone(), scope)?.take(n)))
+}
+
+/// skip(xs, n) gives an Iterator of everything after the first n items
+pub fn skip(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("skip", args, 2)?;
+    let n = expect_count("skip", &args[1])?;
+    Ok(wrap(iterate(args[0].clone(), scope)?.skip(n)))
+}
+
+/// take_while(xs, f) gives an Iterator of items up until f is false
+pub fn take_while(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("take_while", args, 2)?;
+    let (scope, f) = (Rc::clone(scope), args[1].clone());
+    let items = iterate(args[0].clone(), &scope)?;
+    Ok(wrap(items.map_while(move |x| {
+        let keep = x.clone().and_then(|x| test_bool_val(call_in(&scope, &f, vec![x])?));
+        match keep {
+            Ok(true) => Some(x),
+            Ok(false) => None,
+            Err(e) => Some(Err(e)),
+        }
+    })))
+}
+
+/// chain(xs, ys) gives an Iterator of the items of xs, then those of ys
+pub fn chain(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("chain", args, 2)?;
+    let xs = iterate(args[0].clone(), scope)?;
+    let ys = iterate(args[1].clone(), scope)?;
+    Ok(wrap(xs.chain(ys)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Interpreter, Limits};
+
+    fn eval(source: &str) -> Result<Value, IrisError> {
+        Interpreter::new().eval_str(source)
+    }
+
+    fn ints(xs: &[i32]) -> Value {
+        Value::List(xs.iter().map(|&x| Value::Integer(x)).collect())
+    }
+
+    #[test]
+    fn adaptors_chain_into_iterators() {
+        let source = "[1, 2, 3, 4, 5].iter().map(fn(x) x * 10 end).filter(fn(x) x > 10 end).take(2).collect()";
+        assert_eq!(eval(source), Ok(ints(&[20, 30])));
+        assert_eq!(eval("repr([1].iter().map(fn(x) x end))"), Ok(Value::from("<Iterator>")));
+        assert_eq!(eval("[1, 2, 3].iter().skip(1).collect()"), Ok(ints(&[2, 3])));
+        assert_eq!(eval("[1, 2, 3, 1].iter().take_while(fn(x) x < 3 end).collect()"), Ok(ints(&[1, 2])));
+        assert_eq!(eval("[1].iter().chain([2, 3]).collect()"), Ok(ints(&[1, 2, 3])));
+        assert_eq!(eval("enumerate(iter(\"ab\")).collect()[1][0]"), Ok(Value::Integer(1)));
+        assert_eq!(eval("zip(count(), \"ab\").collect()[1][0]"), Ok(Value::Integer(1)));
+    }
+
+    #[test]
+    fn only_the_items_needed_are_worked_out() {
+        let source = "let calls = 0\nfn f(x)\n  calls = calls + 1\n  x\nend\n\
+            let firsts = count().map(f).take(3).collect()\ncalls";
+        assert_eq!(eval(source), Ok(Value::Integer(3)));
+        let source = "let calls = 0\nfn f(x)\n  calls = calls + 1\n  x\nend\n\
+            let it = [1, 2, 3].iter().map(f)\nlet first = next(it)\n[calls, first]";
+        assert_eq!(eval(source), Ok(Value::List(vec![Value::Integer(1), Value::Some(Box::new(Value::Integer(1)))])));
+    }
+
+    #[test]
+    fn sources() {
+        assert_eq!(eval("range(3).collect()"), Ok(ints(&[0, 1, 2])));
+        assert_eq!(eval("range(2, 5).collect()"), Ok(ints(&[2, 3, 4])));
+        assert_eq!(eval("range(5, 0, -2).collect()"), Ok(ints(&[5, 3, 1])));
+        assert_eq!(eval("range(3, 3).collect()"), Ok(ints(&[])));
+        assert_eq!(eval("range(3, 0).collect()"), Ok(ints(&[])));
+        assert_eq!(eval("count(10, 5).take(3).collect()"), Ok(ints(&[10, 15, 20])));
+        assert_eq!(eval("repeat(7).take(2).collect()"), Ok(ints(&[7, 7])));
+        // Counting forever stops at the last int
+        assert_eq!(eval("count(2147483646).collect()"), Ok(ints(&[2147483646, 2147483647])));
+    }
+
+    #[test]
+    fn iterators_are_used_up() {
+        let source = "let it = iter([1, 2, 3])\nnext(it)\nlet rest = collect(it)\n[rest, collect(it)]";
+        assert_eq!(eval(source), Ok(Value::List(vec![ints(&[2, 3]), ints(&[])])));
+        assert_eq!(eval("let it = iter(list())\nnext(it)"), Ok(Value::None));
+        // iter() of an Iterator is the same Iterator
+        assert_eq!(eval("let it = range(3)\nnext(iter(it))\nit.collect()"), Ok(ints(&[1, 2])));
+    }
+
+    #[test]
+    fn endless_iterators_stop_at_the_step_limit() {
+        let limits = Limits { max_steps: Some(10000), ..Limits::default() };
+        let res = Interpreter::with_limits(limits).eval_str("count().collect()");
+        assert_eq!(res, Err(IrisError::StepLimit(10000)));
+    }
+
+    #[test]
+    fn errors_come_out_when_the_item_is_taken() {
+        let source = "let it = [1, 0].iter().map(fn(x) 1 / x end)\nnext(it)";
+        assert_eq!(eval(source), Ok(Value::Some(Box::new(Value::Integer(1)))));
+        assert!(eval("[1, 0].iter().map(fn(x) 1 / x end).collect()").is_err());
+        assert!(eval("[1].iter().filter(fn(x) 1 end).collect()").is_err());
+    }
+
+    #[test]
+    fn wrong_args_are_errors() {
+        for source in &[
+            "range()",
+            "range(1, 2, 0)",
+            "range(\"a\")",
+            "count(1, 2, 3)",
+            "take(count(), -1)",
+            "skip([1], \"a\")",
+            "iter(1)",
+            "next([1])",
+            "chain([1], 2).collect()",
+        ] {
+            assert!(eval(source).is_err(), "{} should be an error", source);
+        }
+    }
+}
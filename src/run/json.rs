@@ -0,0 +1,406 @@
+//! JSON parsing and serializing. Objects become dicts, arrays become lists
+//! and null becomes None. Numbers become ints when they're whole and fit,
+//! and floats otherwise.
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::error::IrisError;
+use super::Value;
+use super::builtins::{expect_args, expect_args_between, expect_str};
+use super::dict::Dict;
+use super::registry::Registry;
+use super::test_bool_val;
+
+/// How deeply arrays and objects can be nested, so that hostile input can't
+/// run us out of stack
+const MAX_DEPTH: usize = 512;
+
+pub fn register(registry: &mut Registry) {
+    registry.register_fn("json_parse", json_parse);
+    registry.register_fn("json_stringify", json_stringify);
+}
+
+/// json_parse(s) turns a JSON document into Iris values
+pub fn json_parse(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("json_parse", args, 1)?;
+    let source = expect_str("json_parse", &args[0])?;
+    let mut parser = Parser { chars: source.chars().peekable(), line: 1, col: 1 };
+    parser.skip_whitespace();
+    let val = parser.value(0)?;
+    parser.skip_whitespace();
+    if parser.chars.peek().is_some() {
+        return Err(parser.error("expected the end of the document"));
+    }
+    Ok(val)
+}
+
+/// json_stringify(value, pretty) turns Iris values into JSON. Pretty output
+/// is indented over multiple lines; it's compact if pretty is left off.
+pub fn json_stringify(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args_between("json_stringify", args, 1, 2)?;
+    let pretty = match args.get(1) {
+        Some(val) => test_bool_val(val.clone())?,
+        None => false,
+    };
+    let mut out = String::new();
+    write_value(&mut out, &args[0], pretty, 0)?;
+    Ok(Value::Str(out))
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    col: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, msg: &str) -> IrisError {
+        IrisError::runtime(format!("invalid JSON at {}:{}: {}", self.line, self.col, msg))
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ') | Some('\t') | Some('\n') | Some('\r') = self.chars.peek() {
+            self.next();
+        }
+    }
+
+    fn expect(&mut self, want: char) -> Result<(), IrisError> {
+        match self.next() {
+            Some(c) if c == want => Ok(()),
+            _ => Err(self.error(&format!("expected {:?}", want))),
+        }
+    }
+
+    fn keyword(&mut self, word: &str, val: Value) -> Result<Value, IrisError> {
+        for want in word.chars() {
+            if self.next() != Some(want) {
+                return Err(self.error(&format!("expected {}", word)));
+            }
+        }
+        Ok(val)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, IrisError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("nested too deeply"));
+        }
+        match self.chars.peek() {
+            Some('{') => self.object(depth),
+            Some('[') => self.array(depth),
+            Some('"') => Ok(Value::Str(self.string()?)),
+            Some('t') => self.keyword("true", Value::Bool(true)),
+            Some('f') => self.keyword("false", Value::Bool(false)),
+            Some('n') => self.keyword("null", Value::None),
+            Some(c) if *c == '-' || c.is_ascii_digit() => self.number(),
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Value, IrisError> {
+        self.expect('{')?;
+        let mut d = Dict::new();
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&'}') {
+            self.next();
+            return Ok(Value::Dict(d));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.chars.peek() != Some(&'"') {
+                return Err(self.error("expected a string key"));
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            self.skip_whitespace();
+            let val = self.value(depth + 1)?;
+            d.insert(Value::Str(key), val)?;
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(Value::Dict(d)),
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Value, IrisError> {
+        self.expect('[')?;
+        let mut xs = vec![];
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&']') {
+            self.next();
+            return Ok(Value::List(xs));
+        }
+        loop {
+            self.skip_whitespace();
+            xs.push(self.value(depth + 1)?);
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(Value::List(xs)),
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, IrisError> {
+        let mut n = 0;
+        for _ in 0..4 {
+            let digit = self.next()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| self.error("expected 4 hex digits after \\u"))?;
+            n = n * 16 + digit;
+        }
+        Ok(n)
+    }
+
+    fn string(&mut self) -> Result<String, IrisError> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(s),
+                Some('\\') => {
+                    let c = match self.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.unicode_escape()?,
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    s.push(c);
+                },
+                Some(c) if (c as u32) < 0x20 => {
+                    return Err(self.error("control characters must be escaped in strings"));
+                },
+                Some(c) => s.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    /// The char for a \uXXXX escape, which may be the first half of a
+    /// surrogate pair written as two escapes
+    fn unicode_escape(&mut self) -> Result<char, IrisError> {
+        let first = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&first) {
+            if self.next() != Some('\\') || self.next() != Some('u') {
+                return Err(self.error("expected the second half of a surrogate pair"));
+            }
+            let second = self.hex4()?;
+            if !(0xDC00..0xE000).contains(&second) {
+                return Err(self.error("invalid surrogate pair"));
+            }
+            0x10000 + ((first - 0xD800) << 10) + (second - 0xDC00)
+        } else {
+            first
+        };
+        std::char::from_u32(code).ok_or_else(|| self.error("invalid unicode escape"))
+    }
+
+    fn number(&mut self) -> Result<Value, IrisError> {
+        let mut s = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if c.is_ascii_digit() || "+-.eE".contains(c) {
+                s.push(c);
+                self.next();
+            } else {
+                break;
+            }
+        }
+        let is_float = s.contains(|c| ".eE".contains(c));
+        if !is_float {
+            if let Ok(x) = s.parse::<i32>() {
+                return Ok(Value::Integer(x));
+            }
+        }
+        s.parse::<f64>()
+            .ok()
+            .filter(|x| x.is_finite())
+            .map(Value::DoublePrecisionFloat)
+            .ok_or_else(|| self.error(&format!("invalid number {}", s)))
+    }
+}
+
+fn write_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Start a new line at the given depth, if we're pretty printing
+fn newline(out: &mut String, pretty: bool, depth: usize) {
+    if pretty {
+        out.push('\n');
+        out.push_str(&"  ".repeat(depth));
+    }
+}
+
+fn write_array<'a, I>(out: &mut String, items: I, pretty: bool, depth: usize) -> Result<(), IrisError>
+where I: Iterator<Item = &'a Value> {
+    out.push('[');
+    let mut empty = true;
+    for (i, x) in items.enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        newline(out, pretty, depth + 1);
+        write_value(out, x, pretty, depth + 1)?;
+        empty = false;
+    }
+    if !empty {
+        newline(out, pretty, depth);
+    }
+    out.push(']');
+    Ok(())
+}
+
+fn write_value(out: &mut String, val: &Value, pretty: bool, depth: usize) -> Result<(), IrisError> {
+    match val {
+        Value::None => out.push_str("null"),
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Integer(x) => out.push_str(&x.to_string()),
+        Value::DoublePrecisionFloat(x) if x.is_finite() => out.push_str(&format!("{:?}", x)),
+        Value::DoublePrecisionFloat(x) => {
+            return Err(IrisError::runtime(format!("can't convert {} to JSON", x)));
+        },
+        Value::Str(s) => write_str(out, s),
+        // Options are written as their value, or null
+        Value::Some(x) => write_value(out, x, pretty, depth)?,
+        Value::List(xs) => write_array(out, xs.iter(), pretty, depth)?,
+        // Sets are written as arrays, so they come back as lists
+        Value::Set(s) => write_array(out, s.iter(), pretty, depth)?,
+        Value::Dict(d) => {
+            out.push('{');
+            for (i, (k, v)) in d.iter().enumerate() {
+                let key = match k {
+                    Value::Str(key) => key,
+                    _ => return Err(IrisError::runtime(format!(
+                        "can't convert dict to JSON: keys must be strings, got {:?}", k,
+                    ))),
+                };
+                if i > 0 {
+                    out.push(',');
+                }
+                newline(out, pretty, depth + 1);
+                write_str(out, key);
+                out.push_str(if pretty { ": " } else { ":" });
+                write_value(out, v, pretty, depth + 1)?;
+            }
+            if !d.is_empty() {
+                newline(out, pretty, depth);
+            }
+            out.push('}');
+        },
+        Value::Function(_) | Value::NativeFunction(_) => {
+            return Err(IrisError::runtime("can't convert a function to JSON"));
+        },
+        Value::Native(obj) => {
+            return Err(IrisError::runtime(format!("can't convert a {} to JSON", obj.type_name)));
+        },
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<Value, IrisError> {
+        json_parse(&[Value::from(source)])
+    }
+
+    fn stringify(val: Value) -> Result<Value, IrisError> {
+        json_stringify(&[val])
+    }
+
+    #[test]
+    fn surrogate_pairs() {
+        assert_eq!(parse(r#""\ud83d\ude00""#), Ok(Value::from("\u{1F600}")));
+        assert_eq!(parse(r#""\u00e9""#), Ok(Value::from("\u{e9}")));
+        // Characters outside the BMP are written as they are
+        assert_eq!(stringify(Value::from("\u{1F600}")), Ok(Value::from("\"\u{1F600}\"")));
+    }
+
+    #[test]
+    fn broken_surrogates_are_errors() {
+        for source in &[r#""\ud83d""#, r#""\ud83dx""#, r#""\ud83dA""#, r#""\ude00""#] {
+            assert!(parse(source).is_err(), "{} should be an error", source);
+        }
+    }
+
+    #[test]
+    fn dict_keys_must_be_strings() {
+        let mut dict = Dict::new();
+        dict.insert(Value::Integer(1), Value::Bool(true)).unwrap();
+        let err = stringify(Value::Dict(dict)).unwrap_err();
+        assert!(err.to_string().contains("keys must be strings"), "{}", err);
+        assert!(parse(r#"{1: true}"#).is_err());
+    }
+
+    #[test]
+    fn nan_and_infinity_are_errors() {
+        assert!(stringify(Value::DoublePrecisionFloat(f64::NAN)).is_err());
+        assert!(stringify(Value::DoublePrecisionFloat(f64::INFINITY)).is_err());
+        assert!(stringify(Value::List(vec![Value::DoublePrecisionFloat(f64::NEG_INFINITY)])).is_err());
+        assert!(parse("NaN").is_err());
+        assert!(parse("1e999").is_err());
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse("[1, -2, 1.5, 1e2, 3000000000]"), Ok(Value::List(vec![
+            Value::Integer(1),
+            Value::Integer(-2),
+            Value::DoublePrecisionFloat(1.5),
+            Value::DoublePrecisionFloat(100.0),
+            Value::DoublePrecisionFloat(3e9),
+        ])));
+        assert_eq!(stringify(Value::DoublePrecisionFloat(2.0)), Ok(Value::from("2.0")));
+    }
+
+    #[test]
+    fn escapes_round_trip() {
+        let s = Value::from("quote \" backslash \\ newline \n tab \t bell \u{7}");
+        let json = stringify(s.clone()).unwrap();
+        assert_eq!(json, Value::from(r#""quote \" backslash \\ newline \n tab \t bell \u0007""#));
+        assert_eq!(json_parse(&[json]), Ok(s));
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let deep = "[".repeat(MAX_DEPTH * 2) + &"]".repeat(MAX_DEPTH * 2);
+        assert!(parse(&deep).is_err());
+        let ok = "[".repeat(MAX_DEPTH) + &"]".repeat(MAX_DEPTH);
+        assert!(parse(&ok).is_ok());
+    }
+}
@@ -0,0 +1,64 @@
+//! Resource limits, so that runaway or untrusted scripts fail with an error
+//! instead of taking the host process down with them.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Limits for a running program. Steps and memory are unlimited by default.
+#[derive(Clone, Debug)]
+pub struct Limits {
+    /// Maximum depth of nested function calls. Calls in tail position don't count.
+    pub max_depth: usize,
+    /// Maximum number of expressions evaluated over the whole run
+    pub max_steps: Option<u64>,
+    /// Maximum number of bytes allocated at any one time, on top of what was
+    /// in use when the program started. Only enforced when the host installs
+    /// `CountingAllocator` as its global allocator.
+    pub max_memory: Option<usize>,
+    /// Maximum depth of lists, dicts, sets and Somes inside each other. Values
+    /// nested any deeper could overflow the stack when they're compared,
+    /// printed, copied or freed.
+    pub max_nesting: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits { max_depth: 1000, max_steps: None, max_memory: None, max_nesting: 256 }
+    }
+}
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+/// A wrapper around the system allocator that keeps count of the bytes in use.
+/// The count is process-wide, so allocations made by other threads are
+/// included too.
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+            ALLOCATED.fetch_add(new_size, Ordering::Relaxed);
+        }
+        new_ptr
+    }
+}
+
+/// Bytes currently allocated through `CountingAllocator`
+pub fn allocated_bytes() -> usize {
+    ALLOCATED.load(Ordering::Relaxed)
+}
@@ -0,0 +1,393 @@
+//! Higher order list functions. These take Iris functions as arguments, and
+//! accept anything that can be iterated over: lists, or values with an Iter
+//! method. They give back lists, except map, filter, zip and enumerate on an
+//! Iterator, which stay lazy and give back another Iterator, and group_by,
+//! which gives a dict.
+
+use std::cmp::Ordering;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use crate::ast::Op;
+use crate::error::IrisError;
+use super::builtins::{expect_args, expect_args_between};
+use super::dict::Dict;
+use super::iter::{self, collect, is_iterator, iterate};
+use super::ops;
+use super::registry::Registry;
+use super::scope::Scope;
+use super::{Value, call_in, test_bool_val};
+
+pub fn register(registry: &mut Registry) {
+    registry.register_scoped_fn("map", map);
+    registry.register_scoped_fn("filter", filter);
+    registry.register_scoped_fn("reduce", reduce);
+    registry.register_scoped_fn("fold", fold);
+    registry.register_scoped_fn("zip", zip);
+    registry.register_scoped_fn("enumerate", enumerate);
+    registry.register_scoped_fn("any", any);
+    registry.register_scoped_fn("all", all);
+    registry.register_scoped_fn("sum", sum);
+    registry.register_scoped_fn("min", min);
+    registry.register_scoped_fn("max", max);
+    registry.register_scoped_fn("sort", sort);
+    registry.register_scoped_fn("sort_by", sort_by);
+    registry.register_scoped_fn("reverse", reverse);
+    registry.register_scoped_fn("flat_map", flat_map);
+    registry.register_scoped_fn("group_by", group_by);
+}
+
+type ScopeRef = Rc<RefCell<Scope>>;
+
+/// map(xs, f) applies f to each item
+pub fn map(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    if args.first().is_some_and(is_iterator) {
+        return iter::map(scope, args);
+    }
+    expect_args("map", args, 2)?;
+    let mut ret = vec![];
+    for x in iterate(args[0].clone(), scope)? {
+        ret.push(call_in(scope, &args[1], vec![x?])?);
+    }
+    Ok(Value::List(ret))
+}
+
+/// filter(xs, f) keeps the items that f returns true for
+pub fn filter(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    if args.first().is_some_and(is_iterator) {
+        return iter::filter(scope, args);
+    }
+    expect_args("filter", args, 2)?;
+    let mut ret = vec![];
+    for x in iterate(args[0].clone(), scope)? {
+        let x = x?;
+        if test_bool_val(call_in(scope, &args[1], vec![x.clone()])?)? {
+            ret.push(x);
+        }
+    }
+    Ok(Value::List(ret))
+}
+
+/// reduce(xs, f) combines the items with f(acc, x), starting from the first item
+pub fn reduce(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("reduce", args, 2)?;
+    let mut xs = iterate(args[0].clone(), scope)?;
+    let mut acc = match xs.next() {
+        Some(x) => x?,
+        None => return Err(IrisError::runtime("reduce() of an empty list")),
+    };
+    for x in xs {
+        acc = call_in(scope, &args[1], vec![acc, x?])?;
+    }
+    Ok(acc)
+}
+
+/// fold(xs, init, f) combines the items with f(acc, x), starting from init
+pub fn fold(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("fold", args, 3)?;
+    let mut acc = args[1].clone();
+    for x in iterate(args[0].clone(), scope)? {
+        acc = call_in(scope, &args[2], vec![acc, x?])?;
+    }
+    Ok(acc)
+}
+
+/// zip(xs, ys) pairs up items as [x, y], stopping at the end of the shorter one
+pub fn zip(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    if args.first().is_some_and(is_iterator) {
+        return iter::zip(scope, args);
+    }
+    expect_args("zip", args, 2)?;
+    let xs = iterate(args[0].clone(), scope)?;
+    let ys = iterate(args[1].clone(), scope)?;
+    let mut ret = vec![];
+    for (x, y) in xs.zip(ys) {
+        ret.push(Value::List(vec![x?, y?]));
+    }
+    Ok(Value::List(ret))
+}
+
+/// enumerate(xs) pairs each item with its index as [i, x]
+pub fn enumerate(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    if args.first().is_some_and(is_iterator) {
+        return iter::enumerate(scope, args);
+    }
+    expect_args("enumerate", args, 1)?;
+    let mut ret = vec![];
+    for (i, x) in iterate(args[0].clone(), scope)?.enumerate() {
+        ret.push(Value::List(vec![Value::Integer(i as i32), x?]));
+    }
+    Ok(Value::List(ret))
+}
+
+/// Test an item against an optional predicate; with no predicate the item
+/// itself must be a bool
+fn test_item(scope: &ScopeRef, pred: Option<&Value>, x: Value) -> Result<bool, IrisError> {
+    match pred {
+        Some(f) => test_bool_val(call_in(scope, f, vec![x])?),
+        None => test_bool_val(x),
+    }
+}
+
+/// any(xs, f) checks if f is true for any item. f can be left off for a list of bools.
+pub fn any(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args_between("any", args, 1, 2)?;
+    for x in iterate(args[0].clone(), scope)? {
+        if test_item(scope, args.get(1), x?)? {
+            return Ok(Value::Bool(true));
+        }
+    }
+    Ok(Value::Bool(false))
+}
+
+/// all(xs, f) checks if f is true for every item. f can be left off for a list of bools.
+pub fn all(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args_between("all", args, 1, 2)?;
+    for x in iterate(args[0].clone(), scope)? {
+        if !test_item(scope, args.get(1), x?)? {
+            return Ok(Value::Bool(false));
+        }
+    }
+    Ok(Value::Bool(true))
+}
+
+/// sum(xs) adds up the items, which is 0 for an empty list
+pub fn sum(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("sum", args, 1)?;
+    let mut acc = Value::Integer(0);
+    for x in iterate(args[0].clone(), scope)? {
+        acc = ops::infix(acc, Op::Add, x?)?;
+    }
+    Ok(acc)
+}
+
+/// Find the item that compares as `want` against all the others
+fn extreme(scope: &ScopeRef, name: &str, args: &[Value], want: Ordering) -> Result<Value, IrisError> {
+    expect_args(name, args, 1)?;
+    let mut best: Option<Value> = None;
+    for x in iterate(args[0].clone(), scope)? {
+        let x = x?;
+        best = match best {
+            Some(b) if ops::compare(&x, &b)? != want => Some(b),
+            _ => Some(x),
+        };
+    }
+    best.ok_or_else(|| IrisError::runtime(format!("{}() of an empty list", name)))
+}
+
+pub fn min(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    extreme(scope, "min", args, Ordering::Less)
+}
+
+pub fn max(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    extreme(scope, "max", args, Ordering::Greater)
+}
+
+/// Stable merge sort of (key, item) pairs by key. We can't use sort_by from
+/// std, since comparisons can fail.
+fn merge_sort(mut items: Vec<(Value, Value)>) -> Result<Vec<(Value, Value)>, IrisError> {
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+    let right = items.split_off(items.len() / 2);
+    let mut left = merge_sort(items)?.into_iter().peekable();
+    let mut right = merge_sort(right)?.into_iter().peekable();
+
+    let mut ret = Vec::with_capacity(left.len() + right.len());
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        if ops::compare(&r.0, &l.0)? == Ordering::Less {
+            ret.extend(right.next());
+        } else {
+            ret.extend(left.next());
+        }
+    }
+    ret.extend(left);
+    ret.extend(right);
+    Ok(ret)
+}
+
+fn sort_keyed(items: Vec<(Value, Value)>) -> Result<Vec<Value>, IrisError> {
+    Ok(merge_sort(items)?.into_iter().map(|(_, x)| x).collect())
+}
+
+/// sort(xs) sorts the items in ascending order
+pub fn sort(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("sort", args, 1)?;
+    let items = collect(args[0].clone(), scope)?;
+    Ok(Value::List(sort_keyed(items.into_iter().map(|x| (x.clone(), x)).collect())?))
+}
+
+/// sort_by(xs, f) sorts the items by the key f(x). Items with equal keys stay in order.
+pub fn sort_by(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("sort_by", args, 2)?;
+    let mut items = vec![];
+    for x in iterate(args[0].clone(), scope)? {
+        let x = x?;
+        items.push((call_in(scope, &args[1], vec![x.clone()])?, x));
+    }
+    Ok(Value::List(sort_keyed(items)?))
+}
+
+/// reverse(xs) reverses a list, or a string
+pub fn reverse(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("reverse", args, 1)?;
+    match &args[0] {
+        Value::Str(s) => Ok(Value::Str(s.chars().rev().collect())),
+        val => {
+            let mut items = collect(val.clone(), scope)?;
+            items.reverse();
+            Ok(Value::List(items))
+        },
+    }
+}
+
+/// flat_map(xs, f) applies f to each item and joins the resulting lists together
+pub fn flat_map(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("flat_map", args, 2)?;
+    let mut ret = vec![];
+    for x in iterate(args[0].clone(), scope)? {
+        let mapped = call_in(scope, &args[1], vec![x?])?;
+        for y in iterate(mapped, scope)? {
+            ret.push(y?);
+        }
+    }
+    Ok(Value::List(ret))
+}
+
+/// group_by(xs, f) groups items by the key f(x), giving a dict from each key
+/// to the list of items with that key, in the order each key was first seen
+pub fn group_by(scope: &ScopeRef, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("group_by", args, 2)?;
+    // Where each key's items are in groups
+    let mut index = Dict::new();
+    let mut groups: Vec<(Value, Vec<Value>)> = vec![];
+    for x in iterate(args[0].clone(), scope)? {
+        let x = x?;
+        let key = call_in(scope, &args[1], vec![x.clone()])?;
+        match index.get(&key) {
+            Some(Value::Integer(idx)) => groups[*idx as usize].1.push(x),
+            _ => {
+                index.insert(key.clone(), Value::Integer(groups.len() as i32))?;
+                groups.push((key, vec![x]));
+            },
+        }
+    }
+    let mut ret = Dict::new();
+    for (key, items) in groups {
+        ret.insert(key, Value::List(items))?;
+    }
+    Ok(Value::Dict(ret))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Interpreter;
+
+    fn eval(source: &str) -> Result<Value, IrisError> {
+        Interpreter::new().eval_str(source)
+    }
+
+    fn ints(xs: &[i32]) -> Value {
+        Value::List(xs.iter().map(|&x| Value::Integer(x)).collect())
+    }
+
+    fn pairs(xs: &[(i32, i32)]) -> Value {
+        Value::List(xs.iter().map(|&(a, b)| ints(&[a, b])).collect())
+    }
+
+    #[test]
+    fn map_filter_and_fold() {
+        assert_eq!(eval("map([1, 2, 3], fn(x) x * 2 end)"), Ok(ints(&[2, 4, 6])));
+        assert_eq!(eval("[1, 2, 3, 4].filter(fn(x) x % 2 == 0 end)"), Ok(ints(&[2, 4])));
+        assert_eq!(eval("reduce([1, 2, 3], fn(a, x) a * 10 + x end)"), Ok(Value::Integer(123)));
+        assert_eq!(eval("fold([1, 2, 3], 10, fn(a, x) a - x end)"), Ok(Value::Integer(4)));
+        assert_eq!(eval("flat_map([1, 2], fn(x) [x, x] end)"), Ok(ints(&[1, 1, 2, 2])));
+    }
+
+    #[test]
+    fn zip_and_enumerate() {
+        assert_eq!(eval("zip([1, 2, 3], [4, 5])"), Ok(pairs(&[(1, 4), (2, 5)])));
+        assert_eq!(eval("enumerate([7, 8])"), Ok(pairs(&[(0, 7), (1, 8)])));
+        assert_eq!(eval("enumerate(\"ab\")[1][1]"), Ok(Value::from("b")));
+    }
+
+    #[test]
+    fn any_all_sum_min_max() {
+        assert_eq!(eval("[any([1, 2], fn(x) x > 1 end), all([1, 2], fn(x) x > 1 end)]"), Ok(Value::from(vec![true, false])));
+        assert_eq!(eval("[any([false, true]), all([true, true])]"), Ok(Value::from(vec![true, true])));
+        assert_eq!(eval("sum([1, 2, 3])"), Ok(Value::Integer(6)));
+        assert_eq!(eval("sum([1, 0.5])"), Ok(Value::DoublePrecisionFloat(1.5)));
+        assert_eq!(eval("[min([3, 1, 2]), max([3, 1, 2])]"), Ok(ints(&[1, 3])));
+        assert_eq!(eval("max([\"b\", \"c\", \"a\"])"), Ok(Value::from("c")));
+    }
+
+    #[test]
+    fn sorting() {
+        assert_eq!(eval("sort([3, 1, 2, 1])"), Ok(ints(&[1, 1, 2, 3])));
+        assert_eq!(eval("sort([[2, 1], [1, 2], [1, 1]])"), Ok(pairs(&[(1, 1), (1, 2), (2, 1)])));
+        // sort_by is stable
+        let source = "sort_by([[1, 3], [0, 2], [1, 1], [0, 0]], fn(p) p[0] end)";
+        assert_eq!(eval(source), Ok(pairs(&[(0, 2), (0, 0), (1, 3), (1, 1)])));
+        assert_eq!(eval("reverse([1, 2, 3])"), Ok(ints(&[3, 2, 1])));
+        assert_eq!(eval("reverse(\"abc\")"), Ok(Value::from("cba")));
+    }
+
+    #[test]
+    fn group_by_keeps_the_order_keys_are_first_seen() {
+        let source = "group_by([1, 2, 3, 4, 5], fn(x) x % 3 end).items()";
+        let expected = Value::List(vec![
+            Value::List(vec![Value::Integer(1), ints(&[1, 4])]),
+            Value::List(vec![Value::Integer(2), ints(&[2, 5])]),
+            Value::List(vec![Value::Integer(0), ints(&[3])]),
+        ]);
+        assert_eq!(eval(source), Ok(expected));
+    }
+
+    #[test]
+    fn empty_lists() {
+        for (source, expected) in &[
+            ("map(list(), fn(x) x end)", ints(&[])),
+            ("filter(list(), fn(x) true end)", ints(&[])),
+            ("fold(list(), 5, fn(a, x) a + x end)", Value::Integer(5)),
+            ("zip(list(), [1])", ints(&[])),
+            ("enumerate(list())", ints(&[])),
+            ("any(list())", Value::Bool(false)),
+            ("all(list())", Value::Bool(true)),
+            ("sum(list())", Value::Integer(0)),
+            ("sort(list())", ints(&[])),
+            ("sort_by(list(), fn(x) x end)", ints(&[])),
+            ("reverse(list())", ints(&[])),
+            ("flat_map([1, 2], fn(x) list() end)", ints(&[])),
+            ("len(group_by(list(), fn(x) x end))", Value::Integer(0)),
+        ] {
+            assert_eq!(eval(source).as_ref(), Ok(expected), "{}", source);
+        }
+        for source in &["reduce(list(), fn(a, x) a end)", "min(list())", "max(list())"] {
+            let err = eval(source).unwrap_err().to_string();
+            assert!(err.contains("of an empty list"), "{}: {}", source, err);
+        }
+    }
+
+    #[test]
+    fn wrong_args_are_errors() {
+        for source in &[
+            "map([1])",
+            "map(1, fn(x) x end)",
+            "map([1], 2)",
+            "map([1], fn(a, b) a end)",
+            "filter([1], fn(x) x end)",
+            "any([1])",
+            "sum([1, \"a\"])",
+            "sort([1, \"a\"])",
+            "min([1, \"a\"])",
+            "sort_by([1, 2], fn(x) if x == 1 then \"a\" else 2 end end)",
+            "flat_map([1], fn(x) x end)",
+            "group_by([1], fn(x) print end)",
+            "zip([1])",
+            "reverse(1)",
+        ] {
+            assert!(eval(source).is_err(), "{} should be an error", source);
+        }
+    }
+}
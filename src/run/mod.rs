@@ -1,28 +1,53 @@
+mod assertions;
 mod builtins;
+pub mod capabilities;
+mod convert;
+mod dict;
+mod format;
+mod interpreter;
+mod io;
+mod iter;
+pub(crate) mod json;
+pub mod limits;
+mod lists;
 mod ops;
+mod re;
+pub mod registry;
+mod runtime;
 mod scope;
-mod typing;
+mod strings;
+pub mod typing;
 
 use std::rc::Rc;
 use std::cell::RefCell;
 
-use crate::ast::{AstNode,Term};
-use scope::Scope;
+use crate::ast::{AstNode,Op,Term,TypeExpr};
+use crate::error::IrisError;
+use registry::{NativeFunction, NativeObject};
+pub use dict::{Dict, Set};
+pub(crate) use runtime::Hook;
+pub(crate) use scope::Scope;
 use typing::*;
 
+pub use interpreter::Interpreter;
+
 /// Representation of any input value or result of computation
 #[derive(Clone, Debug)]
 pub enum Value {
     Integer(i32),
+    DoublePrecisionFloat(f64),
     Str(String),
     Bool(bool),
     List(Vec<Value>),
+    Dict(Dict),
+    Set(Set),
     Function(Function),
+    NativeFunction(NativeFunction),
+    Native(NativeObject),
     Some(Box<Value>),
     None,
     // Future planned builtin types:
     // Tuple(Vec<Value>, usize),
-    // Dict(HashMap<Value, Value>),
     // Record{
     //   ...   
     // }
@@ -31,231 +56,538 @@ pub enum Value {
     //     variant: String,
     //     data: Vec<Value>,
     // },
-    // Decimal(?)
 }
 
-/// Call the given function with args
-fn fn_call(name: &str, args: &Vec<AstNode>, scope: &Rc<RefCell<Scope>>) -> Value {
-    let evalled_args = args.iter().map(|arg| eval(arg, scope)).collect();
-
-    match name {
-        "print" => builtins::print(evalled_args),
-        "list" => Value::List(evalled_args),
-        "len" => builtins::len(evalled_args),
-        "Some" => builtins::some(evalled_args),
-        "unwrap" => builtins::unwrap(evalled_args),
-        "is_some" => builtins::is_some(evalled_args),
-        "is_none" => builtins::is_none(evalled_args),
-        _ => {
-            let s = scope.borrow();
-            let mut func = if name.chars().next().unwrap().is_uppercase() {
-                let first_arg = evalled_args.get(0)
-                    .expect(&format!(
-                        "Method {} has no args. Methods must have at least one arg.
-                        NOTE: Capitalized function names are interpreted as methods by convention.",
-                        name,
-                    ));
-                let typ = type_of(first_arg);
-                s.get_method(name, typ.clone())
-                    .expect(&format!(
-                        "Unknown method {} for type {:?}
-                        NOTE: Capitalized function names are interpreted as methods by convention.",
-                        name,
-                        typ,
-                    ))
-            } else {
-                s.get_fn(name)
-            };
- 
-            if func.args.len() != evalled_args.len() {
-                panic!("Incorrect number of args for function \"{}\": got {}, expected {}", name,  evalled_args.len(), func.args.len());
-            }
-            for (idx, argname) in func.args.iter().enumerate() {
-                if s.var_is_set(argname) {
-                    panic!("function argument {} mirrors variable of the same name in outer scope", argname);
-                }
-                func.scope.declare_var(argname, evalled_args[idx].clone());
-            }
-            exec_fn(func)
+impl Value {
+    /// Wrap a Rust object so it can be handed to a script. Methods registered
+    /// for `TypeDefinition::Native(type_name)` can be called on it.
+    pub fn native<T: std::any::Any>(type_name: &str, object: T) -> Value {
+        Value::Native(NativeObject::new(type_name, object))
+    }
+
+    /// Get at the Rust object wrapped in a native value, if it's of type T
+    pub fn downcast_ref<T: std::any::Any>(&self) -> Option<&T> {
+        match self {
+            Value::Native(obj) => obj.downcast_ref::<T>(),
+            _ => None,
+        }
+    }
+
+    /// How the value would be written in source, with strings quoted
+    pub fn repr(&self) -> String {
+        builtins::repr(self)
+    }
+
+    /// Call this value as a function. This lets Rust code, such as functions
+    /// registered by a host program, call back into Iris.
+    pub fn call(&self, args: Vec<Value>) -> Result<Value, IrisError> {
+        match self {
+            Value::Function(f) => exec_fn(f.clone(), args),
+            Value::NativeFunction(f) => f.call(&args),
+            _ => Err(IrisError::runtime(format!("{:?} is not a function", self))),
         }
     }
 }
 
-fn exec_fn(func: Function) -> Value {
-    let mut ret: Option<Value> = None;
+/// The result of evaluating an expression in tail position. A function call
+/// in tail position is handed back to the caller as `Call` instead of being
+/// executed, so that `exec_fn` can run it in a loop rather than recursing.
+/// This is what lets recursive functions run in constant stack space.
+enum Tail {
+    Value(Value),
+    Call(Function, Vec<Value>),
+}
 
-    let body_len = func.body.len();
-    let scope = Rc::new(RefCell::new(func.scope));
-    for (idx, ast) in func.body.iter().enumerate() {
-        match ast {
-            _ => {
-                let val = stmt(&ast, &scope);
-                if idx == (body_len - 1) {
-                    ret = Some(val);
-                }
-            }
-        };
+/// Run a pending tail call, if any, to get a final value
+fn finish(tail: Tail) -> Result<Value, IrisError> {
+    match tail {
+        Tail::Value(val) => Ok(val),
+        Tail::Call(func, args) => exec_fn(func, args),
+    }
+}
+
+/// Call the given function with args
+fn fn_call(name: &str, args: &[AstNode], scope: &Rc<RefCell<Scope>>) -> Result<Value, IrisError> {
+    finish(tail_call(name, args, scope)?)
+}
+
+/// Evaluate the args and resolve the function being called, without calling it.
+fn tail_call(name: &str, args: &[AstNode], scope: &Rc<RefCell<Scope>>) -> Result<Tail, IrisError> {
+    let evalled_args = args.iter()
+        .map(|arg| eval(arg, scope))
+        .collect::<Result<Vec<Value>, IrisError>>()?;
+    resolve_call(name, evalled_args, scope)
+}
+
+/// Evaluate a call written `x.name(args)`, or `Type.name(args)` to call the
+/// method for that type, without calling it
+fn tail_method_call(name: &str, args: &[AstNode], scope: &Rc<RefCell<Scope>>) -> Result<Tail, IrisError> {
+    let eval_args = |args: &[AstNode]| args.iter()
+        .map(|arg| eval(arg, scope))
+        .collect::<Result<Vec<Value>, IrisError>>();
+    match receiver_type(&args[0], scope) {
+        Some(typ) => {
+            let evalled_args = eval_args(&args[1..])?;
+            let callee = find_method(name, &evalled_args, Some(&typ), scope)?
+                .ok_or_else(|| IrisError::runtime(format!(
+                    "no method {} for {} that fits {}", name, typ, describe_args(&evalled_args),
+                )))?;
+            call_value(callee, evalled_args, scope)
+        },
+        None => resolve_method_call(name, eval_args(args)?, scope),
+    }
+}
+
+/// The type named by the receiver of `Type.name(args)`, if it's a type name
+/// rather than a variable
+fn receiver_type(receiver: &AstNode, scope: &Rc<RefCell<Scope>>) -> Option<TypeDefinition> {
+    match receiver {
+        AstNode::Term(Term::Ident(name)) if scope.borrow().get_var(name).is_none() => {
+            read_type_definition(&TypeExpr::Named(name.to_string())).ok()
+        },
+        _ => None,
     }
+}
+
+/// Resolve a call written `name(args)` with already evaluated args. Functions
+/// are looked for first, then methods for the args.
+fn resolve_call(name: &str, evalled_args: Vec<Value>, scope: &Rc<RefCell<Scope>>) -> Result<Tail, IrisError> {
+    let callee = match find_fn(name, scope)? {
+        Some(f) => f,
+        None => find_method(name, &evalled_args, None, scope)?
+            .ok_or_else(|| unknown_call(name, &evalled_args, scope))?,
+    };
+    call_value(callee, evalled_args, scope)
+}
+
+/// Resolve a call written `x.name(args)`, with x first in the evaluated args.
+/// Methods for the args are looked for first, then functions.
+fn resolve_method_call(name: &str, evalled_args: Vec<Value>, scope: &Rc<RefCell<Scope>>) -> Result<Tail, IrisError> {
+    let callee = match find_method(name, &evalled_args, None, scope)? {
+        Some(f) => f,
+        None => find_fn(name, scope)?
+            .ok_or_else(|| unknown_call(name, &evalled_args, scope))?,
+    };
+    call_value(callee, evalled_args, scope)
+}
+
+/// List and dict literals are parsed as calls to these, so they always get
+/// the builtins
+const LITERALS: [&str; 2] = ["list", "dict"];
 
-    match ret {
-        Some(val) => val,
-        None => Value::None,
+/// Find a function by name. Variables in scope, including functions defined
+/// in Iris, shadow builtins and host functions of the same name, like they
+/// do when the name is used as a value.
+fn find_fn(name: &str, scope: &Rc<RefCell<Scope>>) -> Result<Option<Value>, IrisError> {
+    let s = scope.borrow();
+    if !LITERALS.contains(&name) && s.get_var(name).is_some() {
+        return s.get_fn(name).map(Some);
     }
+    let native = s.runtime().borrow().registry.get_fn(name);
+    Ok(native.map(Value::NativeFunction))
 }
 
+/// Find the method to call for some args, either defined in Iris or by the
+/// host. If a type is given, only methods for that type are considered.
+fn find_method(
+    name: &str,
+    args: &[Value],
+    for_type: Option<&TypeDefinition>,
+    scope: &Rc<RefCell<Scope>>,
+) -> Result<Option<Value>, IrisError> {
+    if args.is_empty() {
+        return Ok(None);
+    }
+    let s = scope.borrow();
+    if let Some(f) = s.get_method(name, args, for_type)? {
+        return Ok(Some(Value::Function(f)));
+    }
+    let native = s.runtime().borrow().registry.get_method(name, args, for_type)?;
+    Ok(native.map(Value::NativeFunction))
+}
 
-fn test_bool_val(v: Value) -> bool {
+/// The error for a call that matches no function or method
+fn unknown_call(name: &str, args: &[Value], scope: &Rc<RefCell<Scope>>) -> IrisError {
+    let s = scope.borrow();
+    if s.has_method(name) || s.runtime().borrow().registry.has_method(name) {
+        IrisError::runtime(format!("no method {} for {}", name, describe_args(args)))
+    } else {
+        IrisError::runtime(format!("unknown function {}", name))
+    }
+}
+
+/// The types of some args, like "int" or "(int, str)"
+fn describe_args(args: &[Value]) -> String {
+    let types: Vec<String> = args.iter().map(type_name).collect();
+    match types.as_slice() {
+        [t] => t.to_string(),
+        _ => format!("({})", types.join(", ")),
+    }
+}
+
+/// Call a function value. Natives are run immediately since they can't recurse
+/// in Iris; Iris functions are handed back to be run by the caller.
+fn call_value(callee: Value, args: Vec<Value>, scope: &Rc<RefCell<Scope>>) -> Result<Tail, IrisError> {
+    match callee {
+        Value::Function(f) => Ok(Tail::Call(f, args)),
+        Value::NativeFunction(f) => {
+            let val = f.call_in(scope, &args)?;
+            scope.borrow().runtime().borrow().check_nesting(&val)?;
+            Ok(Tail::Value(val))
+        },
+        _ => Err(IrisError::runtime(format!("{:?} is not a function", callee))),
+    }
+}
+
+/// Call a function value from a builtin running in the given scope
+fn call_in(scope: &Rc<RefCell<Scope>>, callee: &Value, args: Vec<Value>) -> Result<Value, IrisError> {
+    finish(call_value(callee.clone(), args, scope)?)
+}
+
+/// Stack to leave free for running one more call before growing the stack
+const STACK_RED_ZONE: usize = 1024 * 1024;
+/// How much stack to add at a time when we're running out
+const STACK_GROWTH: usize = 16 * 1024 * 1024;
+
+/// Execute a function, counting it against the recursion limit. Every call
+/// that isn't in tail position recurses in Rust, so the native stack is
+/// grown as needed to reach the recursion limit on whatever thread we're on.
+fn exec_fn(func: Function, args: Vec<Value>) -> Result<Value, IrisError> {
+    let runtime = func.scope.runtime();
+    runtime.borrow_mut().enter_call(func.scope.context())?;
+    let res = stacker::maybe_grow(STACK_RED_ZONE, STACK_GROWTH, || exec_fn_body(func, args));
+    runtime.borrow_mut().leave(res.is_err());
+    res
+}
+
+/// Execute a function body with the given args. Calls in tail position replace
+/// the current function and args, and we go around the loop again.
+fn exec_fn_body(func: Function, args: Vec<Value>) -> Result<Value, IrisError> {
+    let mut func = func;
+    let mut args = args;
+    loop {
+        if func.args.len() != args.len() {
+            return Err(IrisError::runtime(format!(
+                "Incorrect number of args for function \"{}\": got {}, expected {}",
+                func.name, args.len(), func.args.len(),
+            )));
+        }
+        for (argname, val) in func.args.iter().zip(args) {
+            if func.scope.parent_var_is_set(argname) {
+                return Err(IrisError::runtime(format!(
+                    "function argument {} mirrors variable of the same name in outer scope", argname,
+                )));
+            }
+            func.scope.declare_var(argname, val)?;
+        }
+
+        let (last, init) = match func.body.split_last() {
+            Some(split) => split,
+            None => return Ok(Value::None),
+        };
+        let scope = Rc::new(RefCell::new(func.scope));
+        for ast in init {
+            stmt(ast, &scope)?;
+        }
+        match tail_stmt(last, &scope)? {
+            Tail::Value(val) => return Ok(val),
+            Tail::Call(next_func, next_args) => {
+                next_func.scope.runtime().borrow_mut().replace_call(next_func.scope.context());
+                func = next_func;
+                args = next_args;
+            },
+        }
+    }
+}
+
+fn test_bool_val(v: Value) -> Result<bool, IrisError> {
     match v {
-        Value::Bool(true) => true,
-        Value::Bool(false) => false,
-        _ => panic!("Expected bool, got: {:?}", v),
+        Value::Bool(b) => Ok(b),
+        _ => Err(IrisError::runtime(format!("Expected bool, got: {:?}", v))),
     }
 }
 
-fn declare_var_in(scope: &Rc<RefCell<Scope>>, var: &str, val: Value) {
+fn declare_var_in(scope: &Rc<RefCell<Scope>>, var: &str, val: Value) -> Result<(), IrisError> {
     let mut s = scope.borrow_mut();
-    s.declare_var(var, val);
+    s.declare_var(var, val)
 }
-fn set_var_in(scope: &Rc<RefCell<Scope>>, var: &str, val: Value) {
+fn set_var_in(scope: &Rc<RefCell<Scope>>, var: &str, val: Value) -> Result<(), IrisError> {
     let mut s = scope.borrow_mut();
-    s.set_var(var, val);
+    s.set_var(var, val)
 }
 
-fn for_in(item_var: &str, in_val: &AstNode, body: &Vec<AstNode>, scope: &Rc<RefCell<Scope>>) {
+fn for_in(
+    item_var: &str,
+    in_val: &AstNode,
+    body: &[AstNode],
+    scope: &Rc<RefCell<Scope>>
+) -> Result<(), IrisError> {
     let inner_scope = Rc::new(RefCell::new(Scope::nest(scope, "for loop")));
-    let next = fn_call("Iter", &vec![in_val.clone()], &inner_scope);
-    declare_var_in(&inner_scope, "__next", next);
-    declare_var_in(&inner_scope, &item_var, fn_call("__next", &vec![], &inner_scope));
-
-    let is_some_arg = vec![AstNode::Term(Term::Ident(item_var.to_string()))];
-    while test_bool_val(fn_call("is_some", &is_some_arg, &inner_scope)) {
-        stmt_body(body, &inner_scope);
-        set_var_in(&inner_scope, item_var, fn_call("__next", &vec![], &inner_scope));
-    }
+    in_loop(&inner_scope, || {
+        let items = iter::iterate(eval(in_val, &inner_scope)?, &inner_scope)?;
+        declare_var_in(&inner_scope, item_var, Value::None)?;
+        for item in items {
+            set_var_in(&inner_scope, item_var, item?)?;
+            stmt_body(body, &inner_scope)?;
+        }
+        Ok(())
+    })
 }
 
-fn while_stmt(cond_expr: &AstNode, body: &Vec<AstNode>, scope: &Rc<RefCell<Scope>>) {
-    let inner_scope = Rc::new(RefCell::new(Scope::nest(scope, "for loop")));
-    while test_bool_val(eval(cond_expr, &inner_scope)) {
-        stmt_body(body, &inner_scope);
-    }
+fn while_stmt(cond_expr: &AstNode, body: &[AstNode], scope: &Rc<RefCell<Scope>>) -> Result<(), IrisError> {
+    let inner_scope = Rc::new(RefCell::new(Scope::nest(scope, "while loop")));
+    in_loop(&inner_scope, || {
+        while test_bool_val(eval(cond_expr, &inner_scope)?)? {
+            stmt_body(body, &inner_scope)?;
+        }
+        Ok(())
+    })
+}
+
+/// Run a loop, with a frame for it so it shows up in tracebacks
+fn in_loop<F>(scope: &Rc<RefCell<Scope>>, run: F) -> Result<(), IrisError>
+where F: FnOnce() -> Result<(), IrisError> {
+    let runtime = scope.borrow().runtime();
+    runtime.borrow_mut().enter_loop(scope.borrow().context());
+    let res = run();
+    runtime.borrow_mut().leave(res.is_err());
+    res
 }
 
 fn exec_if(
     cond_expr: &AstNode,
-    body: &Vec<AstNode>,
-    else_if: &Vec<AstNode>,
-    else_body: &Vec<AstNode>,
+    body: &[AstNode],
+    else_if: &[AstNode],
+    else_body: &[AstNode],
     scope: &Rc<RefCell<Scope>>
-) -> Value {
-    if test_bool_val(eval(cond_expr, scope)) {
-        return stmt_body(body, scope);
+) -> Result<Tail, IrisError> {
+    if test_bool_val(eval(cond_expr, scope)?)? {
+        return tail_body(body, scope);
     }
     for try_else_if in else_if {
         let (cond_expr, body) = match try_else_if {
-            AstNode::ElseIf{ cond_expr, body } => (cond_expr, body),
-            _ => panic!("expected ElseIf, got {:?}", try_else_if),
+            AstNode::ElseIf { cond_expr, body, .. } => (cond_expr, body),
+            _ => unreachable!("expected ElseIf, got {:?}", try_else_if),
         };
-        if test_bool_val(eval(cond_expr, scope)) {
-            return stmt_body(body, scope);
+        if test_bool_val(eval(cond_expr, scope)?)? {
+            return tail_body(body, scope);
         }
     }
-    stmt_body(else_body, scope)
+    tail_body(else_body, scope)
+}
 
+fn stmt_body(body: &[AstNode], scope: &Rc<RefCell<Scope>>) -> Result<Value, IrisError> {
+    finish(tail_body(body, scope)?)
 }
 
-fn stmt_body(body: &Vec<AstNode>, scope: &Rc<RefCell<Scope>>) -> Value {
-    let mut ret: Option<Value> = None;
-    let body_len = body.len();
-    for (idx, ast) in body.iter().enumerate() {
-        let val = stmt(&ast, scope);
-        if idx == (body_len - 1) {
-            ret = Some(val);
-        }
+/// Run each statement of a body, leaving the last one in tail position
+fn tail_body(body: &[AstNode], scope: &Rc<RefCell<Scope>>) -> Result<Tail, IrisError> {
+    match body.split_last() {
+        Some((last, init)) => {
+            for ast in init {
+                stmt(ast, scope)?;
+            }
+            tail_stmt(last, scope)
+        },
+        None => Ok(Tail::Value(Value::None)),
     }
-    match ret {
-        Some(val) => val,
-        None => Value::None,
+}
+
+/// What to call a function in tracebacks. Anonymous functions don't have a
+/// name, so go by where they're defined.
+fn fn_context(name: &str, pos: usize, scope: &Rc<RefCell<Scope>>) -> String {
+    if name != "anon" {
+        return format!("fn {}", name);
     }
+    let runtime = scope.borrow().runtime();
+    let runtime = runtime.borrow();
+    format!("<anonymous fn defined at line {}>", runtime.line(pos))
 }
 
+/// Note where the statement about to run is, and tell the hook if there is one
+fn enter_stmt(pos: usize, scope: &Rc<RefCell<Scope>>) -> Result<(), IrisError> {
+    let runtime = scope.borrow().runtime();
+    let hook = {
+        let mut runtime = runtime.borrow_mut();
+        runtime.enter_stmt(pos);
+        runtime.hook.clone()
+    };
+    match hook {
+        Some(hook) => hook.borrow_mut().before_stmt(pos, scope),
+        None => Ok(()),
+    }
+}
 
-fn eval(ast: &AstNode, scope: &Rc<RefCell<Scope>>) -> Value { // : &mut Scope) -> Value {
+/// Like stmt, but a function call in tail position is returned to the caller
+/// instead of being executed
+fn tail_stmt(ast: &AstNode, scope: &Rc<RefCell<Scope>>) -> Result<Tail, IrisError> {
     match ast {
-        AstNode::FnCall{ name, args } => fn_call(name, args, scope),
-        AstNode::FnDef{ name, args, body } => {
+        AstNode::Located{ pos, stmt, .. } => {
+            enter_stmt(*pos, scope)?;
+            tail_stmt(stmt, scope)
+        },
+        AstNode::FnCall{ name, args } => tail_call(name, args, scope),
+        AstNode::MethodCall{ name, args } => tail_method_call(name, args, scope),
+        AstNode::If{ cond_expr, body, else_if, else_body, .. } => {
+            exec_if(cond_expr, body, else_if, else_body, scope)
+        },
+        _ => Ok(Tail::Value(stmt(ast, scope)?)),
+    }
+}
+
+
+fn eval(ast: &AstNode, scope: &Rc<RefCell<Scope>>) -> Result<Value, IrisError> {
+    let runtime = scope.borrow().runtime();
+    runtime.borrow_mut().step()?;
+
+    let val = match ast {
+        AstNode::FnCall{ name, args } => fn_call(name, args, scope)?,
+        AstNode::MethodCall{ name, args } => finish(tail_method_call(name, args, scope)?)?,
+        AstNode::FnDef{ pos, name, args, body, .. } => {
             Value::Function(
                 Function {
                     name: name.to_string(),
                     args: args.to_vec(),
                     body: body.to_vec(),
-                    scope: Scope::nest(scope, name),
+                    scope: Scope::nest(scope, &fn_context(name, *pos, scope)),
                 }
             )
         },
-        AstNode::If{ cond_expr, body, else_if, else_body } => {
-            exec_if(cond_expr, body, else_if, else_body, scope)
+        AstNode::If{ cond_expr, body, else_if, else_body, .. } => {
+            finish(exec_if(cond_expr, body, else_if, else_body, scope)?)?
+        },
+        // and/or only evaluate the rhs if they need to
+        AstNode::Infix(lhs, Op::And, rhs) => {
+            Value::Bool(test_bool_val(eval(lhs, scope)?)? && test_bool_val(eval(rhs, scope)?)?)
+        },
+        AstNode::Infix(lhs, Op::Or, rhs) => {
+            Value::Bool(test_bool_val(eval(lhs, scope)?)? || test_bool_val(eval(rhs, scope)?)?)
         },
         AstNode::Infix(lhs, op, rhs) => ops::infix(
-            eval(lhs, scope), op.clone(), eval(rhs, scope)
-        ),
-        AstNode::Unary(op, rhs) => ops::unary(op.clone(), eval(rhs, scope)),
+            eval(lhs, scope)?, op.clone(), eval(rhs, scope)?
+        )?,
+        AstNode::Unary(op, rhs) => ops::unary(op.clone(), eval(rhs, scope)?)?,
         AstNode::Term(Term::Str(x)) => Value::Str(x.to_string()),
+        AstNode::Interpolation(parts) => {
+            let mut ret = String::new();
+            for part in parts {
+                ret.push_str(&builtins::fmt(&eval(part, scope)?));
+            }
+            Value::Str(ret)
+        },
         AstNode::Term(Term::Integer(x)) => Value::Integer(*x),
         AstNode::Term(Term::Bool(x)) => Value::Bool(*x),
         AstNode::Term(Term::None) => Value::None,
-        // AstNode::Term(Term::DoublePrecisionFloat(x)) => Value::DoublePrecisionFloat(*x),
+        AstNode::Term(Term::DoublePrecisionFloat(x)) => Value::DoublePrecisionFloat(*x),
         AstNode::Term(Term::Ident(var)) => {
             let s = scope.borrow();
-            s.get_var(var).expect(&format!("Unable to resolve var {:?}", var))
+            match s.get_var(var) {
+                Some(val) => val,
+                None => runtime.borrow().registry.get_fn(var)
+                    .map(Value::NativeFunction)
+                    .ok_or_else(|| IrisError::runtime(format!("Unable to resolve var {:?}", var)))?,
+            }
         },
-        _ => panic!("Unexpected ast {:?}", ast),
-    }
+        _ => return Err(IrisError::runtime(format!("Unexpected ast {:?}", ast))),
+    };
+    Ok(val)
 }
 
-fn stmt(ast: &AstNode, scope: &Rc<RefCell<Scope>>) -> Value {
+fn stmt(ast: &AstNode, scope: &Rc<RefCell<Scope>>) -> Result<Value, IrisError> {
     match ast {
+        AstNode::Located{ pos, stmt: inner, .. } => {
+            enter_stmt(*pos, scope)?;
+            return stmt(inner, scope);
+        },
+        // Named functions are bound in the scope they're defined in, which is
+        // also the parent of their own scope, so they can call themselves
+        AstNode::FnDef{ name, .. } if name != "anon" => {
+            let func = eval(ast, scope)?;
+            let mut s = scope.borrow_mut();
+            s.declare_var(name, func)?;
+        },
         AstNode::VarDeclaration(Term::Ident(var), astbox) => {
-            let val = eval(astbox, scope);
+            let val = eval(astbox, scope)?;
             let mut s = scope.borrow_mut();
-            s.declare_var(var, val);
-            Value::None
+            s.declare_var(var, val)?;
         },
         AstNode::Assignment(Term::Ident(var), astbox) => {
-            let val = eval(astbox, scope);
+            let val = eval(astbox, scope)?;
             let mut s = scope.borrow_mut();
-            s.set_var(var, val);
-            Value::None
+            s.set_var(var, val)?;
         },
-        AstNode::MethodDef{ name, for_type, args, body } => {
-            if !name.chars().next().unwrap().is_uppercase() {
-                panic!("Method names must be capitalized by convention. Got: {}", name);
+        AstNode::MethodDef{ name, for_types, args, body, .. } => {
+            if for_types.len() > args.len() {
+                return Err(IrisError::runtime(format!(
+                    "Method {} is for {} types, but only takes {} arg{}",
+                    name, for_types.len(), args.len(), if args.len() == 1 { "" } else { "s" },
+                )));
             }
+            let for_types = for_types.iter()
+                .map(read_type_definition)
+                .collect::<Result<Vec<_>, IrisError>>()?;
             let method = Function {
                 name: name.to_string(),
                 args: args.to_vec(),
                 body: body.to_vec(),
-                scope: Scope::nest(scope, name),
+                scope: Scope::nest(scope, &format!("method {}", name)),
             };
             let mut s = scope.borrow_mut();
-            s.declare_method(name, read_type_definition(for_type), method);
-            Value::None
-        },
-        AstNode::WhileStmt(cond, body) => {
-            while_stmt(cond, body, scope);
-            Value::None
-        },
-        AstNode::ForStmt(iter_var, iterable, body) => {
-            for_in(iter_var, iterable, body, scope);
-            Value::None
+            s.declare_method(name, for_types, method);
         },
-        _ => eval(ast, scope),
+        AstNode::WhileStmt(cond, body) => while_stmt(cond, body, scope)?,
+        AstNode::ForStmt(iter_var, iterable, body) => for_in(iter_var, iterable, body, scope)?,
+        _ => return eval(ast, scope),
     }
+    Ok(Value::None)
 }
 
-pub fn run(ast_list: Vec<AstNode>) {
-    let global_scope = Rc::new(RefCell::new(Scope::new(String::from("<top level>"))));
-    for ast_node in ast_list {
-        stmt(&ast_node, &global_scope);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use limits::Limits;
+
+    fn eval_str(source: &str) -> Result<Value, IrisError> {
+        Interpreter::new().eval_str(source)
+    }
+
+    #[test]
+    fn tail_calls_dont_count_against_the_recursion_limit() {
+        let source = "fn count(n, acc)\n  if n == 0 then acc else count(n - 1, acc + 1) end\nend\ncount(100000, 0)";
+        assert_eq!(eval_str(source), Ok(Value::Integer(100000)));
+    }
+
+    #[test]
+    fn mutual_tail_calls_dont_count_against_the_recursion_limit() {
+        let source = "fn is_even(n)\n  if n == 0 then true else is_odd(n - 1) end\nend\n\
+            fn is_odd(n)\n  if n == 0 then false else is_even(n - 1) end\nend\nis_even(100001)";
+        assert_eq!(eval_str(source), Ok(Value::Bool(false)));
+    }
+
+    #[test]
+    fn calls_that_arent_in_tail_position_do() {
+        let source = "fn sum(n)\n  if n == 0 then 0 else n + sum(n - 1) end\nend\nsum(100000)";
+        let limit = Limits::default().max_depth;
+        assert_eq!(eval_str(source), Err(IrisError::RecursionLimit(limit)));
+        let source = "fn sum(n)\n  if n == 0 then 0 else n + sum(n - 1) end\nend\nsum(100)";
+        assert_eq!(eval_str(source), Ok(Value::Integer(5050)));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn tail_calls_get_their_own_args() {
+        let source = "fn f(a, b)\n  if a == 0 then b else f(a - 1, a) end\nend\nf(3, 10)";
+        assert_eq!(eval_str(source), Ok(Value::Integer(1)));
+    }
+
+    #[test]
+    fn args_cant_have_the_name_of_a_variable_where_the_function_is_defined() {
+        let res = eval_str("let x = 1\nfn f(x)\n  x\nend\nf(2)");
+        assert!(res.unwrap_err().to_string().contains("function argument x mirrors variable"));
+    }
+
+    #[test]
+    fn args_can_have_the_name_of_a_variable_where_the_function_is_called() {
+        let source = "fn f(x)\n  x\nend\nfn g()\n  let x = 1\n  f(2) + x\nend\ng()";
+        assert_eq!(eval_str(source), Ok(Value::Integer(3)));
+    }
+}
@@ -1,79 +1,380 @@
-/// Unary and binary operators implementations
-/// This is very partially roughed in
+//! Unary and binary operators implementations
+//! This is very partially roughed in
+
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 
 use crate::ast::Op;
+use crate::error::IrisError;
 use super::Value;
+use super::typing::type_name;
 
 /// Dispatch the appropriate infix handler fn based on lhs type
-pub fn infix(lhs: Value, op: Op, rhs: Value) -> Value {
+pub fn infix(lhs: Value, op: Op, rhs: Value) -> Result<Value, IrisError> {
+    // Comparisons work the same way for every type
+    match op {
+        Op::Equal => return Ok(Value::Bool(equals(&lhs, &rhs)?)),
+        Op::NotEqual => return Ok(Value::Bool(!equals(&lhs, &rhs)?)),
+        Op::LessThan => return Ok(Value::Bool(compare(&lhs, &rhs)? == Ordering::Less)),
+        Op::LessThanEqual => return Ok(Value::Bool(compare(&lhs, &rhs)? != Ordering::Greater)),
+        Op::GreaterThan => return Ok(Value::Bool(compare(&lhs, &rhs)? == Ordering::Greater)),
+        Op::GreaterThanEqual => return Ok(Value::Bool(compare(&lhs, &rhs)? != Ordering::Less)),
+        _ => {},
+    }
     match lhs {
         Value::Integer(i) => infix_int(i, op, rhs),
+        Value::DoublePrecisionFloat(x) => infix_float(x, op, rhs),
         Value::Str(s) => infix_str(s, op, rhs),
-        Value::Bool(_) => panic!("todo implement bool arith"),
-        Value::Function(_) => panic!("can't {:?} on function", op),
+        Value::Bool(b) => infix_bool(b, op, rhs),
+        Value::Function(_) | Value::NativeFunction(_) => Err(IrisError::runtime(format!("can't {:?} on function", op))),
         Value::List(l) => infix_list(l, op, rhs),
-        Value::None => panic!("Can't {:?} None and {:?}", op, rhs),
-        _ => panic!("unimplemented infix for {:?}", lhs),
+        Value::Dict(d) if op == Op::MemberAccess => d.get(&rhs)
+            .cloned()
+            .ok_or_else(|| IrisError::runtime(format!("key {:?} not found in dict", rhs))),
+        Value::None => Err(IrisError::runtime(format!("Can't {:?} None and {:?}", op, rhs))),
+        _ => Err(IrisError::runtime(format!("unimplemented infix for {:?}", lhs))),
     }
 }
 
-/// TODO: implement unary ops
-pub fn unary(_op: Op, _rhs: Value) -> Value {
-    Value::None
+/// Order two values, for <, sorting, and finding the min or max. Numbers,
+/// strings, bools, lists and Options can be ordered; lists are ordered item
+/// by item, and None comes before any Some. Anything else is an error.
+pub fn compare(a: &Value, b: &Value) -> Result<Ordering, IrisError> {
+    match (a, b) {
+        (Value::Integer(x), Value::Integer(y)) => Ok(x.cmp(y)),
+        (Value::Integer(x), Value::DoublePrecisionFloat(y)) => Ok(compare_floats(*x as f64, *y)),
+        (Value::DoublePrecisionFloat(x), Value::Integer(y)) => Ok(compare_floats(*x, *y as f64)),
+        (Value::DoublePrecisionFloat(x), Value::DoublePrecisionFloat(y)) => Ok(compare_floats(*x, *y)),
+        (Value::Str(x), Value::Str(y)) => Ok(x.cmp(y)),
+        (Value::Bool(x), Value::Bool(y)) => Ok(x.cmp(y)),
+        (Value::List(xs), Value::List(ys)) => {
+            for (x, y) in xs.iter().zip(ys) {
+                match compare(x, y)? {
+                    Ordering::Equal => continue,
+                    ord => return Ok(ord),
+                }
+            }
+            Ok(xs.len().cmp(&ys.len()))
+        },
+        (Value::None, Value::None) => Ok(Ordering::Equal),
+        (Value::None, Value::Some(_)) => Ok(Ordering::Less),
+        (Value::Some(_), Value::None) => Ok(Ordering::Greater),
+        (Value::Some(x), Value::Some(y)) => compare(x, y),
+        _ => {
+            let (a_type, b_type) = (type_name(a), type_name(b));
+            let why = if a_type == b_type {
+                format!("{} values have no ordering", a_type)
+            } else {
+                format!("{} and {} can't be ordered against each other", a_type, b_type)
+            };
+            Err(IrisError::runtime(format!(
+                "Can't compare {} with {}: {}", super::builtins::repr(a), super::builtins::repr(b), why,
+            )))
+        },
+    }
 }
 
-/// Integer infix operator handler
-fn infix_int(a: i32, op: Op, rhs: Value) -> Value {
-    match rhs {
-        Value::Integer(b) => match op {
-            Op::Add => Value::Integer(a + b),
-            Op::Sub => Value::Integer(a - b),
-            Op::Mul => Value::Integer(a * b),
-            Op::Div => Value::Integer(a / b),
-            Op::Shr => Value::Integer(a >> b),
-            Op::Shl => Value::Integer(a << b),
-            Op::BitwiseAnd => Value::Integer(a & b),
-            Op::BitwiseOr => Value::Integer(a | b),
-            Op::BitwiseXor => Value::Integer(a ^ b),
-            Op::Mod => Value::Integer(a % b),
-            Op::Exp => Value::Integer(a.pow(b as u32)),
-            // bools
-            Op::GreaterThan => Value::Bool(a > b),
-            Op::LessThan => Value::Bool(a < b),
-            Op::GreaterThanEqual => Value::Bool(a >= b),
-            Op::LessThanEqual => Value::Bool(a <= b),
-            Op::Equal => Value::Bool(a == b),
-            Op::NotEqual => Value::Bool(a != b),
-            _ => panic!("{:?} not implemented", op),
+/// A total order on floats, with NaN after everything else
+fn compare_floats(x: f64, y: f64) -> Ordering {
+    match x.partial_cmp(&y) {
+        Some(ord) => ord,
+        None => x.is_nan().cmp(&y.is_nan()),
+    }
+}
+
+fn contains_function(v: &Value) -> bool {
+    match v {
+        Value::Function(_) | Value::NativeFunction(_) => true,
+        Value::List(xs) => xs.iter().any(contains_function),
+        Value::Dict(d) => d.values().any(contains_function),
+        Value::Some(x) => contains_function(x),
+        _ => false,
+    }
+}
+
+/// Deep structural equality, for == and !=. Values of different types are
+/// never equal, except ints and floats with the same value. Functions can't
+/// be compared, since there's no good way to tell if two do the same thing.
+pub fn equals(a: &Value, b: &Value) -> Result<bool, IrisError> {
+    if contains_function(a) || contains_function(b) {
+        return Err(IrisError::runtime(format!(
+            "Can't compare {} with {}: functions can't be compared",
+            super::builtins::repr(a), super::builtins::repr(b),
+        )));
+    }
+    Ok(a == b)
+}
+
+/// Check that a value can be used as a dict key or set item
+pub fn check_hashable(v: &Value) -> Result<(), IrisError> {
+    if contains_function(v) {
+        return Err(IrisError::runtime(format!(
+            "{} can't be used as a dict key or set item", super::builtins::repr(v),
+        )));
+    }
+    Ok(())
+}
+
+/// Whole floats are equal to the matching int, and NaN is equal to itself so
+/// that it can be found in a dict
+fn floats_equal(x: f64, y: f64) -> bool {
+    x == y || (x.is_nan() && y.is_nan())
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Integer(x), Value::Integer(y)) => x == y,
+            (Value::Integer(x), Value::DoublePrecisionFloat(y))
+            | (Value::DoublePrecisionFloat(y), Value::Integer(x)) => floats_equal(*x as f64, *y),
+            (Value::DoublePrecisionFloat(x), Value::DoublePrecisionFloat(y)) => floats_equal(*x, *y),
+            (Value::Str(x), Value::Str(y)) => x == y,
+            (Value::Bool(x), Value::Bool(y)) => x == y,
+            (Value::List(xs), Value::List(ys)) => xs == ys,
+            (Value::Dict(x), Value::Dict(y)) => x == y,
+            (Value::Set(x), Value::Set(y)) => x == y,
+            (Value::Some(x), Value::Some(y)) => x == y,
+            (Value::None, Value::None) => true,
+            (Value::Native(x), Value::Native(y)) => x.ptr_eq(y),
+            // Including functions: equals() reports an error for those, but
+            // == has to answer something
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Value {}
+
+/// Hash a value on its own, for combining hashes in an order that doesn't matter
+fn hash_one(v: &Value) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    v.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Hashing agrees with ==, so e.g. 1 and 1.0 hash the same, and dicts and
+/// sets hash the same whatever order their entries are in
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Value::Integer(x) => {
+                0u8.hash(state);
+                i64::from(*x).hash(state);
+            },
+            Value::DoublePrecisionFloat(x) => {
+                if x.fract() == 0.0 && x.abs() < i64::MAX as f64 {
+                    0u8.hash(state);
+                    (*x as i64).hash(state);
+                } else {
+                    1u8.hash(state);
+                    // Every NaN is equal, so they all hash the same
+                    if !x.is_nan() {
+                        x.to_bits().hash(state);
+                    }
+                }
+            },
+            Value::Str(s) => {
+                2u8.hash(state);
+                s.hash(state);
+            },
+            Value::Bool(b) => {
+                3u8.hash(state);
+                b.hash(state);
+            },
+            Value::List(xs) => {
+                4u8.hash(state);
+                xs.hash(state);
+            },
+            Value::Dict(d) => {
+                5u8.hash(state);
+                d.len().hash(state);
+                let entries = d.iter()
+                    .map(|(k, v)| hash_one(k).wrapping_mul(31).wrapping_add(hash_one(v)))
+                    .fold(0u64, u64::wrapping_add);
+                entries.hash(state);
+            },
+            Value::Set(s) => {
+                6u8.hash(state);
+                s.len().hash(state);
+                s.iter().map(hash_one).fold(0u64, u64::wrapping_add).hash(state);
+            },
+            Value::Some(x) => {
+                7u8.hash(state);
+                x.hash(state);
+            },
+            Value::None => 8u8.hash(state),
+            Value::Native(obj) => {
+                9u8.hash(state);
+                obj.id().hash(state);
+            },
+            Value::Function(_) | Value::NativeFunction(_) => 10u8.hash(state),
+        }
+    }
+}
+
+/// Unary operator handler
+pub fn unary(op: Op, rhs: Value) -> Result<Value, IrisError> {
+    match (&op, rhs) {
+        (Op::Negation, Value::Integer(x)) => Ok(Value::Integer(x.checked_neg().ok_or_else(|| overflow(&op, x, None))?)),
+        (Op::Negation, Value::DoublePrecisionFloat(x)) => Ok(Value::DoublePrecisionFloat(-x)),
+        (Op::Not, Value::Bool(b)) => Ok(Value::Bool(!b)),
+        (Op::BitwiseNot, Value::Integer(x)) => Ok(Value::Integer(!x)),
+        (_, rhs) => Err(IrisError::runtime(format!("Can't {:?} {:?}", op, rhs))),
+    }
+}
+
+/// Integer infix operator handler. Results that don't fit in an int are
+/// errors rather than wrapping around.
+fn infix_int(a: i32, op: Op, rhs: Value) -> Result<Value, IrisError> {
+    let res = match rhs {
+        Value::Integer(b) => {
+            let res = match op {
+                Op::Add => a.checked_add(b),
+                Op::Sub => a.checked_sub(b),
+                Op::Mul => a.checked_mul(b),
+                Op::Div if b == 0 => return Err(division_by_zero()),
+                Op::Div => a.checked_div(b),
+                Op::Mod if b == 0 => return Err(division_by_zero()),
+                Op::Mod => a.checked_rem(b),
+                Op::Shr => u32::try_from(b).ok().and_then(|b| a.checked_shr(b)),
+                Op::Shl => u32::try_from(b).ok().and_then(|b| a.checked_shl(b)),
+                Op::BitwiseAnd => Some(a & b),
+                Op::BitwiseOr => Some(a | b),
+                Op::BitwiseXor => Some(a ^ b),
+                Op::Exp if b < 0 => {
+                    return Err(IrisError::runtime(format!(
+                        "can't raise int {} to the negative power {}, use a float", a, b,
+                    )));
+                },
+                Op::Exp => a.checked_pow(b as u32),
+                _ => return Err(IrisError::runtime(format!("{:?} not implemented", op))),
+            };
+            Value::Integer(res.ok_or_else(|| overflow(&op, a, Some(b)))?)
         },
-        _ => panic!("Can't {:?} int {} with {:?}", op, a, rhs),
+        // Mixed arithmetic is done in floating point
+        Value::DoublePrecisionFloat(_) => return infix_float(a as f64, op, rhs),
+        _ => return Err(IrisError::runtime(format!("Can't {:?} int {} with {:?}", op, a, rhs))),
+    };
+    Ok(res)
+}
+
+/// Float infix operator handler
+fn infix_float(a: f64, op: Op, rhs: Value) -> Result<Value, IrisError> {
+    let b = match rhs {
+        Value::DoublePrecisionFloat(b) => b,
+        Value::Integer(b) => b as f64,
+        _ => return Err(IrisError::runtime(format!("Can't {:?} float {} with {:?}", op, a, rhs))),
+    };
+    let res = match op {
+        Op::Add => Value::DoublePrecisionFloat(a + b),
+        Op::Sub => Value::DoublePrecisionFloat(a - b),
+        Op::Mul => Value::DoublePrecisionFloat(a * b),
+        Op::Div => Value::DoublePrecisionFloat(a / b),
+        Op::Mod => Value::DoublePrecisionFloat(a % b),
+        Op::Exp => Value::DoublePrecisionFloat(a.powf(b)),
+        _ => return Err(IrisError::runtime(format!("{:?} not implemented for float", op))),
+    };
+    Ok(res)
+}
+
+fn division_by_zero() -> IrisError {
+    IrisError::runtime("division by zero")
+}
+
+fn overflow(op: &Op, a: i32, b: Option<i32>) -> IrisError {
+    match b {
+        Some(b) => IrisError::runtime(format!("int overflow: {:?} int {} with {}", op, a, b)),
+        None => IrisError::runtime(format!("int overflow: {:?} int {}", op, a)),
     }
 }
 
 /// Infix operator handler for list. Just support list addition for now
-fn infix_list(a: Vec<Value>, op: Op, r: Value) -> Value {
+fn infix_list(a: Vec<Value>, op: Op, r: Value) -> Result<Value, IrisError> {
     match r {
-        Value::List(_) => match op {
-            _ => panic!("{:?} not implemented for list, list", op),
-        },
+        Value::List(_) => Err(IrisError::runtime(format!("{:?} not implemented for list, list", op))),
         Value::Integer(b) => match op {
             Op::MemberAccess => {
-                a.get(b as usize).expect(&format!("index {} is out of bounds", b)).clone()
+                a.get(b as usize)
+                    .cloned()
+                    .ok_or_else(|| IrisError::runtime(format!("index {} is out of bounds", b)))
             },
-            _ => panic!("{:?} not implemented for (list, int)", op),
+            _ => Err(IrisError::runtime(format!("{:?} not implemented for (list, int)", op))),
         }
-        _ => panic!("Can't {:?} list with {:?}", op, r),
+        _ => Err(IrisError::runtime(format!("Can't {:?} list with {:?}", op, r))),
     }
 }
 
-/// Infix operator handler for string. Just support addition for now
-fn infix_str(a: String, op: Op, rhs: Value) -> Value {
+/// Bool infix operator handler. `and` and `or` short circuit in eval before
+/// they get here, so this just sees the values.
+fn infix_bool(a: bool, op: Op, rhs: Value) -> Result<Value, IrisError> {
+    match (&op, rhs) {
+        (Op::And, Value::Bool(b)) => Ok(Value::Bool(a && b)),
+        (Op::Or, Value::Bool(b)) => Ok(Value::Bool(a || b)),
+        (_, rhs) => Err(IrisError::runtime(format!("Can't {:?} bool {} with {:?}", op, a, rhs))),
+    }
+}
+
+/// Infix operator handler for string. Just support addition and indexing for now
+fn infix_str(a: String, op: Op, rhs: Value) -> Result<Value, IrisError> {
     let res = match rhs {
         Value::Str(b) => match op {
             Op::Add => a + &b,
-            _ => panic!("operator {:?} not defined for string", op),
+            _ => return Err(IrisError::runtime(format!("operator {:?} not defined for string", op))),
         },
-        _ => panic!("Can't {:?} string {} with {:?}", op, a, rhs),
+        Value::Integer(b) if op == Op::MemberAccess => {
+            // Strings are indexed by char, not by byte
+            a.chars()
+                .nth(b as usize)
+                .filter(|_| b >= 0)
+                .ok_or_else(|| IrisError::runtime(format!("index {} is out of bounds", b)))?
+                .to_string()
+        },
+        _ => return Err(IrisError::runtime(format!("Can't {:?} string {} with {:?}", op, a, rhs))),
     };
-    Value::Str(res)
-}
\ No newline at end of file
+    Ok(Value::Str(res))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int_op(a: i32, op: Op, b: i32) -> Result<Value, IrisError> {
+        infix(Value::Integer(a), op, Value::Integer(b))
+    }
+
+    #[test]
+    fn int_overflow_is_an_error() {
+        assert!(int_op(i32::MAX, Op::Add, 1).is_err());
+        assert!(int_op(i32::MIN, Op::Sub, 1).is_err());
+        assert!(int_op(i32::MAX, Op::Mul, 2).is_err());
+        assert!(int_op(i32::MIN, Op::Div, -1).is_err());
+        assert!(int_op(i32::MIN, Op::Mod, -1).is_err());
+        assert!(int_op(2, Op::Exp, 31).is_err());
+        assert!(unary(Op::Negation, Value::Integer(i32::MIN)).is_err());
+    }
+
+    #[test]
+    fn shifts_past_the_width_are_errors() {
+        assert!(int_op(1, Op::Shl, 40).is_err());
+        assert!(int_op(1, Op::Shl, -1).is_err());
+        assert!(int_op(1, Op::Shr, 32).is_err());
+        assert_eq!(int_op(1, Op::Shl, 3), Ok(Value::Integer(8)));
+        assert_eq!(int_op(-8, Op::Shr, 1), Ok(Value::Integer(-4)));
+    }
+
+    #[test]
+    fn negative_int_exponents_are_errors() {
+        assert!(int_op(2, Op::Exp, -1).is_err());
+        assert_eq!(int_op(2, Op::Exp, 10), Ok(Value::Integer(1024)));
+        assert_eq!(int_op(-2, Op::Exp, 0), Ok(Value::Integer(1)));
+    }
+
+    #[test]
+    fn division_by_zero_is_an_error() {
+        assert_eq!(int_op(1, Op::Div, 0), Err(division_by_zero()));
+        assert_eq!(int_op(1, Op::Mod, 0), Err(division_by_zero()));
+        assert_eq!(int_op(-7, Op::Div, 2), Ok(Value::Integer(-3)));
+    }
+}
@@ -0,0 +1,214 @@
+//! Regular expressions. Patterns can be passed as strings, or compiled once
+//! with regex(pattern) into a Regex value that can be stored and reused.
+
+use regex::{Captures, Regex};
+
+use crate::error::IrisError;
+use super::{Dict, Value};
+use super::builtins::{expect_args, expect_str};
+use super::registry::{NativeObject, Registry};
+
+/// The type name of compiled regexes, for declaring methods with `for Regex`
+pub const REGEX_TYPE: &str = "Regex";
+
+pub fn register(registry: &mut Registry) {
+    registry.register_fn("regex", compile);
+    registry.register_fn("re_match", re_match);
+    registry.register_fn("re_find_all", re_find_all);
+    registry.register_fn("re_replace", re_replace);
+    registry.register_fn("re_split", re_split);
+}
+
+fn new_regex(pattern: &str) -> Result<Regex, IrisError> {
+    Regex::new(pattern).map_err(|e| IrisError::runtime(format!("invalid regex: {}", e)))
+}
+
+/// Run f with the regex for the given pattern string or compiled Regex value
+fn with_regex<T, F>(name: &str, val: &Value, f: F) -> Result<T, IrisError>
+where F: FnOnce(&Regex) -> Result<T, IrisError> {
+    match val {
+        Value::Str(pattern) => f(&new_regex(pattern)?),
+        Value::Native(obj) if obj.type_name == REGEX_TYPE => {
+            f(obj.downcast_ref::<Regex>().expect("Regex values always hold a regex::Regex"))
+        },
+        _ => Err(IrisError::runtime(format!("{}() expected a pattern or Regex, got {:?}", name, val))),
+    }
+}
+
+/// The capture groups of a match, starting with the whole match. They're a
+/// list, unless the pattern names any of its groups, like `(?P<year>\d+)`.
+/// Then they're a dict keyed by name, with unnamed groups keyed by number.
+/// Groups that didn't take part in the match are None.
+fn groups(re: &Regex, caps: &Captures) -> Value {
+    let matched = caps.iter().map(|group| match group {
+        Some(m) => Value::Str(m.as_str().to_string()),
+        None => Value::None,
+    });
+    if re.capture_names().all(|name| name.is_none()) {
+        return Value::List(matched.collect());
+    }
+    let mut d = Dict::new();
+    for ((idx, name), val) in re.capture_names().enumerate().zip(matched) {
+        let key = match name {
+            Some(name) => Value::Str(name.to_string()),
+            None => Value::Integer(idx as i32),
+        };
+        d.insert(key, val).expect("group names and numbers are hashable");
+    }
+    Value::Dict(d)
+}
+
+/// regex(pattern) compiles a pattern for reuse
+pub fn compile(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("regex", args, 1)?;
+    let re = new_regex(expect_str("regex", &args[0])?)?;
+    Ok(Value::Native(NativeObject::new(REGEX_TYPE, re)))
+}
+
+/// re_match(pattern, s) finds the first match anywhere in s, giving
+/// Some([whole match, group 1, group 2, ...]) or None. With named groups
+/// it's Some({0: whole match, "name": group, ...}).
+pub fn re_match(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("re_match", args, 2)?;
+    let s = expect_str("re_match", &args[1])?;
+    with_regex("re_match", &args[0], |re| {
+        Ok(match re.captures(s) {
+            Some(caps) => Value::Some(Box::new(groups(re, &caps))),
+            None => Value::None,
+        })
+    })
+}
+
+/// re_find_all(pattern, s) lists every match. If the pattern has capture
+/// groups, each match is the groups like re_match gives; otherwise
+/// it's just the matched string.
+pub fn re_find_all(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("re_find_all", args, 2)?;
+    let s = expect_str("re_find_all", &args[1])?;
+    with_regex("re_find_all", &args[0], |re| {
+        let matches = if re.captures_len() > 1 {
+            re.captures_iter(s).map(|caps| groups(re, &caps)).collect()
+        } else {
+            re.find_iter(s).map(|m| Value::Str(m.as_str().to_string())).collect()
+        };
+        Ok(Value::List(matches))
+    })
+}
+
+/// re_replace(pattern, s, replacement) replaces every match. The replacement
+/// can refer to groups as $1 or ${name}.
+pub fn re_replace(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("re_replace", args, 3)?;
+    let s = expect_str("re_replace", &args[1])?;
+    let replacement = expect_str("re_replace", &args[2])?;
+    with_regex("re_replace", &args[0], |re| {
+        Ok(Value::Str(re.replace_all(s, replacement).into_owned()))
+    })
+}
+
+/// re_split(pattern, s) splits s on every match
+pub fn re_split(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("re_split", args, 2)?;
+    let s = expect_str("re_split", &args[1])?;
+    with_regex("re_split", &args[0], |re| {
+        Ok(Value::List(re.split(s).map(|part| Value::Str(part.to_string())).collect()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strs(xs: &[&str]) -> Value {
+        Value::List(xs.iter().map(|&s| Value::from(s)).collect())
+    }
+
+    fn call(f: fn(&[Value]) -> Result<Value, IrisError>, args: &[&str]) -> Result<Value, IrisError> {
+        f(&args.iter().map(|&s| Value::from(s)).collect::<Vec<_>>())
+    }
+
+    fn dict(entries: Vec<(Value, Value)>) -> Value {
+        let mut d = Dict::new();
+        for (k, v) in entries {
+            d.insert(k, v).unwrap();
+        }
+        Value::Dict(d)
+    }
+
+    #[test]
+    fn unnamed_groups_are_a_list() {
+        assert_eq!(
+            call(re_match, &[r"(\w+)@(\w+)", "mail bob@example now"]),
+            Ok(Value::Some(Box::new(strs(&["bob@example", "bob", "example"])))),
+        );
+        assert_eq!(call(re_match, &[r"\d+", "abc"]), Ok(Value::None));
+        // Groups that didn't match are None
+        assert_eq!(
+            call(re_match, &["a(b)?", "a"]),
+            Ok(Value::Some(Box::new(Value::List(vec![Value::from("a"), Value::None])))),
+        );
+    }
+
+    #[test]
+    fn named_groups_are_a_dict() {
+        let expected = dict(vec![
+            (Value::Integer(0), Value::from("2024-05")),
+            (Value::from("year"), Value::from("2024")),
+            (Value::from("month"), Value::from("05")),
+        ]);
+        assert_eq!(
+            call(re_match, &[r"(?P<year>\d+)-(?P<month>\d+)", "on 2024-05"]),
+            Ok(Value::Some(Box::new(expected))),
+        );
+        // Unnamed groups alongside named ones are keyed by number
+        let expected = dict(vec![
+            (Value::Integer(0), Value::from("a1")),
+            (Value::Integer(1), Value::from("a")),
+            (Value::from("digit"), Value::from("1")),
+            (Value::from("rest"), Value::None),
+        ]);
+        assert_eq!(
+            call(re_match, &[r"(\w)(?P<digit>\d)(?P<rest>x)?", "a1"]),
+            Ok(Value::Some(Box::new(expected))),
+        );
+    }
+
+    #[test]
+    fn find_all_gives_strings_or_groups() {
+        assert_eq!(call(re_find_all, &[r"\d+", "1 22 333"]), Ok(strs(&["1", "22", "333"])));
+        assert_eq!(call(re_find_all, &[r"\d+", ""]), Ok(strs(&[])));
+        assert_eq!(
+            call(re_find_all, &[r"(\w)=(\d)", "a=1 b=2"]),
+            Ok(Value::List(vec![strs(&["a=1", "a", "1"]), strs(&["b=2", "b", "2"])])),
+        );
+        let found = call(re_find_all, &[r"(?P<k>\w)=\d", "a=1 b=2"]).unwrap();
+        let expected = Value::List(vec![
+            dict(vec![(Value::Integer(0), Value::from("a=1")), (Value::from("k"), Value::from("a"))]),
+            dict(vec![(Value::Integer(0), Value::from("b=2")), (Value::from("k"), Value::from("b"))]),
+        ]);
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn replace_and_split() {
+        assert_eq!(call(re_replace, &[r"(?P<w>\w+)@", "bob@x", "${w} at "]), Ok(Value::from("bob at x")));
+        assert_eq!(call(re_replace, &[r"(\d)", "a1b2", "<$1>"]), Ok(Value::from("a<1>b<2>")));
+        assert_eq!(call(re_split, &[r"\s*,\s*", "a , b,c"]), Ok(strs(&["a", "b", "c"])));
+    }
+
+    #[test]
+    fn compiled_regexes_work_like_patterns() {
+        let re = compile(&[Value::from(r"(?P<n>\d+)")]).unwrap();
+        let found = re_match(&[re, Value::from("x42")]).unwrap();
+        let expected = dict(vec![(Value::Integer(0), Value::from("42")), (Value::from("n"), Value::from("42"))]);
+        assert_eq!(found, Value::Some(Box::new(expected)));
+    }
+
+    #[test]
+    fn bad_patterns_are_errors() {
+        let err = call(re_match, &["(", "a"]).unwrap_err();
+        assert!(err.to_string().contains("invalid regex"), "{}", err);
+        assert!(compile(&[Value::from("[")]).is_err());
+        assert!(re_split(&[Value::Integer(1), Value::from("a")]).is_err());
+    }
+}
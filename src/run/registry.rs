@@ -0,0 +1,184 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::error::IrisError;
+use super::Value;
+use super::{assertions, builtins, dict, format, io, iter, json, lists, re, strings};
+use super::scope::Scope;
+use super::typing::{TypeDefinition, most_specific};
+
+/// The signature of every function implemented in Rust, builtin or host-provided
+pub type NativeFn = Rc<dyn Fn(&[Value]) -> Result<Value, IrisError>>;
+
+/// Builtins that need the calling scope, e.g. to find Iter methods
+pub(crate) type ScopedFn = Rc<dyn Fn(&Rc<RefCell<Scope>>, &[Value]) -> Result<Value, IrisError>>;
+
+#[derive(Clone)]
+enum NativeImpl {
+    Plain(NativeFn),
+    Scoped(ScopedFn),
+}
+
+/// A function implemented in Rust that can be called from Iris
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub name: String,
+    imp: NativeImpl,
+}
+
+impl NativeFunction {
+    /// Make a function value out of a Rust closure, e.g. to return one to a script
+    pub fn new<F>(name: &str, func: F) -> NativeFunction
+    where F: Fn(&[Value]) -> Result<Value, IrisError> + 'static {
+        NativeFunction { name: name.to_string(), imp: NativeImpl::Plain(Rc::new(func)) }
+    }
+
+    /// Call the function from Rust. A few builtins need to be called from
+    /// within Iris, and give an error here.
+    pub fn call(&self, args: &[Value]) -> Result<Value, IrisError> {
+        match &self.imp {
+            NativeImpl::Plain(func) => func(args),
+            NativeImpl::Scoped(_) => Err(IrisError::runtime(format!(
+                "{}() can only be called from Iris code", self.name,
+            ))),
+        }
+    }
+
+    /// Call the function from Iris code running in the given scope
+    pub(crate) fn call_in(&self, scope: &Rc<RefCell<Scope>>, args: &[Value]) -> Result<Value, IrisError> {
+        match &self.imp {
+            NativeImpl::Plain(func) => func(args),
+            NativeImpl::Scoped(func) => func(scope, args),
+        }
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NativeFunction({:?})", self.name)
+    }
+}
+
+/// An opaque handle to a Rust object, such as a database connection, passed
+/// into a script by the host. Scripts can hold on to it, pass it around, and
+/// call methods registered for its type name, but can't look inside.
+#[derive(Clone)]
+pub struct NativeObject {
+    pub type_name: String,
+    object: Rc<dyn Any>,
+}
+
+impl NativeObject {
+    pub fn new<T: Any>(type_name: &str, object: T) -> NativeObject {
+        NativeObject { type_name: type_name.to_string(), object: Rc::new(object) }
+    }
+
+    /// Get at the wrapped object, if it's of type T
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.object.downcast_ref::<T>()
+    }
+
+    /// Native objects are equal only if they're the same object
+    pub fn ptr_eq(&self, other: &NativeObject) -> bool {
+        Rc::ptr_eq(&self.object, &other.object)
+    }
+
+    /// An id for the object, unique while it's alive
+    pub fn id(&self) -> usize {
+        Rc::as_ptr(&self.object) as *const () as usize
+    }
+}
+
+impl fmt::Debug for NativeObject {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NativeObject({:?})", self.type_name)
+    }
+}
+
+/// Functions and methods implemented in Rust. This holds the builtins, and
+/// anything a host program registers when embedding Iris.
+pub struct Registry {
+    functions: HashMap<String, NativeFunction>,
+    methods: HashMap<(String, TypeDefinition), NativeFunction>,
+}
+
+impl Default for Registry {
+    fn default() -> Registry {
+        Registry::new()
+    }
+}
+
+impl fmt::Debug for Registry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut names: Vec<&String> = self.functions.keys().collect();
+        names.sort();
+        write!(f, "Registry({:?})", names)
+    }
+}
+
+impl Registry {
+    /// Create a registry holding all the builtins
+    pub fn new() -> Registry {
+        let mut registry = Registry { functions: HashMap::new(), methods: HashMap::new() };
+        builtins::register(&mut registry);
+        strings::register(&mut registry);
+        re::register(&mut registry);
+        lists::register(&mut registry);
+        iter::register(&mut registry);
+        dict::register(&mut registry);
+        io::register(&mut registry);
+        json::register(&mut registry);
+        format::register(&mut registry);
+        assertions::register(&mut registry);
+        registry
+    }
+
+    /// Register a global function, replacing any existing one with the same name
+    pub fn register_fn<F>(&mut self, name: &str, func: F)
+    where F: Fn(&[Value]) -> Result<Value, IrisError> + 'static {
+        self.functions.insert(name.to_string(), NativeFunction::new(name, func));
+    }
+
+    /// Register a builtin that needs the calling scope
+    pub(crate) fn register_scoped_fn<F>(&mut self, name: &str, func: F)
+    where F: Fn(&Rc<RefCell<Scope>>, &[Value]) -> Result<Value, IrisError> + 'static {
+        let native = NativeFunction { name: name.to_string(), imp: NativeImpl::Scoped(Rc::new(func)) };
+        self.functions.insert(name.to_string(), native);
+    }
+
+    /// Register a method for the given type
+    pub fn register_method<F>(&mut self, name: &str, for_type: TypeDefinition, func: F)
+    where F: Fn(&[Value]) -> Result<Value, IrisError> + 'static {
+        self.methods.insert((name.to_string(), for_type), NativeFunction::new(name, func));
+    }
+
+    pub fn get_fn(&self, name: &str) -> Option<NativeFunction> {
+        self.functions.get(name).cloned()
+    }
+
+    /// Find the method to call for the given args, optionally only out of
+    /// those for the given type or more specific kinds of it
+    pub fn get_method(
+        &self,
+        name: &str,
+        args: &[Value],
+        for_type: Option<&TypeDefinition>,
+    ) -> Result<Option<NativeFunction>, IrisError> {
+        let candidates = self.methods.iter()
+            .filter(|((n, typ), _)| n == name && for_type.is_none_or(|t| typ.is_within(t)))
+            .map(|((_, typ), f)| (std::slice::from_ref(typ), f));
+        Ok(most_specific(name, candidates, args)?.cloned())
+    }
+
+    pub fn has_method(&self, name: &str) -> bool {
+        self.methods.keys().any(|(n, _)| n == name)
+    }
+
+    /// The names of all the functions and methods, in no particular order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.functions.keys().chain(self.methods.keys().map(|(name, _)| name)).map(String::as_str)
+    }
+}
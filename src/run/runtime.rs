@@ -0,0 +1,231 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::error::IrisError;
+use super::capabilities::Capabilities;
+use super::limits::{Limits, allocated_bytes};
+use super::registry::Registry;
+use super::scope::Scope;
+use super::Value;
+
+/// Bookkeeping for a running program. Every Scope holds a reference to the
+/// same Runtime, so it can be reached from wherever we are in the program.
+#[derive(Debug, Default)]
+pub struct Runtime {
+    pub limits: Limits,
+
+    /// What the program can access outside the interpreter
+    pub capabilities: Capabilities,
+
+    /// Arguments passed to the script, for args()
+    pub args: Vec<String>,
+
+    /// Builtins and host-provided functions
+    pub registry: Registry,
+
+    /// Where the statement being run starts, as an offset into the source.
+    /// After an error, it's where the error happened.
+    pub position: usize,
+
+    /// Where each line of the source being run starts, to turn positions
+    /// into line numbers
+    pub line_starts: Vec<usize>,
+
+    /// The function calls and loops currently in progress, innermost last
+    frames: Vec<Frame>,
+
+    /// Number of function calls currently in progress
+    depth: usize,
+
+    /// The frames an error was raised in, and where, saved as it unwinds
+    /// out of the innermost one
+    traceback: Option<(Vec<Frame>, usize)>,
+
+    /// Where the statement that made the program fail starts, once it has
+    pub failed_at: Option<usize>,
+
+    /// Told about each statement before it runs, if set. This is how the
+    /// debugger gets control.
+    pub hook: Option<Rc<RefCell<dyn Hook>>>,
+
+    /// Number of expressions evaluated so far
+    steps: u64,
+
+    /// Bytes already allocated before the program started
+    memory_baseline: usize,
+}
+
+impl Runtime {
+    /// Reset the counters, ready to run a new program
+    pub fn start(&mut self) {
+        self.frames.clear();
+        self.depth = 0;
+        self.traceback = None;
+        self.failed_at = None;
+        self.steps = 0;
+        self.memory_baseline = allocated_bytes();
+    }
+
+    /// Record entering a function call from the statement being run,
+    /// failing if we're in too deep
+    pub fn enter_call(&mut self, name: &str) -> Result<(), IrisError> {
+        if self.depth >= self.limits.max_depth {
+            return Err(IrisError::RecursionLimit(self.limits.max_depth));
+        }
+        self.depth += 1;
+        self.frames.push(Frame { name: name.to_string(), call_position: self.position, is_call: true });
+        Ok(())
+    }
+
+    /// Record a call in tail position, which takes the place of the current
+    /// one. It's as if the caller made the call itself.
+    pub fn replace_call(&mut self, name: &str) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.name = name.to_string();
+        }
+    }
+
+    /// Record starting a loop in the statement being run
+    pub fn enter_loop(&mut self, name: &str) {
+        self.frames.push(Frame { name: name.to_string(), call_position: self.position, is_call: false });
+    }
+
+    /// Record leaving the innermost call or loop. If it failed, and the error
+    /// hasn't come out of any other frame yet, save where it happened.
+    /// Otherwise we're back in the statement that made the call or loop.
+    pub fn leave(&mut self, failed: bool) {
+        if failed && self.traceback.is_none() {
+            self.traceback = Some((self.frames.clone(), self.position));
+        }
+        if let Some(frame) = self.frames.pop() {
+            if frame.is_call {
+                self.depth -= 1;
+            }
+            if !failed {
+                self.position = frame.call_position;
+            }
+        }
+    }
+
+    /// Forget an error that's been caught, and carry on with the statement
+    /// at `position`, which caught it
+    pub fn caught(&mut self, position: usize) {
+        self.traceback = None;
+        self.position = position;
+    }
+
+    /// Note the start of a statement. An error can't be on its way out of
+    /// any frames while a statement starts, so if one was, it was caught.
+    pub fn enter_stmt(&mut self, position: usize) {
+        self.position = position;
+        self.traceback = None;
+    }
+
+    /// The calls and loops in progress, innermost last
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// Number of function calls in progress
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// The line a position is on, counting from 1
+    pub fn line(&self, position: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= position).max(1)
+    }
+
+    /// Describe where each frame had got to, given where the innermost one is
+    /// up to. Outermost first, starting from the top level.
+    pub fn describe(&self, frames: &[Frame], position: usize) -> Vec<String> {
+        let names = std::iter::once("<top level>").chain(frames.iter().map(|frame| frame.name.as_str()));
+        let positions = frames.iter().map(|frame| frame.call_position).chain(std::iter::once(position));
+        names.zip(positions)
+            .map(|(name, position)| format!("line {}, in {}", self.line(position), name))
+            .collect()
+    }
+
+    /// Where the error on its way out happened: the statement it was raised
+    /// in, even if that's in a call that's since been left
+    pub fn error_position(&self) -> usize {
+        self.traceback.as_ref().map_or(self.position, |(_, position)| *position)
+    }
+
+    /// The traceback of the error on its way out, outermost frame first, if
+    /// it came out of any frames
+    pub fn take_traceback(&mut self) -> Option<Vec<String>> {
+        let (frames, position) = self.traceback.take()?;
+        Some(self.describe(&frames, position))
+    }
+
+    /// Count one evaluation step against the step and memory budgets
+    pub fn step(&mut self) -> Result<(), IrisError> {
+        self.steps += 1;
+        if let Some(max_steps) = self.limits.max_steps {
+            if self.steps > max_steps {
+                return Err(IrisError::StepLimit(max_steps));
+            }
+        }
+        self.reserve(0)
+    }
+
+    /// Check a value isn't nested too deeply. Every list, dict, set and Some
+    /// is made by a builtin, so checking what they return keeps every value
+    /// within the limit, and this never has to look deeper than that.
+    pub fn check_nesting(&self, val: &Value) -> Result<(), IrisError> {
+        if nested_deeper_than(val, self.limits.max_nesting) {
+            return Err(IrisError::NestingLimit(self.limits.max_nesting));
+        }
+        Ok(())
+    }
+
+    /// Check there's room in the memory budget for another `bytes`, before
+    /// allocating them all in one go
+    pub fn reserve(&self, bytes: usize) -> Result<(), IrisError> {
+        if let Some(max_memory) = self.limits.max_memory {
+            let in_use = allocated_bytes().saturating_sub(self.memory_baseline);
+            if in_use.saturating_add(bytes) > max_memory {
+                return Err(IrisError::MemoryLimit(max_memory));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether a value has containers nested more than `max` deep inside it
+fn nested_deeper_than(val: &Value, max: usize) -> bool {
+    let mut items: Box<dyn Iterator<Item = &Value>> = match val {
+        Value::List(xs) => Box::new(xs.iter()),
+        Value::Set(s) => Box::new(s.iter()),
+        Value::Dict(d) => Box::new(d.iter().flat_map(|(k, v)| vec![k, v])),
+        Value::Some(x) => Box::new(std::iter::once(&**x)),
+        _ => return false,
+    };
+    max == 0 || items.any(|x| nested_deeper_than(x, max - 1))
+}
+
+/// A function call or loop in progress
+#[derive(Clone, Debug)]
+pub struct Frame {
+    /// What's running, from the context of its Scope
+    pub name: String,
+    /// Where the statement making the call or loop starts
+    pub call_position: usize,
+    /// Whether it's a call rather than a loop
+    pub is_call: bool,
+}
+
+/// Something to be told about each statement before it runs
+pub trait Hook {
+    /// Called with where the statement starts, and the scope it runs in.
+    /// Returning an error stops the program with it.
+    fn before_stmt(&mut self, position: usize, scope: &Rc<RefCell<Scope>>) -> Result<(), IrisError>;
+}
+
+impl fmt::Debug for dyn Hook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Hook")
+    }
+}
@@ -2,7 +2,9 @@ use std::collections::HashMap;
 use std::rc::Rc;
 use std::cell::RefCell;
 
+use crate::error::IrisError;
 use super::Value;
+use super::runtime::Runtime;
 use super::typing::*;
 
 /// All program state is stored in a Scope.
@@ -19,14 +21,23 @@ pub struct Scope {
     /// The context can be set to things like the function name, for better error messages
     context: String,
 
+    /// Shared with every other Scope in the program
+    runtime: Rc<RefCell<Runtime>>,
+
     /// Lookups for this scope's variables and methods
     vars: HashMap<String, Value>,
-    methods: HashMap<(String, TypeDefinition), Function>,
+    methods: HashMap<(String, Vec<TypeDefinition>), Function>,
 }
 
 impl Scope {
     pub fn new(context: String) -> Scope {
-        Scope{ context, vars: HashMap::new(), methods: HashMap::new(), parent: None, }
+        Scope {
+            context,
+            vars: HashMap::new(),
+            methods: HashMap::new(),
+            parent: None,
+            runtime: Rc::new(RefCell::new(Runtime::default())),
+        }
     }
 
     /// Create a new Scope as a child of the given Scope.
@@ -34,6 +45,7 @@ impl Scope {
     /// to an existing RefCell, so we accept that instead
     pub fn nest(parent: &Rc<RefCell<Scope>>, context: &str) -> Scope  {
         Scope {
+            runtime: Rc::clone(&parent.borrow().runtime),
             parent: Some(Rc::clone(parent)),
             context: context.to_string(),
             vars: HashMap::new(),
@@ -41,26 +53,54 @@ impl Scope {
         }
     }
 
+    /// What the scope is for, like the name of a function or "for loop"
+    pub fn context(&self) -> &str {
+        &self.context
+    }
+
+    /// The scope this one is nested in, if it isn't the global scope
+    pub fn parent(&self) -> Option<Rc<RefCell<Scope>>> {
+        self.parent.clone()
+    }
+
+    /// The variables declared in this scope itself, sorted by name
+    pub fn vars(&self) -> Vec<(&str, &Value)> {
+        let mut vars: Vec<(&str, &Value)> = self.vars.iter().map(|(name, val)| (name.as_str(), val)).collect();
+        vars.sort_by_key(|(name, _)| *name);
+        vars
+    }
+
+    /// The Runtime shared by all scopes of this program
+    pub fn runtime(&self) -> Rc<RefCell<Runtime>> {
+        Rc::clone(&self.runtime)
+    }
+
     /// Declare a variable in the current scope, unless it has already been declared
-    pub fn declare_var(&mut self, name: &str, val: Value) {
-        assert!(!self.vars.contains_key(name), format!("can't redeclare var {}", name));
+    pub fn declare_var(&mut self, name: &str, val: Value) -> Result<(), IrisError> {
+        if self.vars.contains_key(name) {
+            return Err(IrisError::runtime(format!("can't redeclare var {}", name)));
+        }
         self.vars.insert(name.to_string(), val);
+        Ok(())
     }
 
     /// Assign a new value to an existing variable, which may exist in this Scope or
     /// one of its parents
-    pub fn set_var(&mut self, name: &str, val: Value) {
+    pub fn set_var(&mut self, name: &str, val: Value) -> Result<(), IrisError> {
         if self.vars.contains_key(name) {
             self.vars.insert(name.to_string(), val);
+            Ok(())
         } else {
             // Walk up the hierarchy to check if the variable exists in a parent scope
             // This pattern is repeated a few times in other methods
             match &self.parent {
                 Some(p) => {
                     let mut parent = p.borrow_mut();
-                    parent.set_var(name, val);
+                    parent.set_var(name, val)
                 },
-                None => panic!("can't assign to undeclared var \"{}\" | context: {}", name, self.context),
+                None => Err(IrisError::runtime(format!(
+                    "can't assign to undeclared var \"{}\" | context: {}", name, self.context,
+                ))),
             }
         }
     }
@@ -85,32 +125,55 @@ impl Scope {
         self.vars.contains_key(name)
     }
 
-    /// Declare a method in the current scope
-    pub fn declare_method(&mut self, name: &str, for_type: TypeDefinition, func: Function) {
-        self.methods.insert((name.to_string(), for_type), func);
+    /// Check if a variable is set in the parent of this scope. For a function's
+    /// scope, that's the scope the function was defined in.
+    pub fn parent_var_is_set(&self, name: &str) -> bool {
+        match &self.parent {
+            Some(p) => p.borrow().var_is_set(name),
+            None => false,
+        }
     }
 
-    /// Search for a method in the current and parent scopes
-    pub fn get_method(&self, name: &str, typ: TypeDefinition) -> Option<Function> {
-        let search = self.methods.get(&(name.to_string(), typ.clone()));
-        match search {
-            Some(f) => Some(f.clone()),
-            None => match &self.parent {
-                Some(p) => {
-                    let parent = p.borrow();
-                    parent.get_method(name, typ)
-                },
-                None => None,
-            },
+    /// Declare a method in the current scope, for the types of its first args
+    pub fn declare_method(&mut self, name: &str, for_types: Vec<TypeDefinition>, func: Function) {
+        self.methods.insert((name.to_string(), for_types), func);
+    }
+
+    /// Search for a method for the given args, in the current scope and
+    /// then in parent scopes. If a type is given, only methods whose first
+    /// arg is for that type, or a more specific kind of it like list[int] for
+    /// list, are considered.
+    pub fn get_method(
+        &self,
+        name: &str,
+        args: &[Value],
+        for_type: Option<&TypeDefinition>,
+    ) -> Result<Option<Function>, IrisError> {
+        let candidates = self.methods.iter()
+            .filter(|((n, types), _)| n == name && for_type.is_none_or(|t| types.first().is_some_and(|first| first.is_within(t))))
+            .map(|((_, types), f)| (types.as_slice(), f));
+        if let Some(f) = most_specific(name, candidates, args)? {
+            return Ok(Some(f.clone()));
+        }
+        match &self.parent {
+            Some(p) => p.borrow().get_method(name, args, for_type),
+            None => Ok(None),
         }
     }
 
+    /// Whether there's a method with this name, for any types
+    pub fn has_method(&self, name: &str) -> bool {
+        self.methods.keys().any(|(n, _)| n == name)
+            || self.parent.as_ref().is_some_and(|p| p.borrow().has_method(name))
+    }
+
     /// A special case of get_var, for better error reporting
-    pub fn get_fn(&self, name: &str) -> Function {
-        let val = self.get_var(name).expect(&format!("unknown function {}", name));
+    pub fn get_fn(&self, name: &str) -> Result<Value, IrisError> {
+        let val = self.get_var(name)
+            .ok_or_else(|| IrisError::runtime(format!("unknown function {}", name)))?;
         match val {
-            Value::Function(f) => f,
-            _ => panic!("{:?} is not a function", val)
+            Value::Function(_) | Value::NativeFunction(_) => Ok(val),
+            _ => Err(IrisError::runtime(format!("{:?} is not a function", val))),
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,225 @@
+//! The string standard library. Like every builtin, these can be called
+//! either as `upper(s)` or as `s.upper()`.
+//! Indexes are counted in chars rather than bytes, to match len() and slice().
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::error::IrisError;
+use super::Value;
+use super::builtins::{expect_args, expect_args_between, expect_int, expect_list, expect_str, fmt, reserve};
+use super::iter;
+use super::registry::Registry;
+use super::scope::Scope;
+
+pub fn register(registry: &mut Registry) {
+    registry.register_fn("split", split);
+    registry.register_scoped_fn("join", join);
+    registry.register_fn("trim", trim);
+    registry.register_fn("upper", upper);
+    registry.register_fn("lower", lower);
+    registry.register_scoped_fn("replace", replace);
+    registry.register_fn("starts_with", starts_with);
+    registry.register_fn("ends_with", ends_with);
+    registry.register_fn("contains", contains);
+    registry.register_fn("find", find);
+    registry.register_fn("chars", chars);
+    registry.register_scoped_fn("repeat", repeat);
+    registry.register_fn("parse_int", parse_int);
+    registry.register_fn("parse_float", parse_float);
+}
+
+fn str_list<'a, I: Iterator<Item = &'a str>>(parts: I) -> Value {
+    Value::List(parts.map(|s| Value::Str(s.to_string())).collect())
+}
+
+/// split(s, sep) splits on a separator, or on runs of whitespace if sep is left off
+pub fn split(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args_between("split", args, 1, 2)?;
+    let s = expect_str("split", &args[0])?;
+    match args.get(1) {
+        Some(sep) => {
+            let sep = expect_str("split", sep)?;
+            if sep.is_empty() {
+                return Err(IrisError::runtime("split() separator can't be empty"));
+            }
+            Ok(str_list(s.split(sep)))
+        },
+        None => Ok(str_list(s.split_whitespace())),
+    }
+}
+
+/// join(xs, sep) joins a list together, formatting each item like print does
+pub fn join(scope: &Rc<RefCell<Scope>>, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("join", args, 2)?;
+    let xs = expect_list("join", &args[0])?;
+    let sep = expect_str("join", &args[1])?;
+    let seps = sep.len().checked_mul(xs.len().saturating_sub(1));
+    reserve(scope, "join", seps)?;
+    let strs: Vec<String> = xs.iter().map(fmt).collect();
+    let len = strs.iter().try_fold(seps.unwrap_or(0), |len, s| len.checked_add(s.len()));
+    reserve(scope, "join", len)?;
+    Ok(Value::Str(strs.join(sep)))
+}
+
+pub fn trim(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("trim", args, 1)?;
+    Ok(Value::Str(expect_str("trim", &args[0])?.trim().to_string()))
+}
+
+pub fn upper(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("upper", args, 1)?;
+    Ok(Value::Str(expect_str("upper", &args[0])?.to_uppercase()))
+}
+
+pub fn lower(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("lower", args, 1)?;
+    Ok(Value::Str(expect_str("lower", &args[0])?.to_lowercase()))
+}
+
+/// replace(s, from, to) replaces every occurrence of from
+pub fn replace(scope: &Rc<RefCell<Scope>>, args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("replace", args, 3)?;
+    let s = expect_str("replace", &args[0])?;
+    let from = expect_str("replace", &args[1])?;
+    let to = expect_str("replace", &args[2])?;
+    // An empty pattern matches between every char, and at both ends
+    let matches = if from.is_empty() { s.chars().count() + 1 } else { s.matches(from).count() };
+    let growth = to.len().saturating_sub(from.len()).checked_mul(matches);
+    reserve(scope, "replace", growth.and_then(|growth| growth.checked_add(s.len())))?;
+    Ok(Value::Str(s.replace(from, to)))
+}
+
+pub fn starts_with(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("starts_with", args, 2)?;
+    let s = expect_str("starts_with", &args[0])?;
+    Ok(Value::Bool(s.starts_with(expect_str("starts_with", &args[1])?)))
+}
+
+pub fn ends_with(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("ends_with", args, 2)?;
+    let s = expect_str("ends_with", &args[0])?;
+    Ok(Value::Bool(s.ends_with(expect_str("ends_with", &args[1])?)))
+}
+
+pub fn contains(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("contains", args, 2)?;
+    let s = expect_str("contains", &args[0])?;
+    Ok(Value::Bool(s.contains(expect_str("contains", &args[1])?)))
+}
+
+/// find(s, sub) gives Some(index) of the first occurrence of sub, or None
+pub fn find(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("find", args, 2)?;
+    let s = expect_str("find", &args[0])?;
+    let sub = expect_str("find", &args[1])?;
+    match s.find(sub) {
+        Some(byte_idx) => {
+            let idx = s[..byte_idx].chars().count() as i32;
+            Ok(Value::Some(Box::new(Value::Integer(idx))))
+        },
+        None => Ok(Value::None),
+    }
+}
+
+/// chars(s) splits a string into a list of single character strings
+pub fn chars(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("chars", args, 1)?;
+    let s = expect_str("chars", &args[0])?;
+    Ok(Value::List(s.chars().map(|c| Value::Str(c.to_string())).collect()))
+}
+
+/// repeat(s, n) repeats a string n times, while repeat(x) is an endless Iterator of x
+pub fn repeat(scope: &Rc<RefCell<Scope>>, args: &[Value]) -> Result<Value, IrisError> {
+    if args.len() == 1 {
+        return iter::repeat(args);
+    }
+    expect_args("repeat", args, 2)?;
+    let s = expect_str("repeat", &args[0])?;
+    let n = expect_int("repeat", &args[1])?;
+    if n < 0 {
+        return Err(IrisError::runtime(format!("repeat() count can't be negative, got {}", n)));
+    }
+    reserve(scope, "repeat", s.len().checked_mul(n as usize))?;
+    Ok(Value::Str(s.repeat(n as usize)))
+}
+
+/// parse_int(s) gives Some(int), or None if s isn't a valid int
+pub fn parse_int(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("parse_int", args, 1)?;
+    let s = expect_str("parse_int", &args[0])?;
+    Ok(match s.trim().parse::<i32>() {
+        Ok(x) => Value::Some(Box::new(Value::Integer(x))),
+        Err(_) => Value::None,
+    })
+}
+
+/// parse_float(s) gives Some(float), or None if s isn't a valid number
+pub fn parse_float(args: &[Value]) -> Result<Value, IrisError> {
+    expect_args("parse_float", args, 1)?;
+    let s = expect_str("parse_float", &args[0])?;
+    Ok(match s.trim().parse::<f64>() {
+        Ok(x) => Value::Some(Box::new(Value::DoublePrecisionFloat(x))),
+        Err(_) => Value::None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Interpreter;
+
+    fn strs(xs: &[&str]) -> Value {
+        Value::List(xs.iter().map(|&s| Value::from(s)).collect())
+    }
+
+    fn eval(source: &str) -> Result<Value, IrisError> {
+        Interpreter::new().eval_str(source)
+    }
+
+    #[test]
+    fn empty_strings() {
+        assert_eq!(split(&[Value::from("")]), Ok(strs(&[])));
+        assert_eq!(split(&[Value::from(""), Value::from(",")]), Ok(strs(&[""])));
+        assert_eq!(split(&[Value::from("a,,b"), Value::from(",")]), Ok(strs(&["a", "", "b"])));
+        assert!(split(&[Value::from("a"), Value::from("")]).is_err());
+        assert_eq!(trim(&[Value::from("  ")]), Ok(Value::from("")));
+        assert_eq!(chars(&[Value::from("")]), Ok(strs(&[])));
+        assert_eq!(find(&[Value::from("abc"), Value::from("")]), Ok(Value::Some(Box::new(Value::Integer(0)))));
+        assert_eq!(find(&[Value::from(""), Value::from("a")]), Ok(Value::None));
+        assert_eq!(starts_with(&[Value::from("abc"), Value::from("")]), Ok(Value::Bool(true)));
+        assert_eq!(parse_int(&[Value::from("")]), Ok(Value::None));
+        assert_eq!(eval("join(split(\"\", \",\"), \"-\")"), Ok(Value::from("")));
+        assert_eq!(eval("replace(\"ab\", \"\", \"-\")"), Ok(Value::from("-a-b-")));
+        assert_eq!(eval("repeat(\"ab\", 0)"), Ok(Value::from("")));
+    }
+
+    #[test]
+    fn unicode_is_counted_in_chars() {
+        assert_eq!(chars(&[Value::from("héllo")]), Ok(strs(&["h", "é", "l", "l", "o"])));
+        assert_eq!(find(&[Value::from("日本語"), Value::from("語")]), Ok(Value::Some(Box::new(Value::Integer(2)))));
+        assert_eq!(upper(&[Value::from("straße")]), Ok(Value::from("STRASSE")));
+        assert_eq!(eval("len(\"日本語\")"), Ok(Value::Integer(3)));
+        assert_eq!(eval("\"日本語\"[1]"), Ok(Value::from("本")));
+        assert_eq!(eval("slice(\"日本語\", 1, 2)"), Ok(Value::from("本")));
+        assert_eq!(eval("\"ü-ö\".split(\"-\")"), Ok(strs(&["ü", "ö"])));
+    }
+
+    #[test]
+    fn negative_indexes() {
+        assert_eq!(eval("slice(\"hello\", -3)"), Ok(Value::from("llo")));
+        assert_eq!(eval("slice(\"hello\", 1, -1)"), Ok(Value::from("ell")));
+        assert_eq!(eval("slice(\"hello\", -10, 2)"), Ok(Value::from("he")));
+        assert_eq!(eval("slice(\"hello\", -1, -3)"), Ok(Value::from("")));
+        assert!(eval("\"hello\"[-1]").is_err());
+        assert!(eval("repeat(\"a\", -1)").is_err());
+    }
+
+    #[test]
+    fn args_of_the_wrong_type_are_errors() {
+        assert!(upper(&[Value::Integer(1)]).is_err());
+        assert!(split(&[Value::from("a"), Value::Integer(1)]).is_err());
+        assert!(trim(&[]).is_err());
+        assert!(eval("join(\"abc\", \",\")").is_err());
+    }
+}
@@ -1,4 +1,7 @@
-use crate::ast::AstNode;
+use std::fmt;
+
+use crate::ast::{AstNode, TypeExpr};
+use crate::error::IrisError;
 use super::Value;
 use super::scope::Scope;
 
@@ -10,9 +13,12 @@ use super::scope::Scope;
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum PrimitiveType {
     Integer,
+    DoublePrecisionFloat,
     Str,
     Bool,
     List,
+    Dict,
+    Set,
     Function,
     Option,
     // Enum,
@@ -26,24 +32,198 @@ pub enum PrimitiveType {
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum TypeDefinition {
     PrimitiveType(PrimitiveType),
-    // CompoundType(Box<TypeDefinition>),
+    /// An opaque object passed in by a host program, identified by its type name
+    Native(String),
+    /// A list, set, dict or Option along with the types of what's in it,
+    /// like list[int] or dict[str, int]
+    Generic(PrimitiveType, Vec<TypeDefinition>),
+    /// A function taking and returning the given types, like fn(int) -> str
+    Function(Vec<TypeDefinition>, Box<TypeDefinition>),
+    /// Any value at all. This is what `any` and type variables like T mean.
+    Any,
 }
 
+impl TypeDefinition {
+    /// Whether a value is of this type. The items of a collection are all
+    /// checked, so an empty list is a list[int] and a list[str] at once.
+    /// Functions are only checked for how many args they take, since what
+    /// they take and return isn't known until they're called.
+    pub fn matches(&self, v: &Value) -> bool {
+        match (self, v) {
+            (TypeDefinition::Any, _) => true,
+            (TypeDefinition::Generic(PrimitiveType::List, params), Value::List(xs)) => {
+                xs.iter().all(|x| params[0].matches(x))
+            },
+            (TypeDefinition::Generic(PrimitiveType::Set, params), Value::Set(xs)) => {
+                xs.iter().all(|x| params[0].matches(x))
+            },
+            (TypeDefinition::Generic(PrimitiveType::Dict, params), Value::Dict(d)) => {
+                d.iter().all(|(k, v)| params[0].matches(k) && params[1].matches(v))
+            },
+            (TypeDefinition::Generic(PrimitiveType::Option, _), Value::None) => true,
+            (TypeDefinition::Generic(PrimitiveType::Option, params), Value::Some(x)) => params[0].matches(x),
+            (TypeDefinition::Generic(..), _) => false,
+            (TypeDefinition::Function(params, _), Value::Function(f)) => f.args.len() == params.len(),
+            (TypeDefinition::Function(..), Value::NativeFunction(_)) => true,
+            (TypeDefinition::Function(..), _) => false,
+            (typ, v) => *typ == type_of(v),
+        }
+    }
+
+    /// Whether every value of this type is also of the other type, which
+    /// makes this one the more specific of the two
+    pub fn is_within(&self, other: &TypeDefinition) -> bool {
+        match (self, other) {
+            (_, TypeDefinition::Any) => true,
+            (TypeDefinition::Generic(p, _), TypeDefinition::PrimitiveType(q)) => p == q,
+            (TypeDefinition::Generic(p, a), TypeDefinition::Generic(q, b)) => {
+                p == q && a.iter().zip(b).all(|(a, b)| a.is_within(b))
+            },
+            (TypeDefinition::Function(..), TypeDefinition::PrimitiveType(PrimitiveType::Function)) => true,
+            (TypeDefinition::Function(a, _), TypeDefinition::Function(b, _)) => a.len() == b.len(),
+            (a, b) => a == b,
+        }
+    }
+}
+
+/// Types are shown the way they're written in source
+impl fmt::Display for TypeDefinition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let join = |types: &[TypeDefinition]| types.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(", ");
+        match self {
+            TypeDefinition::PrimitiveType(p) => write!(f, "{}", primitive_name(p)),
+            TypeDefinition::Native(name) => write!(f, "{}", name),
+            TypeDefinition::Generic(p, params) => write!(f, "{}[{}]", primitive_name(p), join(params)),
+            TypeDefinition::Function(params, ret) => write!(f, "fn({}) -> {}", join(params), ret),
+            TypeDefinition::Any => write!(f, "any"),
+        }
+    }
+}
+
+fn primitive_name(p: &PrimitiveType) -> &'static str {
+    match p {
+        PrimitiveType::Integer => "int",
+        PrimitiveType::DoublePrecisionFloat => "float",
+        PrimitiveType::Str => "str",
+        PrimitiveType::Bool => "bool",
+        PrimitiveType::List => "list",
+        PrimitiveType::Dict => "dict",
+        PrimitiveType::Set => "set",
+        PrimitiveType::Function => "function",
+        PrimitiveType::Option => "Option",
+    }
+}
+
+/// A function or method defined in Iris source, along with the scope it closes over
 #[derive(Clone, Debug)]
 pub struct Function {
     pub name: String,
     pub args: Vec<String>,
-    pub body: Vec<AstNode>,
-    pub scope: Scope,
+    pub(crate) body: Vec<AstNode>,
+    pub(crate) scope: Scope,
 }
 
-/// Determine a type definition from the given string
-/// Eventually this should support parsing complex type definitions
-/// from the source
-pub fn read_type_definition(s: &str) -> TypeDefinition {
+/// Determine a type definition from a type written in source
+pub fn read_type_definition(t: &TypeExpr) -> Result<TypeDefinition, IrisError> {
+    match t {
+        TypeExpr::Named(name) => read_type_name(name),
+        TypeExpr::Generic(name, params) => {
+            let base = read_type_name(name)?;
+            let expected = match base {
+                TypeDefinition::PrimitiveType(PrimitiveType::List)
+                | TypeDefinition::PrimitiveType(PrimitiveType::Set)
+                | TypeDefinition::PrimitiveType(PrimitiveType::Option) => 1,
+                TypeDefinition::PrimitiveType(PrimitiveType::Dict) => 2,
+                // What's in a host object, or an Iterator, can't be seen
+                // without using it up, so its params are only for iris check
+                TypeDefinition::Native(_) => return Ok(base),
+                _ => return Err(IrisError::runtime(format!("{} doesn't take type params", name))),
+            };
+            if params.len() != expected {
+                return Err(IrisError::runtime(format!(
+                    "{} takes {} type param{}, got {}",
+                    name, expected, if expected == 1 { "" } else { "s" }, params.len(),
+                )));
+            }
+            let params = params.iter()
+                .map(read_type_definition)
+                .collect::<Result<Vec<_>, IrisError>>()?;
+            match base {
+                // list[any] is no different from list
+                _ if params.iter().all(|p| *p == TypeDefinition::Any) => Ok(base),
+                TypeDefinition::PrimitiveType(p) => Ok(TypeDefinition::Generic(p, params)),
+                _ => unreachable!(),
+            }
+        },
+        TypeExpr::Fn(params, ret) => Ok(TypeDefinition::Function(
+            params.iter().map(read_type_definition).collect::<Result<Vec<_>, IrisError>>()?,
+            Box::new(read_type_definition(ret)?),
+        )),
+    }
+}
+
+fn read_type_name(s: &str) -> Result<TypeDefinition, IrisError> {
     match s {
-        "list" => TypeDefinition::PrimitiveType(PrimitiveType::List),
-        _ => panic!("unknown type {:?}", s),
+        "int" => Ok(TypeDefinition::PrimitiveType(PrimitiveType::Integer)),
+        "float" => Ok(TypeDefinition::PrimitiveType(PrimitiveType::DoublePrecisionFloat)),
+        "str" => Ok(TypeDefinition::PrimitiveType(PrimitiveType::Str)),
+        "bool" => Ok(TypeDefinition::PrimitiveType(PrimitiveType::Bool)),
+        "list" => Ok(TypeDefinition::PrimitiveType(PrimitiveType::List)),
+        "dict" => Ok(TypeDefinition::PrimitiveType(PrimitiveType::Dict)),
+        "set" => Ok(TypeDefinition::PrimitiveType(PrimitiveType::Set)),
+        "function" => Ok(TypeDefinition::PrimitiveType(PrimitiveType::Function)),
+        "Option" => Ok(TypeDefinition::PrimitiveType(PrimitiveType::Option)),
+        "any" => Ok(TypeDefinition::Any),
+        // A single capital letter is a type variable, as in list[T]
+        _ if s.len() == 1 && s.chars().all(char::is_uppercase) => Ok(TypeDefinition::Any),
+        // Host object types are capitalized, like "Connection"
+        _ if s.chars().next().unwrap().is_uppercase() => Ok(TypeDefinition::Native(s.to_string())),
+        _ => Err(IrisError::runtime(format!("unknown type {:?}", s))),
+    }
+}
+
+/// Pick the method to call for some args, out of those with the right
+/// name. Each is for the types of one or more of the first args, and the most
+/// specific one the args match wins: a method for list[int] is picked over
+/// one for list, and one for (int, str) over one for int. If no one method is
+/// more specific than all the others that match, the call is ambiguous.
+pub fn most_specific<'a, F>(
+    name: &str,
+    candidates: impl Iterator<Item = (&'a [TypeDefinition], &'a F)>,
+    args: &[Value],
+) -> Result<Option<&'a F>, IrisError> {
+    let fits: Vec<_> = candidates
+        .filter(|(types, _)| types.len() <= args.len() && types.iter().zip(args).all(|(t, v)| t.matches(v)))
+        .collect();
+    let best = fits.iter().find(|(types, _)| fits.iter().all(|(other, _)| is_within(types, other)));
+    match best {
+        Some((_, f)) => Ok(Some(f)),
+        None if fits.is_empty() => Ok(None),
+        None => {
+            let mut types: Vec<String> = fits.iter().map(|(types, _)| show_types(types)).collect();
+            types.sort();
+            let n = fits.iter().map(|(types, _)| types.len()).max().unwrap_or(1);
+            let args: Vec<String> = args.iter().take(n).map(super::builtins::repr).collect();
+            let args = if n == 1 { args.join("") } else { format!("({})", args.join(", ")) };
+            Err(IrisError::runtime(format!(
+                "ambiguous call to method {}: the methods for {} {} fit {}",
+                name, types.join(" and "), if types.len() == 2 { "both" } else { "all" }, args,
+            )))
+        },
+    }
+}
+
+/// Whether each of some method's types is within the other's. A method
+/// that's for fewer args takes any type for the rest.
+fn is_within(a: &[TypeDefinition], b: &[TypeDefinition]) -> bool {
+    b.iter().enumerate().all(|(i, b)| a.get(i).unwrap_or(&TypeDefinition::Any).is_within(b))
+}
+
+/// Show the types a method is for as they're written after `for`
+fn show_types(types: &[TypeDefinition]) -> String {
+    match types {
+        [t] => t.to_string(),
+        _ => format!("({})", types.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(", ")),
     }
 }
 
@@ -51,11 +231,138 @@ pub fn read_type_definition(s: &str) -> TypeDefinition {
 pub fn type_of(v: &Value) -> TypeDefinition {
     match v {
         Value::Integer(_) => TypeDefinition::PrimitiveType(PrimitiveType::Integer),
+        Value::DoublePrecisionFloat(_) => {
+            TypeDefinition::PrimitiveType(PrimitiveType::DoublePrecisionFloat)
+        },
         Value::Str(_) => TypeDefinition::PrimitiveType(PrimitiveType::Str),
         Value::Bool(_) => TypeDefinition::PrimitiveType(PrimitiveType::Bool),
         Value::List(_) => TypeDefinition::PrimitiveType(PrimitiveType::List),
-        Value::Function(_) => TypeDefinition::PrimitiveType(PrimitiveType::Function),
+        Value::Dict(_) => TypeDefinition::PrimitiveType(PrimitiveType::Dict),
+        Value::Set(_) => TypeDefinition::PrimitiveType(PrimitiveType::Set),
+        Value::Function(_) | Value::NativeFunction(_) => {
+            TypeDefinition::PrimitiveType(PrimitiveType::Function)
+        },
         Value::Some(_) => TypeDefinition::PrimitiveType(PrimitiveType::Option),
         Value::None => TypeDefinition::PrimitiveType(PrimitiveType::Option),
+        Value::Native(obj) => TypeDefinition::Native(obj.type_name.clone()),
     }
-}
\ No newline at end of file
+}
+/// The name of a value's type, as it's written in source
+pub fn type_name(v: &Value) -> String {
+    let name = match v {
+        Value::Integer(_) => "int",
+        Value::DoublePrecisionFloat(_) => "float",
+        Value::Str(_) => "str",
+        Value::Bool(_) => "bool",
+        Value::List(_) => "list",
+        Value::Dict(_) => "dict",
+        Value::Set(_) => "set",
+        Value::Function(_) | Value::NativeFunction(_) => "function",
+        Value::Some(_) | Value::None => "Option",
+        Value::Native(obj) => &obj.type_name,
+    };
+    name.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{check_str, Interpreter};
+
+    fn named(name: &str) -> TypeExpr {
+        TypeExpr::Named(name.to_string())
+    }
+
+    fn generic(name: &str, params: Vec<TypeExpr>) -> TypeExpr {
+        TypeExpr::Generic(name.to_string(), params)
+    }
+
+    fn read(t: &TypeExpr) -> TypeDefinition {
+        read_type_definition(t).unwrap()
+    }
+
+    fn int() -> TypeDefinition {
+        TypeDefinition::PrimitiveType(PrimitiveType::Integer)
+    }
+
+    fn str_type() -> TypeDefinition {
+        TypeDefinition::PrimitiveType(PrimitiveType::Str)
+    }
+
+    #[test]
+    fn generic_types_are_read_from_source() {
+        let list_int = read(&generic("list", vec![named("int")]));
+        assert_eq!(list_int, TypeDefinition::Generic(PrimitiveType::List, vec![int()]));
+        let dict = read(&generic("dict", vec![named("str"), generic("Option", vec![named("int")])]));
+        assert_eq!(dict.to_string(), "dict[str, Option[int]]");
+        let func = read(&TypeExpr::Fn(vec![named("int"), named("str")], Box::new(generic("list", vec![named("bool")]))));
+        assert_eq!(func.to_string(), "fn(int, str) -> list[bool]");
+        // Params that could be anything add nothing
+        assert_eq!(read(&generic("list", vec![named("T")])), TypeDefinition::PrimitiveType(PrimitiveType::List));
+        assert_eq!(read(&generic("set", vec![named("any")])), TypeDefinition::PrimitiveType(PrimitiveType::Set));
+        assert_eq!(read(&generic("Iterator", vec![named("int")])), TypeDefinition::Native("Iterator".to_string()));
+    }
+
+    #[test]
+    fn bad_type_params_are_errors() {
+        let err = |t: TypeExpr| read_type_definition(&t).unwrap_err().to_string();
+        assert!(err(generic("dict", vec![named("str")])).contains("dict takes 2 type params, got 1"));
+        assert!(err(generic("list", vec![named("int"), named("int")])).contains("list takes 1 type param, got 2"));
+        assert!(err(generic("int", vec![named("str")])).contains("int doesn't take type params"));
+        assert!(err(generic("list", vec![named("integer")])).contains("unknown type \"integer\""));
+    }
+
+    #[test]
+    fn values_match_generic_types() {
+        let list_int = TypeDefinition::Generic(PrimitiveType::List, vec![int()]);
+        assert!(list_int.matches(&Value::from(vec![1, 2])));
+        assert!(!list_int.matches(&Value::from(vec![Value::from(1), Value::from("a")])));
+        // An empty list is a list of anything
+        assert!(list_int.matches(&Value::List(vec![])));
+        assert!(!list_int.matches(&Value::from(1)));
+        let option_str = TypeDefinition::Generic(PrimitiveType::Option, vec![str_type()]);
+        assert!(option_str.matches(&Value::None));
+        assert!(option_str.matches(&Value::from(Some("a"))));
+        assert!(!option_str.matches(&Value::from(Some(1))));
+        let nested = TypeDefinition::Generic(PrimitiveType::List, vec![list_int.clone()]);
+        assert!(nested.matches(&Value::List(vec![Value::from(vec![1]), Value::List(vec![])])));
+        assert!(!nested.matches(&Value::from(vec![1])));
+    }
+
+    #[test]
+    fn more_specific_types_are_within_less_specific_ones() {
+        let list = TypeDefinition::PrimitiveType(PrimitiveType::List);
+        let list_int = TypeDefinition::Generic(PrimitiveType::List, vec![int()]);
+        let list_str = TypeDefinition::Generic(PrimitiveType::List, vec![str_type()]);
+        assert!(list_int.is_within(&list) && !list.is_within(&list_int));
+        assert!(list_int.is_within(&TypeDefinition::Any));
+        assert!(!list_int.is_within(&list_str) && !list_str.is_within(&list_int));
+        let func = TypeDefinition::Function(vec![int()], Box::new(str_type()));
+        assert!(func.is_within(&TypeDefinition::PrimitiveType(PrimitiveType::Function)));
+    }
+
+    #[test]
+    fn methods_can_be_for_generic_types() {
+        let source = "method total(self) for list[int]\n  sum(self)\nend\n\
+            method total(self) for list[str]\n  join(self, \"\")\nend\n\
+            method total(self) for list\n  len(self)\nend\n";
+        let mut interpreter = Interpreter::new();
+        interpreter.eval_str(source).unwrap();
+        assert_eq!(interpreter.eval_str("[1, 2].total()"), Ok(Value::Integer(3)));
+        assert_eq!(interpreter.eval_str("[\"a\", \"b\"].total()"), Ok(Value::from("ab")));
+        assert_eq!(interpreter.eval_str("[true, 1].total()"), Ok(Value::Integer(2)));
+        // An empty list fits both list[int] and list[str] equally well
+        let err = interpreter.eval_str("list().total()").unwrap_err().to_string();
+        assert!(err.contains("ambiguous"), "{}", err);
+    }
+
+    #[test]
+    fn the_checker_knows_generic_annotations() {
+        let source = "fn f(g: fn(int) -> str, d: dict[str, Option[int]]) -> str\n  g(1)\nend\n\
+            f(fn(x) str(x) end, {\"a\": None})";
+        assert_eq!(check_str(source), Ok(vec![]));
+        let diagnostics = check_str("fn f(x: list[int]) -> int\n  len(x)\nend\nf([\"a\"])").unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.contains("should be list[int], got list[str]"), "{:?}", diagnostics);
+    }
+}
@@ -0,0 +1,53 @@
+//! `iris test`: runs the tests in a file. Tests are the top-level functions
+//! named test_something that take no args. Each one gets an interpreter of
+//! its own, so a fresh global scope: the file is run from the top, and then
+//! the test is called. A test passes unless that gives an error, such as
+//! from a failed assert(), assert_eq() or assert_raises().
+
+use crate::ast::{AstNode, located};
+use crate::error::IrisError;
+use crate::parse;
+use crate::run::Interpreter;
+
+/// How a test went
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestResult {
+    pub name: String,
+    /// Where the test is defined, or if it failed, the statement that failed.
+    /// Both count from 1.
+    pub line: usize,
+    pub col: usize,
+    /// Why the test failed, if it did
+    pub error: Option<IrisError>,
+}
+
+/// Run each test in some source code, in the order they're defined. It's
+/// only an error if the source doesn't parse.
+pub fn run_tests(source: &str) -> Result<Vec<TestResult>, IrisError> {
+    let ast = parse::parse_str(source)?;
+    let tests = ast.iter().filter_map(|node| match node {
+        AstNode::Located { pos, end, stmt } => match &**stmt {
+            AstNode::FnDef { name, args, .. } if name.starts_with("test_") && args.is_empty() => {
+                Some((*pos, *end, name))
+            },
+            _ => None,
+        },
+        _ => None,
+    });
+    let results = tests
+        .map(|(pos, end, name)| {
+            // The call is located where the test is defined, for when the
+            // test is all on one line
+            let call = located(pos, end, AstNode::FnCall { name: name.to_string(), args: vec![] });
+            let mut interpreter = Interpreter::new();
+            let result = interpreter.run(source, &ast).and_then(|_| interpreter.run(source, &[call]));
+            let (pos, error) = match result {
+                Ok(_) => (pos, None),
+                Err(e) => (interpreter.failed_at().unwrap_or(pos), Some(e)),
+            };
+            let (line, col) = parse::line_col(source, pos);
+            TestResult { name: name.to_string(), line, col, error }
+        })
+        .collect();
+    Ok(results)
+}
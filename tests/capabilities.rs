@@ -0,0 +1,109 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use iris::{Capabilities, Interpreter, Value};
+
+/// A fresh directory for one test, with an allowed subdirectory in it
+fn sandbox(test: &str) -> (PathBuf, PathBuf) {
+    let root = std::env::temp_dir().join(format!("iris-{}-{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    let allowed = root.join("allowed");
+    fs::create_dir_all(&allowed).unwrap();
+    (root, allowed)
+}
+
+fn run(capabilities: Capabilities, source: &str) -> Result<Value, String> {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_capabilities(capabilities);
+    interpreter.eval_str(source).map_err(|e| e.to_string())
+}
+
+fn quoted(path: &Path) -> String {
+    format!("{:?}", path.to_str().unwrap())
+}
+
+#[test]
+fn nothing_is_allowed_by_default() {
+    let (root, allowed) = sandbox("default");
+    fs::write(allowed.join("a.txt"), "hi").unwrap();
+    let file = quoted(&allowed.join("a.txt"));
+    let read = run(Capabilities::default(), &format!("read_file({})", file)).unwrap_err();
+    assert!(read.contains("no read access") && read.contains("--allow-read"), "{}", read);
+    let write = run(Capabilities::default(), &format!("write_file({}, \"x\")", file)).unwrap_err();
+    assert!(write.contains("no write access") && write.contains("--allow-write"), "{}", write);
+    let env = run(Capabilities::default(), "env(\"HOME\")").unwrap_err();
+    assert!(env.contains("--allow-env"), "{}", env);
+    assert_eq!(fs::read_to_string(allowed.join("a.txt")).unwrap(), "hi");
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn allowed_directories_can_be_read_and_written() {
+    let (root, allowed) = sandbox("allowed");
+    let capabilities = Capabilities { read: vec![allowed.clone()], write: vec![allowed.clone()], ..Capabilities::default() };
+    let file = quoted(&allowed.join("sub").join("b.txt"));
+    fs::create_dir(allowed.join("sub")).unwrap();
+    let source = format!("write_file({0}, \"one\\ntwo\")\n[read_file({0}), read_lines({0}), exists({0})]", file);
+    assert_eq!(run(capabilities, &source), Ok(Value::List(vec![
+        Value::from("one\ntwo"),
+        Value::List(vec![Value::from("one"), Value::from("two")]),
+        Value::Bool(true),
+    ])));
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn reading_doesnt_allow_writing() {
+    let (root, allowed) = sandbox("read-only");
+    let capabilities = Capabilities { read: vec![allowed.clone()], ..Capabilities::default() };
+    let file = quoted(&allowed.join("c.txt"));
+    assert!(run(capabilities.clone(), &format!("exists({})", file)).is_ok());
+    assert!(run(capabilities, &format!("write_file({}, \"x\")", file)).is_err());
+    assert!(!allowed.join("c.txt").exists());
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn env_can_be_allowed() {
+    let capabilities = Capabilities { env: true, ..Capabilities::default() };
+    assert_eq!(run(capabilities.clone(), "env(\"IRIS_SURELY_NOT_SET\")"), Ok(Value::None));
+    let path = std::env::var("PATH").unwrap();
+    assert_eq!(run(capabilities, "env(\"PATH\")"), Ok(Value::Some(Box::new(Value::Str(path)))));
+}
+
+#[test]
+fn dot_dot_cant_leave_an_allowed_directory() {
+    let (root, allowed) = sandbox("dotdot");
+    let capabilities = Capabilities { write: vec![allowed.clone()], ..Capabilities::default() };
+    let escape = quoted(&allowed.join("..").join("escaped.txt"));
+    let err = run(capabilities, &format!("write_file({}, \"x\")", escape)).unwrap_err();
+    assert!(err.contains("no write access"), "{}", err);
+    assert!(!root.join("escaped.txt").exists());
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn dangling_symlinks_cant_be_written_through() {
+    let (root, allowed) = sandbox("symlink");
+    let outside = root.join("outside_target");
+    std::os::unix::fs::symlink(&outside, allowed.join("link")).unwrap();
+    let capabilities = Capabilities { write: vec![allowed.clone()], ..Capabilities::default() };
+    let link = quoted(&allowed.join("link"));
+    let err = run(capabilities, &format!("write_file({}, \"escaped\")", link)).unwrap_err();
+    assert!(err.contains("no write access"), "{}", err);
+    assert!(!outside.exists());
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinks_inside_the_sandbox_still_work() {
+    let (root, allowed) = sandbox("inner-symlink");
+    fs::write(allowed.join("real.txt"), "old").unwrap();
+    std::os::unix::fs::symlink(allowed.join("real.txt"), allowed.join("link")).unwrap();
+    let capabilities = Capabilities { write: vec![allowed.clone()], ..Capabilities::default() };
+    assert!(run(capabilities, &format!("write_file({}, \"new\")", quoted(&allowed.join("link")))).is_ok());
+    assert_eq!(fs::read_to_string(allowed.join("real.txt")).unwrap(), "new");
+    fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,77 @@
+use std::convert::TryFrom;
+
+use iris::{Interpreter, IrisError, PrimitiveType, TypeDefinition, Value};
+
+#[test]
+fn globals_persist_between_evals() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval_str("let total = 1\nfn add(n)\n  total = total + n\nend").unwrap();
+    interpreter.eval_str("add(2)").unwrap();
+    assert_eq!(interpreter.get_global("total"), Some(Value::Integer(3)));
+    assert_eq!(interpreter.get_global("missing"), None);
+}
+
+#[test]
+fn hosts_can_set_globals() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_global("name", Value::from("iris"));
+    assert_eq!(interpreter.eval_str("upper(name)"), Ok(Value::from("IRIS")));
+    // Setting it again replaces it
+    interpreter.set_global("name", Value::from("rust"));
+    assert_eq!(interpreter.eval_str("name"), Ok(Value::from("rust")));
+}
+
+#[test]
+fn hosts_can_call_script_functions() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval_str("fn greet(name)\n  \"hi \" + name\nend").unwrap();
+    assert_eq!(interpreter.call_function("greet", vec![Value::from("bob")]), Ok(Value::from("hi bob")));
+    assert_eq!(interpreter.call_function("len", vec![Value::from(vec![1, 2])]), Ok(Value::Integer(2)));
+    assert!(interpreter.call_function("greet", vec![]).is_err());
+    assert!(interpreter.call_function("missing", vec![]).is_err());
+}
+
+#[test]
+fn scripts_can_call_host_functions() {
+    let mut interpreter = Interpreter::new();
+    interpreter.register_fn("double", |args| {
+        let x = i32::try_from(args[0].clone())?;
+        Ok(Value::from(x * 2))
+    });
+    assert_eq!(interpreter.eval_str("double(21)"), Ok(Value::Integer(42)));
+    assert_eq!(interpreter.eval_str("[1, 2].map(double)"), Ok(Value::from(vec![2, 4])));
+    let err = interpreter.eval_str("double(\"a\")").unwrap_err();
+    assert!(err.to_string().contains("expected int"), "{}", err);
+}
+
+#[test]
+fn host_errors_are_passed_through() {
+    let mut interpreter = Interpreter::new();
+    interpreter.register_fn("fail", |_| Err(IrisError::runtime("host says no")));
+    let err = interpreter.eval_str("fail()").unwrap_err();
+    assert!(err.to_string().contains("host says no"), "{}", err);
+    // The interpreter can still be used afterwards
+    assert_eq!(interpreter.eval_str("1 + 1"), Ok(Value::Integer(2)));
+}
+
+#[test]
+fn hosts_can_add_methods() {
+    let mut interpreter = Interpreter::new();
+    let str_type = TypeDefinition::PrimitiveType(PrimitiveType::Str);
+    interpreter.register_method("shout", str_type, |args| {
+        Ok(Value::from(String::try_from(args[0].clone())?.to_uppercase() + "!"))
+    });
+    assert_eq!(interpreter.eval_str("\"hey\".shout()"), Ok(Value::from("HEY!")));
+    assert!(interpreter.eval_str("1.shout()").is_err());
+}
+
+#[test]
+fn values_convert_to_and_from_rust() {
+    assert_eq!(Value::from(Some("a")), Value::Some(Box::new(Value::from("a"))));
+    assert_eq!(Value::from(None::<i32>), Value::None);
+    assert_eq!(Value::from(()), Value::None);
+    assert_eq!(f64::try_from(Value::Integer(2)), Ok(2.0));
+    assert_eq!(bool::try_from(Value::Bool(true)), Ok(true));
+    assert!(String::try_from(Value::Integer(1)).is_err());
+    assert!(i32::try_from(Value::DoublePrecisionFloat(1.5)).is_err());
+}
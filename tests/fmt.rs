@@ -0,0 +1,89 @@
+use iris::format_str;
+
+/// Formatted source should come out of the formatter unchanged
+fn assert_formatted(source: &str) {
+    assert_eq!(format_str(source).unwrap(), source);
+}
+
+#[test]
+fn comments_stay_in_every_branch() {
+    assert_formatted(
+        "let x = 2\n\
+         if x == 1 then\n    # one\n    print(1)\n    # end of one\n\
+         elif x == 2 then\n    # two\n    print(2)\n\
+         elif x == 3 then\n    # only a comment\n\
+         else\n    # otherwise\n    print(3)\n    # end of else\n\
+         end\n\
+         # after\n\
+         print(x)\n",
+    );
+}
+
+#[test]
+fn comments_stay_in_nested_branches() {
+    assert_formatted(
+        "fn f(x)\n\
+         \x20   if x then\n\
+         \x20       if not x then\n            # inner\n            1\n\
+         \x20       else\n            # inner else\n            2\n\
+         \x20       end\n\
+         \x20   else\n        # outer else\n        3\n\
+         \x20   end\n\
+         end\n",
+    );
+}
+
+#[test]
+fn comments_move_into_the_branch_they_open() {
+    let source = "if true then\n  print(1)\nelse\n# otherwise\n  print(2)\nend\n";
+    let expected = "if true then\n    print(1)\nelse\n    # otherwise\n    print(2)\nend\n";
+    assert_eq!(format_str(source).unwrap(), expected);
+    assert_formatted(expected);
+}
+
+/// Unformatted programs, and how they should come out
+const CORPUS: &[(&str, &str)] = &[
+    (
+        "let x=1+2*3\nlet y = (1+2)*3\nlet z = 2**3**2\nlet w = -(2**2)\nprint(x,y,z,w)\n",
+        "let x = 1 + 2 * 3\nlet y = (1 + 2) * 3\nlet z = 2 ** 3 ** 2\nlet w = -(2 ** 2)\nprint(x, y, z, w)\n",
+    ),
+    (
+        "fn add(a:int,b:int)->int\n  a+b\nend\nlet f = fn(x) x*2 end\nlet g = fn (x)\n  # doubled\n  x * 2\nend\n",
+        "fn add(a: int, b: int) -> int\n    a + b\nend\nlet f = fn(x) x * 2 end\nlet g = fn(x)\n    # doubled\n    x * 2\nend\n",
+    ),
+    (
+        "let xs=[1,2,3]\nlet d={\"a\":1,\"b\":[1,2]}\nlet s = 'single' + \"double {xs[0]}\"\n\
+         for x in xs do\nprint(x, d[\"a\"], s)\nend\nlet e = {}\nlet m = (xs).len()\n",
+        "let xs = [1, 2, 3]\nlet d = {\"a\": 1, \"b\": [1, 2]}\nlet s = 'single' + \"double {xs[0]}\"\n\
+         for x in xs do\n    print(x, d[\"a\"], s)\nend\nlet e = {}\nlet m = xs.len()\n",
+    ),
+    (
+        "let i = 0 # counter\nwhile i < 3 do\n    i = i + 1\n\n\n\
+         \x20   if i % 2 == 0 then print(\"even\") else print(\"odd\") end\nend\n\
+         method double() for int\n  self * 2\nend\nprint(3.double(), not true and false or true, !5, 1 << 2)\n",
+        "let i = 0 # counter\nwhile i < 3 do\n    i = i + 1\n\n\
+         \x20   if i % 2 == 0 then print(\"even\") else print(\"odd\") end\nend\n\
+         method double() for int\n    self * 2\nend\nprint(3.double(), not true and false or true, !5, 1 << 2)\n",
+    ),
+    ("# leading\n\nlet a = 1\n# trailing\n", "# leading\n\nlet a = 1\n# trailing\n"),
+];
+
+#[test]
+fn corpus_formats_as_expected() {
+    for (source, expected) in CORPUS {
+        assert_eq!(&format_str(source).unwrap(), expected, "formatting {:?}", source);
+    }
+}
+
+#[test]
+fn formatting_is_idempotent() {
+    for (source, _) in CORPUS {
+        let once = format_str(source).unwrap();
+        assert_formatted(&once);
+    }
+}
+
+#[test]
+fn sources_that_dont_parse_are_errors() {
+    assert!(format_str("let x = \nend").is_err());
+}
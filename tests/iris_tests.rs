@@ -0,0 +1,72 @@
+//! Runs the Iris test files in tests/iris the way `iris test` does
+
+use std::fs;
+use std::path::Path;
+
+use iris::{run_tests, TestResult};
+
+fn run(fname: &str) -> Vec<TestResult> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/iris").join(fname);
+    let source = fs::read_to_string(&path).unwrap();
+    run_tests(&source).unwrap()
+}
+
+/// The names of the tests that passed and those that failed, with why
+fn outcome(results: &[TestResult]) -> (Vec<&str>, Vec<(&str, String)>) {
+    let passed = results.iter().filter(|r| r.error.is_none()).map(|r| r.name.as_str()).collect();
+    let failed = results.iter()
+        .filter_map(|r| r.error.as_ref().map(|e| (r.name.as_str(), e.to_string())))
+        .collect();
+    (passed, failed)
+}
+
+#[test]
+fn passing_tests_pass() {
+    let results = run("passing.iris");
+    let (passed, failed) = outcome(&results);
+    assert!(failed.is_empty(), "{:?}", failed);
+    // Functions with args, or without the test_ prefix, aren't tests
+    assert_eq!(passed, vec![
+        "test_assert",
+        "test_assert_eq",
+        "test_assert_raises",
+        "test_globals_are_fresh_for_each_test",
+        "test_globals_are_fresh_again",
+    ]);
+}
+
+#[test]
+fn failing_tests_fail() {
+    let results = run("failing.iris");
+    let (passed, failed) = outcome(&results);
+    assert_eq!(passed, vec!["test_passes"]);
+    let expected = [
+        ("test_assert_fails", "assertion failed: one isn't more than two"),
+        ("test_assert_eq_fails", "assertion failed: [1, 2] != [2, 1]"),
+        ("test_assert_raises_without_an_error", "assertion failed: expected an error, but got 1"),
+        ("test_assert_raises_with_the_wrong_error", "expected an error containing \"overflow\""),
+        ("test_runtime_error", "index 3 is out of bounds"),
+        ("test_assert_eq_after_a_call_fails", "assertion failed: 3 != 2"),
+    ];
+    assert_eq!(failed.len(), expected.len(), "{:?}", failed);
+    for ((name, error), (want_name, want_error)) in failed.iter().zip(&expected) {
+        assert_eq!(name, want_name);
+        assert!(error.contains(want_error), "{} failed with {:?}", name, error);
+    }
+}
+
+#[test]
+fn failures_are_reported_where_they_happen() {
+    let results = run("failing.iris");
+    let lines: Vec<(&str, usize)> = results.iter().map(|r| (r.name.as_str(), r.line)).collect();
+    assert_eq!(lines, vec![
+        ("test_passes", 3),
+        ("test_assert_fails", 8),
+        ("test_assert_eq_fails", 12),
+        ("test_assert_raises_without_an_error", 16),
+        ("test_assert_raises_with_the_wrong_error", 20),
+        ("test_runtime_error", 25),
+        // Not in two(), which the assertion called
+        ("test_assert_eq_after_a_call_fails", 34),
+    ]);
+}
@@ -0,0 +1,79 @@
+use iris::{CountingAllocator, Interpreter, IrisError, Limits, Value};
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn eval(source: &str) -> Result<Value, IrisError> {
+    Interpreter::new().eval_str(source)
+}
+
+/// Run a for loop over `iterable`, giving the list of items it saw
+fn items_of(iterable: &str) -> Result<Value, IrisError> {
+    eval(&format!("let seen = list()\nfor x in {} do\n  seen = chain(seen, [x]).collect()\nend\nseen", iterable))
+}
+
+fn ints(xs: &[i32]) -> Value {
+    Value::List(xs.iter().map(|&x| Value::Integer(x)).collect())
+}
+
+fn strs(xs: &[&str]) -> Value {
+    Value::List(xs.iter().map(|&s| Value::from(s)).collect())
+}
+
+#[test]
+fn builtin_types_can_be_looped_over() {
+    assert_eq!(items_of("[1, 2, 3]"), Ok(ints(&[1, 2, 3])));
+    assert_eq!(items_of("list()"), Ok(ints(&[])));
+    assert_eq!(items_of("\"héy\""), Ok(strs(&["h", "é", "y"])));
+    assert_eq!(items_of("\"\""), Ok(strs(&[])));
+    assert_eq!(items_of("{\"b\": 1, \"a\": 2}"), Ok(strs(&["b", "a"])));
+    assert_eq!(items_of("set([3, 1, 3])"), Ok(ints(&[3, 1])));
+    assert_eq!(items_of("range(3)"), Ok(ints(&[0, 1, 2])));
+    assert_eq!(items_of("range(3, 0, -1)"), Ok(ints(&[3, 2, 1])));
+    assert_eq!(items_of("range(0)"), Ok(ints(&[])));
+}
+
+#[test]
+fn changing_a_list_while_looping_over_it_doesnt_change_the_loop() {
+    let source = "let xs = [1, 2]\nlet n = 0\nfor x in xs do\n  xs = chain(xs, [x]).collect()\n  n = n + 1\nend\n[n, len(xs)]";
+    assert_eq!(eval(source), Ok(ints(&[2, 4])));
+}
+
+#[test]
+fn big_ranges_arent_built_up_front() {
+    let limits = Limits { max_memory: Some(1 << 20), ..Limits::default() };
+    let source = "let total = 0\nfor i in range(200000) do\n  total = total + 1\nend\ntotal";
+    let res = Interpreter::with_limits(limits.clone()).eval_str(source);
+    assert_eq!(res, Ok(Value::Integer(200000)));
+    let res = Interpreter::with_limits(limits).eval_str("len(range(200000).collect())");
+    assert_eq!(res, Err(IrisError::MemoryLimit(1 << 20)));
+}
+
+#[test]
+fn scripts_can_make_their_own_types_iterable() {
+    let mut interpreter = Interpreter::new();
+    interpreter.register_fn("countdown", |args| Ok(Value::native("Countdown", args[0].clone())));
+    interpreter.register_fn("start", |args| Ok(args[0].downcast_ref::<Value>().unwrap().clone()));
+    let source = "method Iter(self) for Countdown\n  let n = start(self) + 1\n  fn()\n    n = n - 1\n    \
+        if n > 0 then Some(n) else None end\n  end\nend\n\
+        let seen = list()\nfor x in countdown(3) do\n  seen = chain(seen, [x]).collect()\nend\nseen";
+    assert_eq!(interpreter.eval_str(source), Ok(ints(&[3, 2, 1])));
+    // The list library works with them too
+    assert_eq!(interpreter.eval_str("countdown(3).map(fn(x) x * 2 end)"), Ok(ints(&[6, 4, 2])));
+    assert_eq!(interpreter.eval_str("countdown(2).iter().collect()"), Ok(ints(&[2, 1])));
+}
+
+#[test]
+fn the_builtin_types_follow_the_iter_protocol_too() {
+    let source = "let next = Iter([1, 2])\n[next(), next(), next()]";
+    let some = |x| Value::Some(Box::new(Value::Integer(x)));
+    assert_eq!(eval(source), Ok(Value::List(vec![some(1), some(2), Value::None])));
+}
+
+#[test]
+fn other_values_cant_be_looped_over() {
+    for iterable in &["1", "None", "print", "true"] {
+        let err = items_of(iterable).unwrap_err().to_string();
+        assert!(err.contains("Iter"), "{}: {}", iterable, err);
+    }
+}
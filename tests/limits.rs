@@ -0,0 +1,85 @@
+use iris::{Interpreter, IrisError, Limits, Value};
+
+const COUNT_DOWN: &str = "
+fn count_down(n)
+  if n == 0 then
+    0
+  else
+    1 + count_down(n - 1)
+  end
+end
+";
+
+// These run on the test harness's threads, which have a small stack, so the
+// interpreter has to make its own room to get to the recursion limit
+
+#[test]
+fn recursion_limit_is_an_error() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval_str(COUNT_DOWN).unwrap();
+    let limit = Limits::default().max_depth;
+    let res = interpreter.eval_str(&format!("count_down({})", limit + 1));
+    assert_eq!(res, Err(IrisError::RecursionLimit(limit)));
+}
+
+#[test]
+fn recursion_up_to_the_limit_works() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval_str(COUNT_DOWN).unwrap();
+    let limit = Limits::default().max_depth;
+    let res = interpreter.eval_str(&format!("count_down({})", limit - 1));
+    assert_eq!(res, Ok(Value::Integer(limit as i32 - 1)));
+}
+
+fn with_limits(max_steps: Option<u64>, max_memory: Option<usize>) -> Interpreter {
+    Interpreter::with_limits(Limits { max_steps, max_memory, ..Limits::default() })
+}
+
+#[test]
+fn step_budget_covers_native_loops() {
+    let mut interpreter = with_limits(Some(1000), None);
+    assert_eq!(interpreter.eval_str("count().collect()"), Err(IrisError::StepLimit(1000)));
+    assert_eq!(interpreter.eval_str("sum(count())"), Err(IrisError::StepLimit(1000)));
+    assert_eq!(interpreter.eval_str("for x in count() do\nend"), Err(IrisError::StepLimit(1000)));
+}
+
+#[test]
+fn memory_budget_is_checked_before_building_big_strings() {
+    // Tests don't count allocations, so this is only the size asked for
+    let mut interpreter = with_limits(None, Some(1_000_000));
+    let too_big = [
+        "repeat(\"abcdefgh\", 100000000)",
+        "join(repeat(\"\").take(200000).collect(), \"0123456789\")",
+        "replace(repeat(\"a\", 1000), \"a\", repeat(\"b\", 10000))",
+        "format(\"{:>100000000}\", 1)",
+    ];
+    for source in too_big.iter() {
+        assert_eq!(interpreter.eval_str(source), Err(IrisError::MemoryLimit(1_000_000)), "{}", source);
+    }
+    assert_eq!(interpreter.eval_str("len(repeat(\"ab\", 1000))"), Ok(Value::Integer(2000)));
+}
+
+#[test]
+fn deeply_nested_values_are_an_error() {
+    let limit = Limits::default().max_nesting;
+    let nest = |wrap: &str| format!(
+        "let x = None\nlet i = 0\nwhile i < 200000 do\n  x = {}\n  i = i + 1\nend\nx", wrap,
+    );
+    for wrap in ["[x]", "Some(x)", "{\"k\": x}", "[1].map(fn(y) x end)"].iter() {
+        let res = Interpreter::new().eval_str(&nest(wrap));
+        assert_eq!(res, Err(IrisError::NestingLimit(limit)), "{}", wrap);
+    }
+    // Built up inside one builtin
+    let res = Interpreter::new().eval_str("fold(range(200000), None, fn(acc, i) Some(acc) end)");
+    assert_eq!(res, Err(IrisError::NestingLimit(limit)));
+}
+
+#[test]
+fn nesting_up_to_the_limit_works() {
+    let limits = Limits { max_nesting: 50, ..Limits::default() };
+    let nest = |n| format!("let x = 1\nfor i in range({}) do\n  x = [x]\nend\nx == x and len(repr(x)) > 0", n);
+    let res = Interpreter::with_limits(limits.clone()).eval_str(&nest(50));
+    assert_eq!(res, Ok(Value::Bool(true)));
+    let res = Interpreter::with_limits(limits).eval_str(&nest(51));
+    assert_eq!(res, Err(IrisError::NestingLimit(50)));
+}
@@ -0,0 +1,111 @@
+use iris::{check_str, Interpreter, IrisError, Value};
+
+fn eval(source: &str) -> Value {
+    Interpreter::new().eval_str(source).unwrap()
+}
+
+#[test]
+fn set_and_dict_operations_are_methods() {
+    let source = "fn add(a: int, b: int) -> int\n  a + b\nend\nlet s = set([1]).add(2).union(set([3]))\n[add(1, 2), s.has(3), {\"a\": 1}.insert(\"b\", 2).keys()]";
+    assert_eq!(check_str(source), Ok(vec![]));
+    let expected = Value::List(vec![
+        Value::Integer(3),
+        Value::Bool(true),
+        Value::List(vec![Value::from("a"), Value::from("b")]),
+    ]);
+    assert_eq!(eval(source), expected);
+}
+
+const COMBINE: &str = "method combine(a, b) for (int, str)\n  \"int, str\"\nend\n\
+    method combine(a, b) for (int, any)\n  \"int, any\"\nend\n\
+    method combine(a, b) for (any, str)\n  \"any, str\"\nend\n";
+
+fn combine(call: &str) -> Result<Value, IrisError> {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval_str(COMBINE)?;
+    interpreter.eval_str(call)
+}
+
+#[test]
+fn multimethods_pick_the_most_specific_match() {
+    assert_eq!(combine("combine(1, \"a\")"), Ok(Value::from("int, str")));
+    assert_eq!(combine("combine(1, 2)"), Ok(Value::from("int, any")));
+    assert_eq!(combine("combine(true, \"a\")"), Ok(Value::from("any, str")));
+    // Calling them like methods dispatches on all the args too
+    assert_eq!(combine("1.combine(\"a\")"), Ok(Value::from("int, str")));
+    assert_eq!(combine("[1.combine(2), \"b\".combine(\"a\")]"), Ok(Value::from(vec!["int, any", "any, str"])));
+}
+
+#[test]
+fn multimethods_that_dont_fit_are_errors() {
+    let err = combine("combine(1.5, 2)").unwrap_err().to_string();
+    assert!(err.contains("no method combine for (float, int)"), "{}", err);
+}
+
+#[test]
+fn ambiguous_multimethod_calls_are_errors() {
+    let source = "method pick(a, b) for (int, any)\n  1\nend\nmethod pick(a, b) for (any, int)\n  2\nend\n";
+    let mut interpreter = Interpreter::new();
+    interpreter.eval_str(source).unwrap();
+    let err = interpreter.eval_str("pick(1, 1)").unwrap_err().to_string();
+    assert!(err.contains("ambiguous call to method pick"), "{}", err);
+    assert!(err.contains("(any, int) and (int, any) both fit (1, 1)"), "{}", err);
+    // Only one of them fits these
+    assert_eq!(interpreter.eval_str("[pick(1, \"a\"), pick(\"a\", 1)]"), Ok(Value::from(vec![1, 2])));
+}
+
+#[test]
+fn multimethods_need_an_arg_for_each_type() {
+    let err = Interpreter::new().eval_str("method m(a) for (int, str)\n  a\nend").unwrap_err().to_string();
+    assert!(err.contains("Method m is for 2 types, but only takes 1 arg"), "{}", err);
+}
+
+#[test]
+fn the_checker_knows_multimethods() {
+    assert_eq!(check_str(&format!("{}combine(1, \"a\")\ncombine(true, \"a\")", COMBINE)), Ok(vec![]));
+    let diagnostics = check_str(&format!("{}combine(true, 2)", COMBINE)).unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].message, "no method combine for (bool, int)");
+}
+
+#[test]
+fn method_calls_try_methods_before_functions() {
+    let source = "fn double(x)\n  x * 2\nend\nmethod double(self) for str\n  self + self\nend\n\
+        [3.double(), \"a\".double(), double(3)]";
+    assert_eq!(eval(source), Value::List(vec![Value::Integer(6), Value::from("aa"), Value::Integer(6)]));
+    // A method can take the name of a builtin function without replacing it
+    let source = "method len(self) for str\n  99\nend\n[\"abc\".len(), len(\"abc\"), [1].len()]";
+    assert_eq!(eval(source), Value::from(vec![99, 3, 1]));
+}
+
+#[test]
+fn capitalized_names_are_nothing_special() {
+    let source = "fn Shout(s)\n  upper(s)\nend\nmethod Whisper(self) for str\n  lower(self)\nend\n\
+        [Shout(\"a\"), \"b\".Shout(), \"C\".Whisper(), Some(1).unwrap()]";
+    assert_eq!(eval(source), Value::List(vec![
+        Value::from("A"), Value::from("B"), Value::from("c"), Value::Integer(1),
+    ]));
+}
+
+#[test]
+fn type_dot_name_calls_the_method_for_that_type() {
+    let source = "method len(self) for str\n  99\nend\nmethod describe(self) for list[int]\n  \"ints\"\nend\n";
+    let mut interpreter = Interpreter::new();
+    interpreter.eval_str(source).unwrap();
+    assert_eq!(interpreter.eval_str("str.len(\"abc\")"), Ok(Value::Integer(99)));
+    assert_eq!(interpreter.eval_str("list.describe([1])"), Ok(Value::from("ints")));
+    // The value still has to be of that type
+    let err = interpreter.eval_str("str.len(1)").unwrap_err().to_string();
+    assert!(err.contains("no method len for str that fits int"), "{}", err);
+    // And there has to be a method, not just a function
+    assert!(interpreter.eval_str("int.len(1)").is_err());
+}
+
+#[test]
+fn the_checker_knows_type_dot_name() {
+    let source = "method describe(self) for list[int]\n  \"ints\"\nend\nlist.describe([1])";
+    assert_eq!(check_str(source), Ok(vec![]));
+    let diagnostics = check_str("method describe(self) for list[int]\n  \"ints\"\nend\nstr.describe(\"a\")").unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].message, "no method describe for str");
+}
@@ -0,0 +1,94 @@
+use std::cell::Cell;
+
+use iris::{Interpreter, TypeDefinition, Value};
+
+fn counter_interpreter() -> Interpreter {
+    let mut interpreter = Interpreter::new();
+    let counter_type = TypeDefinition::Native("Counter".to_string());
+    interpreter.register_fn("counter", |_| Ok(Value::native("Counter", Cell::new(0))));
+    interpreter.register_method("bump", counter_type.clone(), |args| {
+        let count = args[0].downcast_ref::<Cell<i32>>().unwrap();
+        count.set(count.get() + 1);
+        Ok(Value::Integer(count.get()))
+    });
+    interpreter.register_method("get", counter_type, |args| {
+        Ok(Value::Integer(args[0].downcast_ref::<Cell<i32>>().unwrap().get()))
+    });
+    interpreter
+}
+
+#[test]
+fn host_methods_can_be_called_on_native_objects() {
+    let mut interpreter = counter_interpreter();
+    assert_eq!(interpreter.eval_str("let c = counter()\nc.bump()\nc.bump()\nc.get()"), Ok(Value::Integer(2)));
+}
+
+#[test]
+fn scripts_can_define_methods_for_native_types() {
+    let mut interpreter = counter_interpreter();
+    let source = "method bump_twice(self) for Counter\n  self.bump()\n  self.bump()\nend\ncounter().bump_twice()";
+    assert_eq!(interpreter.eval_str(source), Ok(Value::Integer(2)));
+}
+
+#[test]
+fn native_objects_are_shared_not_copied() {
+    let mut interpreter = counter_interpreter();
+    let source = "let c = counter()\nlet cs = [c, c]\nfor x in cs do\n  x.bump()\nend\nc.get()";
+    assert_eq!(interpreter.eval_str(source), Ok(Value::Integer(2)));
+}
+
+#[test]
+fn native_objects_are_only_equal_to_themselves() {
+    let mut interpreter = counter_interpreter();
+    assert_eq!(interpreter.eval_str("let a = counter()\n[a == a, a == counter()]"), Ok(Value::from(vec![true, false])));
+    assert_eq!(interpreter.eval_str("let b = counter()\nlen(set([b, b, counter()]))"), Ok(Value::Integer(2)));
+    assert_eq!(interpreter.eval_str("let d = {counter(): 1}\nlen(d)"), Ok(Value::Integer(1)));
+}
+
+#[test]
+fn native_objects_go_through_list_functions() {
+    let mut interpreter = counter_interpreter();
+    let source = "let cs = [counter(), counter()]\ncs.map(fn(c) c.bump() end).sum()";
+    assert_eq!(interpreter.eval_str(source), Ok(Value::Integer(2)));
+    let source = "[counter()].filter(fn(c) c.get() > 0 end).map(fn(c) c.bump() end)";
+    assert_eq!(interpreter.eval_str(source), Ok(Value::List(vec![])));
+}
+
+#[test]
+fn scripts_cant_look_inside_native_objects() {
+    let mut interpreter = counter_interpreter();
+    assert_eq!(interpreter.eval_str("repr(counter())"), Ok(Value::from("<Counter>")));
+    assert!(interpreter.eval_str("counter().missing()").is_err());
+    assert!(interpreter.eval_str("len(counter())").is_err());
+    assert!(interpreter.eval_str("counter() + 1").is_err());
+    assert!(interpreter.eval_str("for x in counter() do\n  x\nend").is_err());
+}
+
+#[test]
+fn hosts_get_their_objects_back() {
+    let mut interpreter = counter_interpreter();
+    let val = interpreter.eval_str("let c = counter()\nc.bump()\nc").unwrap();
+    assert_eq!(val.downcast_ref::<Cell<i32>>().map(Cell::get), Some(1));
+    assert_eq!(val.downcast_ref::<String>(), None);
+    assert_eq!(Value::Integer(1).downcast_ref::<i32>(), None);
+}
+
+#[test]
+fn methods_only_apply_to_their_own_type() {
+    let mut interpreter = counter_interpreter();
+    interpreter.register_fn("other", |_| Ok(Value::native("Other", Cell::new(0))));
+    assert!(interpreter.eval_str("other().bump()").is_err());
+}
+
+#[test]
+fn iterators_are_native_objects() {
+    let mut interpreter = Interpreter::new();
+    let source = "let it = iter([1].filter(fn(x) false end))\n[repr(it), next(it), collect(it)]";
+    assert_eq!(
+        interpreter.eval_str(source),
+        Ok(Value::List(vec![Value::from("<Iterator>"), Value::None, Value::List(vec![])])),
+    );
+    assert!(interpreter.eval_str("next([1])").is_err());
+    assert!(interpreter.eval_str("collect(1)").is_err());
+    assert!(interpreter.eval_str("iter()").is_err());
+}
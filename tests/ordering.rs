@@ -0,0 +1,57 @@
+use iris::{check_str, lint_str, Diagnostic, Interpreter};
+
+fn messages(diagnostics: Vec<Diagnostic>) -> Vec<String> {
+    diagnostics.into_iter().map(|d| format!("{}:{}: {}", d.line, d.col, d.message)).collect()
+}
+
+const CALLED_TOO_EARLY: &str = "print(later(1))\nfn later(x)\n  x\nend";
+
+#[test]
+fn functions_are_not_hoisted_when_running() {
+    assert!(Interpreter::new().eval_str(CALLED_TOO_EARLY).is_err());
+}
+
+#[test]
+fn check_reports_calls_before_the_definition() {
+    assert_eq!(
+        messages(check_str(CALLED_TOO_EARLY).unwrap()),
+        vec!["1:1: later() is used before it's defined"],
+    );
+}
+
+#[test]
+fn check_follows_calls_through_other_functions() {
+    let source = "fn first()\n  later(1)\nend\nfirst()\nfn later(x)\n  x\nend";
+    assert_eq!(
+        messages(check_str(source).unwrap()),
+        vec!["4:1: later() is used before it's defined, by calling first()"],
+    );
+}
+
+#[test]
+fn check_allows_calls_that_run_after_the_definition() {
+    let source = "fn a(n)\n  if n > 0 then b(n - 1) else 0 end\nend\n\
+                  let f = fn () later(1) end\nfn b(n)\n  a(n)\nend\n\
+                  fn later(x)\n  x\nend\nprint(a(3), later(2), f())";
+    assert_eq!(check_str(source), Ok(vec![]));
+    assert!(Interpreter::new().eval_str(source).is_ok());
+}
+
+#[test]
+fn lint_warns_about_calls_before_the_definition() {
+    assert_eq!(
+        messages(lint_str(CALLED_TOO_EARLY).unwrap()),
+        vec!["1:1: call to function later before it's defined"],
+    );
+    let source = "print(map([1], later))\nfn later(x)\n  x\nend";
+    assert_eq!(
+        messages(lint_str(source).unwrap()),
+        vec!["1:1: function later is used before it's defined"],
+    );
+}
+
+#[test]
+fn lint_allows_calls_from_functions_defined_first() {
+    let source = "fn first()\n  later(1)\nend\nfn later(x)\n  x\nend\nprint(first())";
+    assert_eq!(lint_str(source), Ok(vec![]));
+}
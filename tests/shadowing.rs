@@ -0,0 +1,39 @@
+use iris::{check_str, Interpreter, Value};
+
+fn eval(source: &str) -> Value {
+    Interpreter::new().eval_str(source).unwrap()
+}
+
+#[test]
+fn args_shadow_builtins() {
+    assert_eq!(eval("fn call_it(next)\n  next(1)\nend\ncall_it(fn (x) x + 1 end)"), Value::Integer(2));
+}
+
+#[test]
+fn variables_shadow_builtins() {
+    assert_eq!(eval("let max = fn (a, b) 99 end\nmax(1, 2)"), Value::Integer(99));
+}
+
+#[test]
+fn functions_shadow_builtins() {
+    assert_eq!(eval("fn count(xs)\n  42\nend\ncount([1])"), Value::Integer(42));
+}
+
+#[test]
+fn literals_still_work_when_list_is_shadowed() {
+    assert_eq!(eval("let list = 3\nlen([1, 2])"), Value::Integer(2));
+}
+
+#[test]
+fn host_functions_are_shadowed_too() {
+    let mut interpreter = Interpreter::new();
+    interpreter.register_fn("answer", |_| Ok(Value::Integer(42)));
+    assert_eq!(interpreter.eval_str("answer()"), Ok(Value::Integer(42)));
+    assert_eq!(interpreter.eval_str("fn answer()\n  7\nend\nanswer()"), Ok(Value::Integer(7)));
+}
+
+#[test]
+fn checker_agrees_about_shadowing() {
+    let source = "fn count(xs: list[int]) -> int\n  42\nend\nlet n = count([1]) + 1\nlet max = fn (a, b) a end\nmax(\"a\", 2)";
+    assert_eq!(check_str(source), Ok(vec![]));
+}
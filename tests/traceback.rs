@@ -0,0 +1,39 @@
+use iris::Interpreter;
+
+fn error(source: &str) -> String {
+    Interpreter::new().eval_str(source).unwrap_err().to_string()
+}
+
+#[test]
+fn anonymous_functions_are_named_by_where_they_start() {
+    let source = "let fs = [fn(a) a end, fn(b)\n    b\nend, fn(c)\n    c[5]\nend]\nlet f = fs[2]\nf([1])";
+    let message = error(source);
+    assert!(message.contains("line 4, in <anonymous fn defined at line 3>"), "{}", message);
+}
+
+#[test]
+fn named_functions_are_named() {
+    let message = error("fn first(xs)\n  xs[5]\nend\nfirst([1])");
+    assert!(message.contains("line 4, in <top level>\n  line 2, in fn first"), "{}", message);
+}
+
+#[test]
+fn errors_after_a_call_returns_are_where_the_caller_is() {
+    let message = error("fn f()\n  1\nend\nfn g()\n  let y = 2\n  f() + \"a\"\nend\ng()");
+    assert!(message.contains("line 8, in <top level>\n  line 6, in fn g"), "{}", message);
+}
+
+#[test]
+fn errors_after_a_tail_call_or_loop_are_where_the_caller_is() {
+    let source = "fn h()\n  2\nend\nfn f()\n  for x in [1] do\n    x\n  end\n  h()\nend\n\
+                  fn g()\n  f() + map([1], fn(x) x end)[5]\nend\ng()";
+    let message = error(source);
+    assert!(message.contains("line 13, in <top level>\n  line 11, in fn g"), "{}", message);
+}
+
+#[test]
+fn errors_after_a_caught_one_are_where_they_happen() {
+    let source = "fn bad()\n  [1][5]\nend\nfn g()\n  assert_raises(bad) + 1\nend\ng()";
+    let message = error(source);
+    assert!(message.contains("line 7, in <top level>\n  line 5, in fn g"), "{}", message);
+}